@@ -0,0 +1,74 @@
+#ifndef ONEBRC_H
+#define ONEBRC_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Aggregated stations of a file, in no particular order
+ */
+typedef struct brc_result brc_result;
+
+/**
+ * A single station, values are in degrees Celsius
+ */
+typedef struct {
+  /**
+   * UTF-8 name, NUL terminated and valid until the result is freed
+   */
+  const char *name;
+  /**
+   * Length of the name in bytes, without the terminator
+   */
+  size_t name_len;
+  double min;
+  double mean;
+  double max;
+  uint32_t count;
+} brc_station;
+
+/**
+ * Aggregate the measurements file at `path` with the default options.
+ *
+ * Returns NULL if the file cannot be read. The result must be released
+ * with `brc_result_free`.
+ *
+ * # Safety
+ *
+ * `path` must be a valid NUL terminated string.
+ */
+brc_result *brc_process_file(const char *path);
+
+/**
+ * Number of stations in the result.
+ *
+ * # Safety
+ *
+ * `result` must come from `brc_process_file` and not be freed yet.
+ */
+size_t brc_result_len(const brc_result *result);
+
+/**
+ * The station at `index`, which must be less than `brc_result_len`.
+ *
+ * # Safety
+ *
+ * `result` must come from `brc_process_file` and not be freed yet.
+ */
+brc_station brc_result_station(const brc_result *result, size_t index);
+
+/**
+ * Release a result, NULL is ignored.
+ *
+ * # Safety
+ *
+ * `result` must come from `brc_process_file` and not be freed yet.
+ */
+void brc_result_free(brc_result *result);
+
+#endif  /* ONEBRC_H */
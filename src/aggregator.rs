@@ -0,0 +1,246 @@
+//! Per-station statistics
+//!
+//! The engine parses each line into a name and a fixed point value and hands
+//! the value to an `Aggregator` for its station. Chunks are aggregated on
+//! their own threads, so aggregators of the same station get merged.
+//!
+//! ```no_run
+//! use onebrc::{aggregator::Aggregator, options::Options, Processor};
+//!
+//! // Counts values per whole degree
+//! #[derive(Clone, Default)]
+//! struct WholeDegrees(std::collections::BTreeMap<i32, u32>);
+//!
+//! impl Aggregator for WholeDegrees {
+//!     type Output = Vec<(f64, u32)>;
+//!
+//!     fn new() -> Self {
+//!         Self::default()
+//!     }
+//!
+//!     fn update(&mut self, value: i32, options: &Options) {
+//!         *self.0.entry(value / options.scale() as i32).or_default() += 1;
+//!     }
+//!
+//!     fn merge(&mut self, other: &Self, _options: &Options) {
+//!         for (&bucket, &count) in &other.0 {
+//!             *self.0.entry(bucket).or_default() += count;
+//!         }
+//!     }
+//!
+//!     fn finalize(&self, _options: &Options) -> Self::Output {
+//!         self.0.iter().map(|(&bucket, &count)| (bucket as f64, count)).collect()
+//!     }
+//! }
+//!
+//! let stations = Processor::default().process_with::<WholeDegrees>("measurements.txt")?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::{accumulate, alloc_check, name::Name, options::Options, tally, Sum};
+
+pub trait Aggregator: Clone + Send {
+    // What `finalize` turns the statistics into
+    type Output;
+
+    // Statistics without any values yet
+    fn new() -> Self;
+
+    // Add a value in fixed point, see `Options::scale`
+    fn update(&mut self, value: i32, options: &Options);
+
+    // Add all values of `other`, for the same station from another chunk
+    fn merge(&mut self, other: &Self, options: &Options);
+
+    fn finalize(&self, options: &Options) -> Self::Output;
+}
+
+// An aggregated station, the name borrows from the input unless it had to
+// be transcoded or outlive it
+#[derive(Debug, Clone)]
+pub struct Station<'a, A> {
+    pub name: Name<'a>,
+    pub stats: A,
+}
+
+impl<A> Station<'_, A> {
+    pub fn into_owned(self) -> Station<'static, A> {
+        Station {
+            name: self.name.into_owned(),
+            stats: self.stats,
+        }
+    }
+}
+
+// The statistics of the challenge, what `aggregate` uses
+#[derive(Debug, Clone, Copy)]
+pub struct MinMeanMax {
+    pub sum: Sum,
+    pub count: u32,
+    pub min: i32,
+    pub max: i32,
+}
+
+// Final values in the input unit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+    pub count: u32,
+}
+
+impl Aggregator for MinMeanMax {
+    type Output = Summary;
+
+    fn new() -> Self {
+        Self {
+            sum: 0,
+            count: 0,
+            min: i32::MAX,
+            max: i32::MIN,
+        }
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn update(&mut self, value: i32, options: &Options) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum = accumulate(self.sum, value as Sum, options.strict);
+        self.count = tally(self.count, 1, options.strict);
+    }
+
+    fn merge(&mut self, other: &Self, options: &Options) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum = accumulate(self.sum, other.sum, options.strict);
+        self.count = tally(self.count, other.count, options.strict);
+    }
+
+    fn finalize(&self, options: &Options) -> Summary {
+        let scale = options.scale();
+        Summary {
+            min: self.min as f64 / scale,
+            mean: self.sum as f64 / self.count as f64 / scale,
+            max: self.max as f64 / scale,
+            count: self.count,
+        }
+    }
+}
+
+// The statistics of the challenge and a sum of squares for the standard
+// deviation, for `--flag-outliers`. Squares of i32 values are below 2^62,
+// so even `u32::MAX` of them fit an i128 without checks.
+#[derive(Debug, Clone, Copy)]
+pub struct Moments {
+    pub stats: MinMeanMax,
+    pub squares: i128,
+}
+
+// A summary and the standard deviation of the values, in the input unit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spread {
+    pub summary: Summary,
+    pub deviation: f64,
+}
+
+impl Spread {
+    // Whether the minimum or the maximum is more than `sigmas` standard
+    // deviations from the mean
+    pub fn is_outlier(&self, sigmas: f64) -> bool {
+        let Summary { min, mean, max, .. } = self.summary;
+        let limit = sigmas * self.deviation;
+        mean - min > limit || max - mean > limit
+    }
+}
+
+impl Aggregator for Moments {
+    type Output = Spread;
+
+    fn new() -> Self {
+        Self {
+            stats: MinMeanMax::new(),
+            squares: 0,
+        }
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn update(&mut self, value: i32, options: &Options) {
+        self.stats.update(value, options);
+        self.squares += value as i128 * value as i128;
+    }
+
+    fn merge(&mut self, other: &Self, options: &Options) {
+        self.stats.merge(&other.stats, options);
+        self.squares += other.squares;
+    }
+
+    fn finalize(&self, options: &Options) -> Spread {
+        let summary = self.stats.finalize(options);
+        let scale = options.scale();
+        let squares = self.squares as f64 / self.stats.count as f64 / (scale * scale);
+        Spread {
+            summary,
+            // Rounding can leave a tiny negative variance for equal values
+            deviation: (squares - summary.mean * summary.mean).max(0.0).sqrt(),
+        }
+    }
+}
+
+// Values counted in `Options::bins` bins of equal width over the range any
+// value is in, for `--histogram`
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    counts: Vec<u32>,
+}
+
+impl Histogram {
+    // Degrees in the input unit, values are below 100 either way
+    pub const RANGE: (f64, f64) = (-100.0, 100.0);
+
+    // Lower edge of `bin`, or the upper edge of the last one for `bins`
+    pub fn edge(bin: usize, options: &Options) -> f64 {
+        let (low, high) = Self::RANGE;
+        low + (high - low) * bin as f64 / options.bins as f64
+    }
+}
+
+impl Aggregator for Histogram {
+    // Counts by bin
+    type Output = Vec<u32>;
+
+    fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn update(&mut self, value: i32, options: &Options) {
+        // Sized here, as `new` does not know the bins, once per station like
+        // an interned name
+        if self.counts.is_empty() {
+            self.counts = alloc_check::allow(|| vec![0; options.bins]);
+        }
+        let (low, high) = Self::RANGE;
+        let scale = options.scale();
+        let from = (low * scale) as i64;
+        let width = ((high - low) * scale) as i64;
+        let bin = (value as i64 - from) * options.bins as i64 / width;
+        self.counts[bin.clamp(0, options.bins as i64 - 1) as usize] += 1;
+    }
+
+    fn merge(&mut self, other: &Self, _options: &Options) {
+        if self.counts.is_empty() {
+            self.counts.clone_from(&other.counts);
+            return;
+        }
+        for (count, &other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+    }
+
+    fn finalize(&self, options: &Options) -> Vec<u32> {
+        let mut counts = self.counts.clone();
+        counts.resize(options.bins, 0);
+        counts
+    }
+}
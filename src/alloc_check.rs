@@ -0,0 +1,98 @@
+//! Allocation checks of the hot loop, with the `alloc-check` feature
+//!
+//! Parsing a line and updating its station never allocate, names borrow
+//! from the input. With the feature a counting global allocator notes every
+//! allocation made inside `forbid`, which then panics, so a test that
+//! aggregates anything catches a regression. Allocating in the allocator is
+//! not allowed and neither is unwinding from it, so it only counts. Without
+//! the feature both functions just call their closure.
+
+#[cfg(feature = "alloc-check")]
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+#[cfg(feature = "alloc-check")]
+thread_local! {
+    static FORBIDDEN: Cell<bool> = const { Cell::new(false) };
+    static FORBIDDEN_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+#[cfg(feature = "alloc-check")]
+struct Checking;
+
+#[cfg(feature = "alloc-check")]
+impl Checking {
+    fn count() {
+        // Threads that are shutting down have no locals left to check
+        let _ = FORBIDDEN.try_with(|forbidden| {
+            if forbidden.get() {
+                FORBIDDEN_ALLOCATIONS.with(|count| count.set(count.get() + 1));
+            }
+        });
+    }
+}
+
+#[cfg(feature = "alloc-check")]
+unsafe impl GlobalAlloc for Checking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::count();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::count();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+        Self::count();
+        System.realloc(ptr, layout, size)
+    }
+}
+
+#[cfg(feature = "alloc-check")]
+#[global_allocator]
+static ALLOCATOR: Checking = Checking;
+
+// Run `run`, which must not allocate, panics naming `what` if it did
+#[cfg(feature = "alloc-check")]
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+pub(crate) fn forbid<R>(what: &str, run: impl FnOnce() -> R) -> R {
+    let outer = FORBIDDEN.replace(true);
+    let result = run();
+    FORBIDDEN.set(outer);
+    if !outer {
+        let allocations = FORBIDDEN_ALLOCATIONS.take();
+        assert!(allocations == 0, "{what} allocated {allocations} times");
+    }
+    result
+}
+
+// Run `run`, which may allocate even inside `forbid`, e.g. to transcode a
+// name
+#[cfg(feature = "alloc-check")]
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+pub(crate) fn allow<R>(run: impl FnOnce() -> R) -> R {
+    let outer = FORBIDDEN.replace(false);
+    let result = run();
+    FORBIDDEN.set(outer);
+    result
+}
+
+#[cfg(not(feature = "alloc-check"))]
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+pub(crate) fn forbid<R>(_what: &str, run: impl FnOnce() -> R) -> R {
+    run()
+}
+
+#[cfg(not(feature = "alloc-check"))]
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+pub(crate) fn allow<R>(run: impl FnOnce() -> R) -> R {
+    run()
+}
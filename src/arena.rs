@@ -0,0 +1,59 @@
+//! Bump arena for station names
+//!
+//! Names that have to outlive the input they were read from are copied into
+//! an arena instead of being allocated one by one. The bytes come from large
+//! blocks that never move and are only freed with the arena, so a name is a
+//! plain slice into a block. An arena belongs to one thread, it is not
+//! `Sync`.
+
+use std::{cell::RefCell, slice};
+
+use crate::{alloc_check, memory};
+
+// Bytes of a block, longer names get a block of their own
+const BLOCK: usize = 64 * 1024;
+
+#[derive(Default)]
+pub struct Arena {
+    blocks: RefCell<Vec<Vec<u8>>>,
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Copy `bytes` into the arena
+    pub fn alloc(&self, bytes: &[u8]) -> &[u8] {
+        let mut blocks = self.blocks.borrow_mut();
+        let fits = blocks
+            .last()
+            .is_some_and(|block| block.capacity() - block.len() >= bytes.len());
+        if !fits {
+            // A block for many names, not one per line
+            alloc_check::allow(|| {
+                let block = Vec::with_capacity(bytes.len().max(BLOCK));
+                memory::allocate(block.capacity());
+                blocks.push(block);
+            });
+        }
+        let block = blocks.last_mut().unwrap();
+        let start = block.len();
+        block.extend_from_slice(bytes);
+        // SAFETY: blocks never grow past their capacity, so their bytes stay
+        // where they are, bytes handed out are never written again and the
+        // blocks are only freed with the arena, which the slice borrows
+        unsafe { slice::from_raw_parts(block.as_ptr().add(start), bytes.len()) }
+    }
+
+    // Bytes reserved by all blocks
+    pub fn allocated(&self) -> usize {
+        self.blocks.borrow().iter().map(Vec::capacity).sum()
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        memory::free(self.allocated());
+    }
+}
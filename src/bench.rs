@@ -0,0 +1,272 @@
+//! Repeated runs for benchmarking
+//!
+//! Every run loads and aggregates the file from scratch like a normal run,
+//! only nothing is written. Warmup runs fill the page cache and are not
+//! counted, with `--cold` every run starts without it instead. With
+//! `--matrix` that is done for every combination of the strategies named,
+//! the others stay as given, and the medians are printed side by side.
+
+use std::{fs::File, io, iter, thread, time::Instant};
+
+use onebrc::{
+    options::{Axis, Options},
+    scan::{self, Scanner},
+    table::Table,
+    Io,
+};
+
+use crate::{aggregate_with_fallback, generate, load_input, page_cache};
+
+// Seconds of the timed runs, fastest first
+struct Timings {
+    seconds: Vec<f64>,
+    bytes: usize,
+    lines: u64,
+}
+
+impl Timings {
+    fn median(&self) -> f64 {
+        match self.seconds.len() {
+            n if n % 2 == 0 => (self.seconds[n / 2 - 1] + self.seconds[n / 2]) / 2.0,
+            n => self.seconds[n / 2],
+        }
+    }
+
+    fn stddev(&self) -> f64 {
+        let runs = self.seconds.len() as f64;
+        let mean = self.seconds.iter().sum::<f64>() / runs;
+        let variance = self.seconds.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / runs;
+        variance.sqrt()
+    }
+}
+
+pub fn run(options: &Options) -> io::Result<()> {
+    if !options.matrix.is_empty() {
+        return run_matrix(options);
+    }
+    let timings = measure(options)?;
+    let median = timings.median();
+    // Compared across tables, see `--table`
+    let scanner = options.scanner.unwrap_or_else(scan::detect);
+    println!(
+        "scanner: {}, table: {}",
+        scanner.name(),
+        options.table.name()
+    );
+    println!(
+        "runs: {} ({} warmup)",
+        timings.seconds.len(),
+        options.warmup
+    );
+    println!("min: {:.3}s", timings.seconds[0]);
+    println!("median: {median:.3}s");
+    println!("stddev: {:.3}s", timings.stddev());
+    println!(
+        "throughput: {:.2} GB/s, {:.0} rows/s at the median",
+        timings.bytes as f64 / median / 1e9,
+        timings.lines as f64 / median
+    );
+    Ok(())
+}
+
+// Time the stages of aggregating rows generated in memory, see `parts`
+pub fn parts(options: &Options) {
+    let mut data = Vec::with_capacity(options.rows as usize * 16);
+    generate::write(&mut data, options).expect("writing to memory does not fail");
+    let scanner = options.scanner.unwrap_or_else(scan::detect);
+    println!(
+        "rows: {} of {} stations, scanner: {}, runs: {} of each stage",
+        options.rows,
+        options.stations,
+        scanner.name(),
+        options.runs
+    );
+    let stages = onebrc::parts::time(&data, options);
+    let lines = stages
+        .iter()
+        .map(|stage| {
+            [
+                stage.name.to_string(),
+                format!("{:.3}ms", stage.seconds * 1e3),
+                format!("{:.2}", stage.seconds * 1e9 / stage.items.max(1) as f64),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["stage", "median", "ns/item"].map(String::from);
+    let mut widths = header.clone().map(|title| title.len());
+    for line in &lines {
+        for (width, field) in widths.iter_mut().zip(line) {
+            *width = (*width).max(field.len());
+        }
+    }
+    for [stage, median, per_item] in iter::once(header).chain(lines) {
+        let [stage_width, median_width, per_item_width] = widths;
+        println!("{stage:<stage_width$}  {median:>median_width$}  {per_item:>per_item_width$}");
+    }
+}
+
+// Time the runs of `options`
+fn measure(options: &Options) -> io::Result<Timings> {
+    let mut seconds = Vec::with_capacity(options.runs);
+    let (mut bytes, mut lines) = (0, 0);
+    for run in 0..options.warmup + options.runs {
+        let file = File::open(&options.file_name)?;
+        if options.cold {
+            page_cache::evict(&file)?;
+        }
+        let started = Instant::now();
+        let input = load_input(&file, options)?;
+        let (result, _) = aggregate_with_fallback(&input, options)?;
+        let elapsed = started.elapsed().as_secs_f64();
+        if run >= options.warmup {
+            seconds.push(elapsed);
+        }
+        bytes = input.len();
+        lines = result.key_set().map(|(_, stats)| stats.count as u64).sum();
+    }
+    seconds.sort_unstable_by(f64::total_cmp);
+    Ok(Timings {
+        seconds,
+        bytes,
+        lines,
+    })
+}
+
+// Bench every combination and print a line for each, with its median
+// relative to the fastest one
+fn run_matrix(options: &Options) -> io::Result<()> {
+    let mut combinations = vec![options.clone()];
+    for &axis in &options.matrix {
+        combinations = combinations
+            .iter()
+            .flat_map(|combination| vary(combination, axis))
+            .collect();
+    }
+    let mut rows = Vec::with_capacity(combinations.len());
+    for combination in &combinations {
+        let timings = measure(combination)?;
+        let choices = options.matrix.iter().map(|&axis| choice(combination, axis));
+        rows.push((choices.collect::<Vec<_>>(), timings));
+    }
+    let fastest = rows
+        .iter()
+        .map(|(_, timings)| timings.median())
+        .fold(f64::INFINITY, f64::min);
+
+    let header = options
+        .matrix
+        .iter()
+        .map(|axis| axis.name().to_string())
+        .chain(["median", "stddev", "GB/s", "relative"].map(String::from))
+        .collect::<Vec<_>>();
+    let lines = rows
+        .into_iter()
+        .map(|(choices, timings)| {
+            let median = timings.median();
+            let measured = [
+                format!("{median:.3}s"),
+                format!("{:.3}s", timings.stddev()),
+                format!("{:.2}", timings.bytes as f64 / median / 1e9),
+                format!("{:.2}x", median / fastest),
+            ];
+            choices.into_iter().chain(measured).collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    // Strategies are left aligned, measurements right aligned
+    let mut widths = header.iter().map(String::len).collect::<Vec<_>>();
+    for line in &lines {
+        for (width, field) in widths.iter_mut().zip(line) {
+            *width = (*width).max(field.len());
+        }
+    }
+    let axes = options.matrix.len();
+    println!(
+        "runs: {} ({} warmup) of each of {} combinations",
+        options.runs,
+        options.warmup,
+        lines.len()
+    );
+    for line in iter::once(&header).chain(&lines) {
+        let fields = line
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (field, &width))| match i < axes {
+                true => format!("{field:<width$}"),
+                false => format!("{field:>width$}"),
+            });
+        println!("{}", fields.collect::<Vec<_>>().join("  "));
+    }
+    Ok(())
+}
+
+// `options` with each choice of `axis`
+fn vary(options: &Options, axis: Axis) -> Vec<Options> {
+    match axis {
+        Axis::Table => {
+            let tables = [Table::Robin, Table::Std, Table::Sharded, Table::Hashbrown];
+            tables
+                .into_iter()
+                .filter(|&table| table != Table::Hashbrown || cfg!(feature = "hashbrown"))
+                .map(|table| Options {
+                    table,
+                    ..options.clone()
+                })
+                .collect()
+        }
+        Axis::Scanner => {
+            let scanners = [
+                Scanner::Scalar,
+                Scanner::Neon,
+                Scanner::Avx2,
+                Scanner::Avx512,
+            ];
+            scanners
+                .into_iter()
+                .filter(|scanner| scanner.is_supported())
+                .map(|scanner| Options {
+                    scanner: Some(scanner),
+                    ..options.clone()
+                })
+                .collect()
+        }
+        Axis::Io => [Io::Mmap, Io::Read]
+            .into_iter()
+            .map(|io| Options {
+                io,
+                ..options.clone()
+            })
+            .collect(),
+        Axis::Threads => {
+            let cores = thread::available_parallelism().map_or(1, usize::from);
+            let mut threads = iter::successors(Some(1), |threads| Some(threads * 2))
+                .take_while(|&threads| threads < cores)
+                .collect::<Vec<_>>();
+            threads.push(cores);
+            threads
+                .into_iter()
+                .map(|threads| Options {
+                    threads: Some(threads),
+                    ..options.clone()
+                })
+                .collect()
+        }
+    }
+}
+
+// The choice of `axis` in `options`
+fn choice(options: &Options, axis: Axis) -> String {
+    match axis {
+        Axis::Table => options.table.name().to_string(),
+        Axis::Scanner => options
+            .scanner
+            .unwrap_or_else(scan::detect)
+            .name()
+            .to_string(),
+        Axis::Io => options.io.name().to_string(),
+        Axis::Threads => options
+            .threads
+            .map_or_else(|| "all".to_string(), |threads| threads.to_string()),
+    }
+}
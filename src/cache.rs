@@ -0,0 +1,85 @@
+//! Result cache
+//!
+//! Aggregation states are saved under a key of the file identity and the
+//! options that affect parsing, so repeated runs on an unchanged file skip
+//! the aggregation. The identity is the canonical path, size, modification
+//! time and a hash of the first and last block, which catches files that
+//! were rewritten without changing their size or time.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use onebrc::{hash_table::HashTable, options::Options, state, StationStats};
+
+use crate::output;
+
+const BLOCK: usize = 64 * 1024;
+
+// Location of the cached state for this file and options
+pub fn path(dir: &Path, file: &File, data: &[u8], options: &Options) -> io::Result<PathBuf> {
+    let metadata = file.metadata()?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let mut hasher = DefaultHasher::new();
+    fs::canonicalize(&options.file_name)?.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    modified.hash(&mut hasher);
+    data[..BLOCK.min(data.len())].hash(&mut hasher);
+    data[data.len().saturating_sub(BLOCK)..].hash(&mut hasher);
+    // Everything that changes how lines are aggregated
+    options.encoding.hash(&mut hasher);
+    options.strict_utf8.hash(&mut hasher);
+    options.strict.hash(&mut hasher);
+    options.lenient.hash(&mut hasher);
+    options.dedup.hash(&mut hasher);
+    options.flexible_values.hash(&mut hasher);
+    options.delimiter.hash(&mut hasher);
+    options.columns.hash(&mut hasher);
+    options.group_by.hash(&mut hasher);
+    options.quotes.hash(&mut hasher);
+    options.header.hash(&mut hasher);
+    Ok(dir.join(format!("{:016x}.state", hasher.finish())))
+}
+
+pub fn load(path: &Path) -> io::Result<HashTable<StationStats<'static>>> {
+    let (_, stations) = state::read(path)?;
+    let mut result = HashTable::new();
+    for stats in stations {
+        let mut duplicate = false;
+        result.insert_or_update(
+            stats.key(),
+            |stored: &StationStats| stored.name == stats.name,
+            |_| duplicate = true,
+            || stats.clone(),
+        );
+        // A stored state has every station once, anything else is corrupt
+        if duplicate {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the station `{}` is cached twice",
+                    String::from_utf8_lossy(&stats.name)
+                ),
+            ));
+        }
+    }
+    Ok(result)
+}
+
+pub fn store(path: &Path, result: &HashTable<StationStats>, options: &Options) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let stations = result.key_set().map(|(_, value)| value).collect::<Vec<_>>();
+    let scale = options.scale() as u8;
+    output::write_atomically(path, |out| state::write(&stations, scale, out))
+}
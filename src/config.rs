@@ -0,0 +1,98 @@
+//! Configuration files
+//!
+//! A TOML file sets options like the command line does, its keys are long
+//! flags without the dashes. Tables named after a subcommand only apply to
+//! that one, `run` without a subcommand:
+//!
+//! ```toml
+//! threads = 8
+//! io = "mmap"
+//! format = "table"
+//!
+//! [bench]
+//! runs = 20
+//!
+//! [generate]
+//! rows = 100000000
+//! seed = 7
+//! ```
+//!
+//! `ONEBRC_*` environment variables come after the file and the command line
+//! after both, so its options win. A flag set to `false` is turned off like
+//! with `--no-<flag>`, which the command line can turn on again.
+
+use std::{env, fs, path::Path};
+
+use toml::{Table, Value};
+
+// Used without `--config` if it exists in the working directory
+pub const DEFAULT_PATH: &str = "1brc.toml";
+
+// Environment variables and the flags they set
+const VARIABLES: [(&str, &str); 4] = [
+    ("ONEBRC_THREADS", "--threads"),
+    ("ONEBRC_IO", "--io"),
+    ("ONEBRC_FORMAT", "--format"),
+    ("ONEBRC_MADVISE", "--madvise"),
+];
+
+// The options set in the environment as command line arguments
+pub fn env_args() -> Vec<String> {
+    VARIABLES
+        .iter()
+        .filter_map(|(variable, flag)| {
+            let value = env::var(variable).ok()?;
+            Some([flag.to_string(), value])
+        })
+        .flatten()
+        .collect()
+}
+
+// The options of the file as command line arguments
+pub fn args(path: &Path, command: &str) -> Result<Vec<String>, String> {
+    let config = fs::read_to_string(path)
+        .map_err(|error| format!("failed to read the config `{}`: {error}", path.display()))?;
+    let table = config
+        .parse::<Table>()
+        .map_err(|error| format!("invalid config `{}`: {error}", path.display()))?;
+    let mut args = Vec::new();
+    push_args(&table, &mut args)?;
+    if let Some(Value::Table(table)) = table.get(command) {
+        push_args(table, &mut args)?;
+    }
+    Ok(args)
+}
+
+fn push_args(table: &Table, args: &mut Vec<String>) -> Result<(), String> {
+    for (key, value) in table {
+        let flag = format!("--{key}");
+        match value {
+            Value::Boolean(true) => args.push(flag),
+            // Off even if set before, see `--no-<flag>`
+            Value::Boolean(false) => args.push(format!("--no-{key}")),
+            // Subcommands, see `args`
+            Value::Table(_) => {}
+            // Lists like `--workers`
+            Value::Array(values) => {
+                let values = values.iter().map(scalar).collect::<Option<Vec<_>>>();
+                let values = values.ok_or_else(|| format!("`{key}` must be a list of values"))?;
+                args.extend([flag, values.join(",")]);
+            }
+            value => {
+                let value = scalar(value).ok_or_else(|| format!("`{key}` cannot be a date"))?;
+                args.extend([flag, value]);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(string) => Some(string.clone()),
+        Value::Integer(integer) => Some(integer.to_string()),
+        Value::Float(float) => Some(float.to_string()),
+        Value::Boolean(boolean) => Some(boolean.to_string()),
+        Value::Datetime(_) | Value::Array(_) | Value::Table(_) => None,
+    }
+}
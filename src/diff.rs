@@ -0,0 +1,86 @@
+//! Compare two results in the plain format
+//!
+//! Values are compared with a tolerance, so results rounded differently in
+//! the last digit still match.
+
+use std::{collections::BTreeMap, fs, io, mem};
+
+use onebrc::options::Options;
+
+// Values of a station in the order they are printed
+type Values = [f64; 3];
+
+// Returns whether the results match
+pub fn run(options: &Options) -> io::Result<bool> {
+    let expected = fs::read_to_string(&options.results[0])?;
+    let actual = fs::read_to_string(&options.results[1])?;
+    let (expected, actual) = (
+        parse(&expected, &options.results[0])?,
+        parse(&actual, &options.results[1])?,
+    );
+
+    let mut matching = true;
+    for (name, expected_values) in &expected {
+        let Some(actual_values) = actual.get(name) else {
+            println!("{name}: missing");
+            matching = false;
+            continue;
+        };
+        for ((label, expected), actual) in ["min", "max", "mean"]
+            .iter()
+            .zip(expected_values)
+            .zip(actual_values)
+        {
+            // Slack for the values not being exact in binary
+            if (expected - actual).abs() > options.tolerance + 1e-9 {
+                println!("{name}: {label} {expected} expected, got {actual}");
+                matching = false;
+            }
+        }
+    }
+    for name in actual.keys().filter(|name| !expected.contains_key(*name)) {
+        println!("{name}: unexpected");
+        matching = false;
+    }
+    Ok(matching)
+}
+
+fn parse(result: &str, path: &str) -> io::Result<BTreeMap<String, Values>> {
+    parse_stations(result).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("`{path}` is not a result in the plain format"),
+        )
+    })
+}
+
+// `{name=min/max/mean, ...}`, names may contain `=` and `, ` as in
+// `Washington, D.C.`, so a part without values belongs to the next name
+fn parse_stations(result: &str) -> Option<BTreeMap<String, Values>> {
+    let stations = result.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let mut parsed = BTreeMap::new();
+    if stations.is_empty() {
+        return Some(parsed);
+    }
+    let mut name = String::new();
+    for part in stations.split(", ") {
+        if !name.is_empty() {
+            name.push_str(", ");
+        }
+        match station(part) {
+            Some((last, values)) => {
+                name.push_str(last);
+                parsed.insert(mem::take(&mut name), values);
+            }
+            None => name.push_str(part),
+        }
+    }
+    name.is_empty().then_some(parsed)
+}
+
+fn station(part: &str) -> Option<(&str, Values)> {
+    let (name, values) = part.rsplit_once('=')?;
+    let mut values = values.split('/').map(|value| value.parse().ok());
+    let parsed = [values.next()??, values.next()??, values.next()??];
+    values.next().is_none().then_some((name, parsed))
+}
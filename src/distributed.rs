@@ -0,0 +1,227 @@
+//! Distributed aggregation
+//!
+//! Workers listen for tasks over TCP. The coordinator splits the file into
+//! one range of whole lines per worker, sends each its range and merges the
+//! partial results. The file must be readable at the same path on every
+//! worker, e.g. from a shared mount.
+//!
+//! Worker processes per NUMA node get their task the same way, over a pipe
+//! instead of a connection.
+//!
+//! A task is a single line of JSON, the worker answers with a status byte
+//! followed by a saved state on success or an error message otherwise, and
+//! closes the connection.
+
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    ops::Range,
+    process::{self, Stdio},
+    thread,
+};
+
+use log::debug;
+use onebrc::{
+    aggregate,
+    hash_table::HashTable,
+    line_ranges, load,
+    options::{Columns, Encoding, Header, Options, Period},
+    state, StationStats,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::numa;
+
+const OK: u8 = 0;
+const FAILED: u8 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Task {
+    path: String,
+    range: Range<usize>,
+    // Options that change how lines are aggregated
+    encoding: Encoding,
+    strict_utf8: bool,
+    lenient: bool,
+    flexible_values: bool,
+    delimiter: u8,
+    columns: Option<Columns>,
+    group_by: Option<Period>,
+    quotes: bool,
+}
+
+// Answer tasks one at a time until the listener fails
+pub fn work(options: &Options) -> io::Result<()> {
+    if options.pipe {
+        // A single task from a parent process on the same machine
+        if let Some(cpus) = &options.cpus {
+            numa::pin(cpus)?;
+        }
+        return answer(io::stdin().lock(), &mut io::stdout().lock());
+    }
+
+    let listener = TcpListener::bind((options.host.as_str(), options.port))?;
+    if !options.quiet {
+        eprintln!("waiting for tasks on {}", listener.local_addr()?);
+    }
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        // The coordinator reports the task as failed if it went away
+        let _ = answer(&stream, &mut &stream);
+    }
+    Ok(())
+}
+
+fn answer(input: impl Read, out: &mut impl Write) -> io::Result<()> {
+    let mut response = Vec::new();
+    match run_task(input, &mut response) {
+        Ok(()) => response.insert(0, OK),
+        Err(error) => {
+            response = error.to_string().into_bytes();
+            response.insert(0, FAILED);
+        }
+    }
+    out.write_all(&response)?;
+    out.flush()
+}
+
+fn run_task(input: impl Read, out: &mut Vec<u8>) -> io::Result<()> {
+    let mut line = String::new();
+    BufReader::new(input).read_line(&mut line)?;
+    let task: Task = serde_json::from_str(&line)?;
+    let options = Options {
+        encoding: task.encoding,
+        strict_utf8: task.strict_utf8,
+        lenient: task.lenient,
+        flexible_values: task.flexible_values,
+        delimiter: task.delimiter,
+        columns: task.columns,
+        group_by: task.group_by,
+        quotes: task.quotes,
+        // Ranges start after the header
+        header: Header::Absent,
+        ..Options::default()
+    };
+
+    let input = load(&File::open(&task.path)?)?;
+    let data = input
+        .get(task.range)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "range is outside the file"))?;
+    let result = aggregate(data, &options)?;
+    let stations = result.key_set().map(|(_, value)| value).collect::<Vec<_>>();
+    state::write(&stations, options.scale() as u8, out)
+}
+
+// Aggregate the file on all workers and merge their results
+pub fn coordinate(options: &Options) -> io::Result<HashTable<StationStats<'static>>> {
+    distribute(options, options.workers.len(), |i, task| {
+        let worker = &options.workers[i];
+        send(worker, task).map_err(|error| io::Error::other(format!("worker {worker}: {error}")))
+    })
+}
+
+// Aggregate the file with one worker process per NUMA node, each pinned to
+// the CPUs of its node and answering over a pipe
+pub fn per_node(
+    options: &Options,
+    nodes: &[String],
+) -> io::Result<HashTable<StationStats<'static>>> {
+    let exe = env::current_exe()?;
+    distribute(options, nodes.len(), |i, task| {
+        // Workers log like their parent, to the same stderr
+        let logging = match options.quiet {
+            true => vec!["--quiet"],
+            false => vec!["-v"; options.verbosity.into()],
+        };
+        let mut child = process::Command::new(&exe)
+            .args(["worker", "--pipe", "--cpus", &nodes[i]])
+            .args(logging)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut request = serde_json::to_vec(task)?;
+        request.push(b'\n');
+        child.stdin.take().unwrap().write_all(&request)?;
+
+        let mut response = Vec::new();
+        child.stdout.take().unwrap().read_to_end(&mut response)?;
+        child.wait()?;
+        decode_response(&response).map_err(|error| io::Error::other(format!("node {i}: {error}")))
+    })
+}
+
+// Split the file into `parts` tasks, run them in parallel with `run` and
+// merge the partial results
+fn distribute(
+    options: &Options,
+    parts: usize,
+    run: impl Fn(usize, &Task) -> io::Result<Vec<StationStats<'static>>> + Sync,
+) -> io::Result<HashTable<StationStats<'static>>> {
+    let input = load(&File::open(&options.file_name)?)?;
+    let ranges = line_ranges(&input, parts, options);
+
+    let run = &run;
+    let results = thread::scope(|scope| {
+        let handles = ranges
+            .into_iter()
+            .enumerate()
+            .map(|(i, range)| {
+                debug!("task {i}: bytes {range:?}");
+                let task = Task {
+                    path: options.file_name.clone(),
+                    range,
+                    encoding: options.encoding,
+                    strict_utf8: options.strict_utf8,
+                    lenient: options.lenient,
+                    flexible_values: options.flexible_values,
+                    delimiter: options.delimiter,
+                    columns: options.columns,
+                    group_by: options.group_by,
+                    quotes: options.quotes,
+                };
+                scope.spawn(move || run(i, &task))
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let mut merged = HashTable::new();
+    for result in results {
+        // Names are transcoded already, so they are the key
+        for stats in result? {
+            merged.insert_or_update(
+                stats.key(),
+                |merged: &StationStats| merged.name == stats.name,
+                |merged| merged.merge(&stats),
+                || stats.clone(),
+            );
+        }
+    }
+    Ok(merged)
+}
+
+fn send(worker: &str, task: &Task) -> io::Result<Vec<StationStats<'static>>> {
+    let mut stream = TcpStream::connect(worker)?;
+    let mut request = serde_json::to_vec(task)?;
+    request.push(b'\n');
+    stream.write_all(&request)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    decode_response(&response)
+}
+
+fn decode_response(response: &[u8]) -> io::Result<Vec<StationStats<'static>>> {
+    match response.split_first() {
+        Some((&OK, state)) => Ok(state::decode(state)?.1),
+        Some((_, message)) => Err(io::Error::other(String::from_utf8_lossy(message))),
+        None => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
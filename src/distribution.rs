@@ -0,0 +1,67 @@
+//! How often each value occurs over all rows, see `--distribution`
+//!
+//! Values are fixed point, so there are only a few thousand of them and a
+//! count for each is the finest histogram there is. The file is visited
+//! again once the stations are done, which only costs when asked for. For a
+//! generated file the counts are the normal distributions of its stations
+//! piled up, and the mean and standard deviation are logged to compare.
+
+use std::{io::Write, path::Path};
+
+use log::info;
+
+use onebrc::{options::Options, visit_parallel};
+
+use crate::output;
+
+// Write `value,count` lines from the lowest value of `data` to the highest
+pub fn write(data: &[u8], path: &Path, options: &Options) -> std::io::Result<()> {
+    let scale = options.scale();
+    // Values stay below 100 degrees
+    let offset = (100.0 * scale) as i32;
+    let values = 2 * offset as usize + 1;
+    let counts = visit_parallel(
+        data,
+        options,
+        || vec![0u64; values],
+        |counts, _, value| counts[(value + offset).clamp(0, 2 * offset) as usize] += 1,
+    )?
+    .into_iter()
+    .reduce(|mut total, counts| {
+        total
+            .iter_mut()
+            .zip(counts)
+            .for_each(|(total, count)| *total += count);
+        total
+    })
+    .unwrap_or_default();
+
+    let value = |index: usize| (index as i32 - offset) as f64 / scale;
+    let (mut rows, mut sum, mut squares) = (0u64, 0f64, 0f64);
+    for (index, &count) in counts.iter().enumerate() {
+        rows += count;
+        sum += value(index) * count as f64;
+        squares += value(index).powi(2) * count as f64;
+    }
+    if rows > 0 {
+        let mean = sum / rows as f64;
+        let deviation = (squares / rows as f64 - mean * mean).max(0.0).sqrt();
+        info!("{rows} values, mean {mean:.2}, standard deviation {deviation:.2}");
+    }
+
+    // Every step in between, also those that never occur
+    let used = counts.iter().position(|&count| count > 0).map(|first| {
+        let last = counts.iter().rposition(|&count| count > 0).unwrap();
+        first..=last
+    });
+    // Output decimals, at least those of the values
+    let precision = options.precision.max(scale.log10() as usize);
+    output::write_atomically(path, |out| {
+        writeln!(out, "value,count")?;
+        for index in used.into_iter().flatten() {
+            let converted = options.unit.convert(value(index));
+            writeln!(out, "{converted:.precision$},{}", counts[index])?;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,104 @@
+//! Estimate of the distinct stations, see `--estimate`
+//!
+//! A quick pass before aggregating reads a few blocks spread evenly over the
+//! file and counts their names with HyperLogLog. Tables are then sized for
+//! that many stations, so a small input does not allocate the default table
+//! and a large one does not resize. Stations too rare to be in a block are
+//! missed, the tables still grow for those.
+
+use log::info;
+
+use crate::{
+    options::Options,
+    scan::{self, Scalar},
+    visit,
+};
+
+// Blocks sampled, and bytes per block
+const BLOCKS: usize = 64;
+const BLOCK: usize = 64 * 1024;
+
+// 2^12 registers, off by about 1.6% in either direction
+const BITS: u32 = 12;
+const REGISTERS: usize = 1 << BITS;
+
+struct HyperLogLog {
+    // Longest run of leading zeros plus one seen per register
+    registers: Box<[u8]>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        Self {
+            registers: vec![0; REGISTERS].into_boxed_slice(),
+        }
+    }
+
+    // The first bits pick the register, the rest count zeros
+    fn insert(&mut self, hash: u64) {
+        let register = (hash >> (64 - BITS)) as usize;
+        let rank = ((hash << BITS) | (1 << (BITS - 1))).leading_zeros() as u8 + 1;
+        self.registers[register] = self.registers[register].max(rank);
+    }
+
+    fn estimate(&self) -> usize {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum = self
+            .registers
+            .iter()
+            .map(|&rank| 2f64.powi(-(rank as i32)))
+            .sum::<f64>();
+        let raw = alpha * m * m / sum;
+        // Few stations leave registers empty, counting those is closer then
+        let empty = self.registers.iter().filter(|&&rank| rank == 0).count();
+        let estimate = if raw <= 2.5 * m && empty > 0 {
+            m * (m / empty as f64).ln()
+        } else {
+            raw
+        };
+        estimate.round() as usize
+    }
+}
+
+// Distinct stations of `data`, from a sample of it
+pub(crate) fn stations(data: &[u8], options: &Options) -> usize {
+    let mut counter = HyperLogLog::new();
+    let mut rows = 0u64;
+    let mut count = |block: &[u8]| {
+        // A name that is not UTF-8 only ends the sample early, aggregating
+        // fails on it with `--strict-utf8` anyway
+        let _ = visit(block, options, |name, _| {
+            counter.insert(scan::mix(scan::key::<Scalar>(name)));
+            rows += 1;
+        });
+    };
+    if data.len() <= BLOCKS * BLOCK {
+        count(data);
+    } else {
+        let stride = data.len() / BLOCKS;
+        for block in 0..BLOCKS {
+            // Whole lines only, starting after the newline before the block
+            let start = match block {
+                0 => 0,
+                _ => line_start(data, block * stride),
+            };
+            let end = line_start(data, start + BLOCK);
+            count(&data[start..end]);
+        }
+    }
+    let stations = counter.estimate();
+    info!("estimated {stations} stations from {rows} sampled rows");
+    stations
+}
+
+// Start of the first line at or after `position`
+fn line_start(data: &[u8], position: usize) -> usize {
+    match data.get(position..) {
+        Some(rest) => rest
+            .iter()
+            .position(|&c| c == b'\n')
+            .map_or(data.len(), |newline| position + newline + 1),
+        None => data.len(),
+    }
+}
@@ -0,0 +1,125 @@
+//! C bindings
+//!
+//! ```c
+//! brc_result *result = brc_process_file("measurements.txt");
+//! for (size_t i = 0; i < brc_result_len(result); i++) {
+//!     brc_station station = brc_result_station(result, i);
+//! }
+//! brc_result_free(result);
+//! ```
+//!
+//! The header is `include/onebrc.h`, regenerate it with
+//! `cbindgen --config cbindgen.toml --output include/onebrc.h`.
+
+use std::{
+    ffi::{c_char, CStr},
+    fs::File,
+    panic, ptr,
+};
+
+use crate::{aggregate, load, options::Options};
+
+/// Aggregated stations of a file, in no particular order
+pub struct BrcResult {
+    // Owns the NUL terminated names the stations point into
+    _names: Vec<Vec<u8>>,
+    stations: Vec<BrcStation>,
+}
+
+/// A single station, values are in degrees Celsius
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BrcStation {
+    /// UTF-8 name, NUL terminated and valid until the result is freed
+    pub name: *const c_char,
+    /// Length of the name in bytes, without the terminator
+    pub name_len: usize,
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+    pub count: u32,
+}
+
+/// Aggregate the measurements file at `path` with the default options.
+///
+/// Returns NULL if the file cannot be read. The result must be released
+/// with `brc_result_free`.
+///
+/// # Safety
+///
+/// `path` must be a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn brc_process_file(path: *const c_char) -> *mut BrcResult {
+    if path.is_null() {
+        return ptr::null_mut();
+    }
+    let path = CStr::from_ptr(path).to_string_lossy().into_owned();
+    // Unwinding into C is undefined, so malformed input becomes NULL too
+    panic::catch_unwind(|| process_file(&path))
+        .ok()
+        .flatten()
+        .map_or(ptr::null_mut(), |result| Box::into_raw(Box::new(result)))
+}
+
+fn process_file(path: &str) -> Option<BrcResult> {
+    let options = Options::default();
+    let file = File::open(path).ok()?;
+    let input = load(&file).ok()?;
+    let result = aggregate(&input, &options).ok()?;
+
+    let scale = options.scale();
+    let mut names = Vec::new();
+    let mut stations = Vec::new();
+    for (_, stats) in result.key_set() {
+        let mut name = stats.name.to_vec();
+        name.push(0);
+        stations.push(BrcStation {
+            // The heap buffer stays in place when `names` grows
+            name: name.as_ptr().cast(),
+            name_len: name.len() - 1,
+            min: stats.min as f64 / scale,
+            mean: stats.sum as f64 / stats.count as f64 / scale,
+            max: stats.max as f64 / scale,
+            count: stats.count,
+        });
+        names.push(name);
+    }
+    Some(BrcResult {
+        _names: names,
+        stations,
+    })
+}
+
+/// Number of stations in the result.
+///
+/// # Safety
+///
+/// `result` must come from `brc_process_file` and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn brc_result_len(result: *const BrcResult) -> usize {
+    let result = &*result;
+    result.stations.len()
+}
+
+/// The station at `index`, which must be less than `brc_result_len`.
+///
+/// # Safety
+///
+/// `result` must come from `brc_process_file` and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn brc_result_station(result: *const BrcResult, index: usize) -> BrcStation {
+    let result = &*result;
+    result.stations[index]
+}
+
+/// Release a result, NULL is ignored.
+///
+/// # Safety
+///
+/// `result` must come from `brc_process_file` and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn brc_result_free(result: *mut BrcResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
@@ -0,0 +1,133 @@
+//! The final phase
+//!
+//! Tables, of threads, runs or workers, are merged by name into an ordered
+//! map, which is all the output needs: states are saved and rows formatted
+//! from it in one pass. Rows come out in byte order of their names, so that
+//! sort is free and only other orders sort again. Rows that need no sort
+//! are written as they are made by the formats that can. Ties are broken
+//! by name and values are computed from integer sums and counts, which any
+//! split of the file merges to the same totals, so the output does not
+//! depend on threads or hash order.
+
+use std::{collections::BTreeMap, path::Path, str};
+
+use onebrc::{
+    hash_table::HashTable,
+    name::Name,
+    options::{Collation, Options, SortKey},
+    state, StationStats,
+};
+
+use crate::{
+    outliers::Spreads,
+    output::{self, Row},
+};
+
+// Stations by name, names borrow from the tables
+pub struct Stations<'a> {
+    by_name: BTreeMap<&'a [u8], StationStats<'a>>,
+    // Of the rows to mark and their deviations, see `outliers`
+    spreads: Spreads,
+}
+
+impl<'a> Stations<'a> {
+    // Stations of the same name are merged, whatever their tables keyed
+    // them by
+    pub fn merge(tables: impl IntoIterator<Item = &'a HashTable<StationStats<'a>>>) -> Self {
+        let mut by_name = BTreeMap::new();
+        for table in tables {
+            for (_, stats) in table.key_set() {
+                by_name
+                    .entry(&stats.name[..])
+                    .or_insert_with(|| StationStats::new(Name::borrowed(&stats.name)))
+                    .merge(stats);
+            }
+        }
+        Self {
+            by_name,
+            spreads: Spreads::default(),
+        }
+    }
+
+    // Row for `stats`, marked if it is one of the outliers
+    fn row(&'a self, stats: &'a StationStats<'a>, options: &Options) -> Row<'a> {
+        let deviation = self.spreads.deviations.get(&stats.name[..]);
+        Row {
+            outlier: self.spreads.outliers.contains(&stats.name[..]),
+            deviation: deviation
+                .map_or(0.0, |&deviation| options.unit.convert_difference(deviation)),
+            ..Row::new(stats, options)
+        }
+    }
+
+    // In the order of `--sort`, by name in byte order without it
+    pub fn rows(&'a self, options: &Options) -> Vec<Row<'a>> {
+        if let Some(rows) = self.ordered_rows(options) {
+            return rows.collect();
+        }
+        let mut rows = self
+            .by_name
+            .values()
+            .map(|stats| self.row(stats, options))
+            .collect::<Vec<_>>();
+        output::sort(&mut rows, options.sort.unwrap(), options);
+        rows
+    }
+
+    // Rows as they come, if that is already the order of `--sort`
+    fn ordered_rows<'o>(
+        &'a self,
+        options: &'o Options,
+    ) -> Option<Box<dyn Iterator<Item = Row<'a>> + 'o>>
+    where
+        'a: 'o,
+    {
+        let stations = self.by_name.values();
+        // Names that are not valid UTF-8 may sort differently once replaced
+        let sorted = || {
+            options.collation == Collation::Bytes
+                && self.by_name.keys().all(|name| str::from_utf8(name).is_ok())
+        };
+        let row = |stats| self.row(stats, options);
+        match options.sort {
+            None => Some(Box::new(stations.map(row))),
+            Some(SortKey::Name) if sorted() && options.descending => {
+                Some(Box::new(stations.rev().map(row)))
+            }
+            Some(SortKey::Name) if sorted() => Some(Box::new(stations.map(row))),
+            Some(_) => None,
+        }
+    }
+}
+
+// Save the state if asked to and write the result
+pub fn finish(result: &HashTable<StationStats>, options: &Options) {
+    finish_flagged(result, Spreads::default(), options);
+}
+
+// Like `finish`, marking the outliers of `spreads` and writing their
+// deviations
+pub fn finish_flagged(result: &HashTable<StationStats>, spreads: Spreads, options: &Options) {
+    let mut stations = Stations::merge([result]);
+    stations.spreads = spreads;
+    if let Some(path) = &options.save_state {
+        let stations = stations.by_name.values().collect::<Vec<_>>();
+        let scale = options.scale() as u8;
+        let saved =
+            output::write_atomically(Path::new(path), |out| state::write(&stations, scale, out));
+        if let Err(error) = saved {
+            eprintln!("failed to save the state: {error}");
+            std::process::exit(1);
+        }
+    }
+    // Rows in order are written as they are made, many stations would
+    // otherwise take as much memory again
+    let emitted = match stations.ordered_rows(options) {
+        Some(rows) if output::streams(options.format) => output::emit_streamed(rows, options),
+        _ => output::emit(&stations.rows(options), options),
+    };
+    if let Err(error) = emitted {
+        eprintln!("failed to write the result: {error}");
+        std::process::exit(1);
+    }
+}
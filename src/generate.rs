@@ -0,0 +1,84 @@
+//! Random measurement files
+//!
+//! Like the generator of the original challenge, every row is a random
+//! station with a value drawn from a normal distribution around the mean of
+//! that station, with a standard deviation of 10. The same seed always gives
+//! the same file.
+
+use std::{
+    f64::consts::TAU,
+    io::{self, Write},
+    path::Path,
+};
+
+use onebrc::options::Options;
+
+use crate::output;
+
+// Names and mean temperatures, one `name;mean` per line
+const STATIONS: &str = include_str!("stations.txt");
+
+pub fn run(options: &Options) -> io::Result<()> {
+    output::write_atomically(Path::new(&options.file_name), |out| write(out, options))
+}
+
+// The rows of `--rows`, `--stations` and `--seed`
+pub fn write(out: &mut impl Write, options: &Options) -> io::Result<()> {
+    let stations = stations(options.stations);
+    let mut random = Random(options.seed);
+    for _ in 0..options.rows {
+        let (name, mean) = &stations[random.below(stations.len())];
+        let value = (mean + random.normal() * 10.0).clamp(-99.9, 99.9);
+        writeln!(out, "{name};{value:.1}")?;
+    }
+    Ok(())
+}
+
+// Beyond the known stations names repeat with a number, like `Abha 2`
+fn stations(count: usize) -> Vec<(String, f64)> {
+    let known = STATIONS
+        .lines()
+        .map(|line| {
+            let (name, mean) = line.split_once(';').expect("stations are `name;mean`");
+            (name, mean.parse::<f64>().expect("means are numbers"))
+        })
+        .collect::<Vec<_>>();
+    (0..count)
+        .map(|i| {
+            let (name, mean) = known[i % known.len()];
+            match i / known.len() {
+                0 => (name.to_string(), mean),
+                round => (format!("{name} {}", round + 1), mean),
+            }
+        })
+        .collect()
+}
+
+// SplitMix64, good enough for test data and without a dependency
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in 0..n
+    fn below(&mut self, n: usize) -> usize {
+        ((self.next() as u128 * n as u128) >> 64) as usize
+    }
+
+    // Uniform in (0, 1]
+    fn unit(&mut self) -> f64 {
+        ((self.next() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    // Standard normal with the Box-Muller transform
+    fn normal(&mut self) -> f64 {
+        let (u, v) = (self.unit(), self.unit());
+        (-2.0 * u.ln()).sqrt() * (TAU * v).cos()
+    }
+}
@@ -0,0 +1,285 @@
+//! Experimental GPU aggregation
+//!
+//! The input is uploaded in segments of whole lines. Every invocation of the
+//! kernel in `gpu.wgsl` parses the lines starting in its window of bytes and
+//! updates a shared open addressing table with atomics. A second pass then
+//! compares the name of every line with the one its slot was claimed with,
+//! names are only hashed to 32 bits, and the CPU aggregates instead if they
+//! differ. The table stays on the device for all segments and is read back
+//! once at the end.
+//!
+//! Only the default line format is supported, anything else and devices
+//! without a compute capable adapter fall back to the CPU.
+
+use std::sync::mpsc;
+
+use log::{debug, info};
+
+use wgpu::util::DeviceExt;
+
+use crate::{find_next, hash_table::HashTable, line_ranges, options::Options, StationStats, Sum};
+
+// Comfortably below the default storage buffer binding limit of 128 MiB
+const SEGMENT: usize = 64 << 20;
+// Bytes per invocation
+const WINDOW: u32 = 4096;
+const WORKGROUP: u32 = 64;
+// Power of two, well above the 10,000 stations of the challenge
+const SLOTS: usize = 1 << 16;
+// See the bias of `Slot` in the kernel
+const BIAS: i64 = 1000;
+// Matches `NAME_WORDS` in the kernel
+const NAME_WORDS: usize = 26;
+// Set in `full` by the kernel, different names sharing a key set 2
+const FULL: u32 = 1;
+
+// Matches `Slot` in the kernel
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Slot {
+    key: u32,
+    segment: u32,
+    name: u32,
+    count: u32,
+    sum_low: u32,
+    sum_high: u32,
+    min: i32,
+    max: i32,
+}
+
+const EMPTY: Slot = Slot {
+    key: 0,
+    segment: 0,
+    name: 0,
+    count: 0,
+    sum_low: 0,
+    sum_high: 0,
+    min: i32::MAX,
+    max: i32::MIN,
+};
+
+// Returns `None` where the CPU has to aggregate instead
+pub fn aggregate<'a>(data: &'a [u8], options: &'a Options) -> Option<HashTable<StationStats<'a>>> {
+    if options.lenient
+        || options.strict_utf8
+        || options.dedup.is_some()
+        || options.flexible_values
+        || options.columns.is_some()
+        || options.quotes
+    {
+        debug!("the GPU does not support these input options");
+        return None;
+    }
+    pollster::block_on(run(data, options))
+}
+
+async fn run<'a>(data: &'a [u8], options: &'a Options) -> Option<HashTable<StationStats<'a>>> {
+    let instance = wgpu::Instance::default();
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await
+        .inspect_err(|error| debug!("no GPU adapter: {error}"))
+        .ok()?;
+    info!("aggregating on {}", adapter.get_info().name);
+    let (device, queue) = adapter
+        .request_device(&wgpu::DeviceDescriptor::default())
+        .await
+        .ok()?;
+
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("aggregate"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("gpu.wgsl").into()),
+    });
+    let pipeline = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: None,
+            module: &module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        })
+    };
+    // Names are compared once a segment is aggregated, different ones
+    // could share the key of a slot
+    let pipelines = [pipeline("main"), pipeline("check")];
+
+    let segments = line_ranges(data, data.len().div_ceil(SEGMENT), options);
+    let largest = segments.iter().map(|range| range.len()).max()?;
+    let storage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+    let data_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("data"),
+        size: largest.next_multiple_of(4) as u64,
+        usage: storage,
+        mapped_at_creation: false,
+    });
+    let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("params"),
+        size: 5 * 4,
+        usage: storage,
+        mapped_at_creation: false,
+    });
+    let table_bytes = slots_to_bytes(&[EMPTY; SLOTS]);
+    let table_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("table"),
+        contents: &table_bytes,
+        usage: storage | wgpu::BufferUsages::COPY_SRC,
+    });
+    let full_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("full"),
+        contents: &0u32.to_le_bytes(),
+        usage: storage | wgpu::BufferUsages::COPY_SRC,
+    });
+    let names_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("names"),
+        size: (SLOTS * NAME_WORDS * 4) as u64,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+    let bind_groups = pipelines.each_ref().map(|pipeline| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("aggregate"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                data_buffer.as_entire_binding(),
+                params_buffer.as_entire_binding(),
+                table_buffer.as_entire_binding(),
+                full_buffer.as_entire_binding(),
+                names_buffer.as_entire_binding(),
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(binding, resource)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource,
+            })
+            .collect::<Vec<_>>(),
+        })
+    });
+
+    for (segment, range) in segments.iter().enumerate() {
+        let mut bytes = data[range.clone()].to_vec();
+        bytes.resize(bytes.len().next_multiple_of(4), 0);
+        queue.write_buffer(&data_buffer, 0, &bytes);
+        let params = [
+            range.len() as u32,
+            WINDOW,
+            options.delimiter as u32,
+            SLOTS as u32 - 1,
+            segment as u32,
+        ];
+        queue.write_buffer(&params_buffer, 0, &words_to_bytes(&params));
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        for (pipeline, bind_group) in pipelines.iter().zip(&bind_groups) {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            let windows = (range.len() as u32).div_ceil(WINDOW);
+            pass.dispatch_workgroups(windows.div_ceil(WORKGROUP), 1, 1);
+        }
+        queue.submit([encoder.finish()]);
+    }
+
+    let table = read_back(&device, &queue, &table_buffer).await?;
+    let full = read_back(&device, &queue, &full_buffer).await?;
+    match u32::from_le_bytes(full.try_into().ok()?) {
+        0 => {}
+        FULL => {
+            debug!("too many stations for the GPU hash table");
+            return None;
+        }
+        _ => {
+            debug!("names share a key in the GPU hash table, or are too long to compare");
+            return None;
+        }
+    }
+
+    let mut result = HashTable::new();
+    for slot in bytes_to_slots(&table)
+        .into_iter()
+        .filter(|slot| slot.key != 0)
+    {
+        let start = segments[slot.segment as usize].start + slot.name as usize;
+        let name = &data[start..find_next(data, start, options.delimiter)];
+        let sum = ((slot.sum_high as i64) << 32 | slot.sum_low as i64) - BIAS * slot.count as i64;
+        let mut stats = StationStats::new(options.encoding.decode(name).into());
+        stats.sum = sum as Sum;
+        stats.count = slot.count;
+        stats.min = slot.min;
+        stats.max = slot.max;
+        result.push(slot.key as u64, stats);
+    }
+    Some(result)
+}
+
+async fn read_back(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+) -> Option<Vec<u8>> {
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("read back"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+    queue.submit([encoder.finish()]);
+
+    let (sender, receiver) = mpsc::channel();
+    staging
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).unwrap()
+        });
+    device.poll(wgpu::PollType::wait_indefinitely()).ok()?;
+    receiver.recv().ok()?.ok()?;
+    let bytes = staging.slice(..).get_mapped_range().ok()?.to_vec();
+    Some(bytes)
+}
+
+fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+fn slots_to_bytes(slots: &[Slot]) -> Vec<u8> {
+    slots
+        .iter()
+        .flat_map(|slot| {
+            let Slot {
+                key,
+                segment,
+                name,
+                count,
+                sum_low,
+                sum_high,
+                min,
+                max,
+            } = *slot;
+            words_to_bytes(&[
+                key, segment, name, count, sum_low, sum_high, min as u32, max as u32,
+            ])
+        })
+        .collect()
+}
+
+fn bytes_to_slots(bytes: &[u8]) -> Vec<Slot> {
+    bytes
+        .chunks_exact(32)
+        .map(|slot| {
+            let word = |i: usize| u32::from_le_bytes(slot[i * 4..i * 4 + 4].try_into().unwrap());
+            Slot {
+                key: word(0),
+                segment: word(1),
+                name: word(2),
+                count: word(3),
+                sum_low: word(4),
+                sum_high: word(5),
+                min: word(6) as i32,
+                max: word(7) as i32,
+            }
+        })
+        .collect()
+}
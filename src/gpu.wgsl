@@ -0,0 +1,195 @@
+// Parses and aggregates one segment of whole lines, see src/gpu.rs
+
+struct Params {
+    // Bytes in this segment
+    len: u32,
+    // Bytes per invocation
+    window: u32,
+    delimiter: u32,
+    // Slots in the table minus one
+    mask: u32,
+    segment: u32,
+}
+
+// Sums are split into two words since there are no 64 bit atomics. Values
+// are biased by 1000 so the low word only ever carries into the high one.
+struct Slot {
+    key: atomic<u32>,
+    segment: atomic<u32>,
+    name: atomic<u32>,
+    count: atomic<u32>,
+    sum_low: atomic<u32>,
+    sum_high: atomic<u32>,
+    min: atomic<i32>,
+    max: atomic<i32>,
+}
+
+// The longest name compared, longer ones are left to the CPU
+const NAME_BYTES: u32 = 100u;
+// The length of a name and its bytes, four to a word
+const NAME_WORDS: u32 = 26u;
+
+@group(0) @binding(0) var<storage, read> data: array<u32>;
+@group(0) @binding(1) var<storage, read> params: Params;
+@group(0) @binding(2) var<storage, read_write> table: array<Slot>;
+// 1 when the table is full, 2 when different names share a key or a name
+// is too long to compare, the result is wrong then
+@group(0) @binding(3) var<storage, read_write> full: atomic<u32>;
+// The name of each slot, copied when it is claimed so lines of later
+// segments can be compared with it
+@group(0) @binding(4) var<storage, read_write> names: array<u32>;
+
+fn byte_at(index: u32) -> u32 {
+    return (data[index >> 2u] >> ((index & 3u) * 8u)) & 0xffu;
+}
+
+// Start of the first line of the window of invocation `id`, `params.len`
+// or past it if there is none
+fn first_line(id: u32) -> u32 {
+    var position = id * params.window;
+    if position >= params.len {
+        return position;
+    }
+    // A line belongs to the window its first byte is in
+    if position > 0u && byte_at(position - 1u) != 10u {
+        while position < params.len && byte_at(position) != 10u {
+            position += 1u;
+        }
+        position += 1u;
+    }
+    return position;
+}
+
+fn copy_name(slot: u32, name: u32, len: u32) {
+    let base = slot * NAME_WORDS;
+    names[base] = len;
+    for (var word = 0u; word * 4u < min(len, NAME_BYTES); word += 1u) {
+        var bytes = 0u;
+        for (var i = 0u; i < 4u && word * 4u + i < len; i += 1u) {
+            bytes |= byte_at(name + word * 4u + i) << (i * 8u);
+        }
+        names[base + 1u + word] = bytes;
+    }
+}
+
+fn same_name(slot: u32, name: u32, len: u32) -> bool {
+    let base = slot * NAME_WORDS;
+    if names[base] != len {
+        return false;
+    }
+    for (var i = 0u; i < len; i += 1u) {
+        let stored = (names[base + 1u + (i >> 2u)] >> ((i & 3u) * 8u)) & 0xffu;
+        if stored != byte_at(name + i) {
+            return false;
+        }
+    }
+    return true;
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    var position = first_line(id.x);
+    let end = min(id.x * params.window + params.window, params.len);
+
+    while position < end {
+        let name = position;
+        var hash = 0u;
+        while position < params.len && byte_at(position) != params.delimiter {
+            hash = hash * 31u + byte_at(position);
+            position += 1u;
+        }
+        // Zero marks empty slots
+        hash = max(hash, 1u);
+        let len = position - name;
+        position += 1u;
+
+        var negative = false;
+        if position < params.len && byte_at(position) == 45u {
+            negative = true;
+            position += 1u;
+        }
+        // Skips the decimal point and the carriage return of CRLF endings
+        var value = 0;
+        while position < params.len && byte_at(position) != 10u {
+            let digit = byte_at(position);
+            if digit >= 48u && digit <= 57u {
+                value = value * 10 + i32(digit - 48u);
+            }
+            position += 1u;
+        }
+        position += 1u;
+        if negative {
+            value = -value;
+        }
+
+        var index = hash & params.mask;
+        var probes = 0u;
+        loop {
+            let slot = atomicCompareExchangeWeak(&table[index].key, 0u, hash);
+            if slot.exchanged {
+                atomicStore(&table[index].segment, params.segment);
+                atomicStore(&table[index].name, name);
+                copy_name(index, name, len);
+            }
+            if slot.exchanged || slot.old_value == hash {
+                break;
+            }
+            // Weak exchanges may fail spuriously, retry the empty slot
+            if slot.old_value != 0u {
+                index = (index + 1u) & params.mask;
+                probes += 1u;
+                if probes > params.mask {
+                    atomicStore(&full, 1u);
+                    return;
+                }
+            }
+        }
+
+        atomicAdd(&table[index].count, 1u);
+        let biased = u32(value + 1000);
+        let low = atomicAdd(&table[index].sum_low, biased);
+        if low > 0xffffffffu - biased {
+            atomicAdd(&table[index].sum_high, 1u);
+        }
+        atomicMin(&table[index].min, value);
+        atomicMax(&table[index].max, value);
+    }
+}
+
+// Run over each segment after `main`, once all slots it claimed have their
+// names: every line compares its name with that of the slot of its key
+@compute @workgroup_size(64)
+fn check(@builtin(global_invocation_id) id: vec3<u32>) {
+    var position = first_line(id.x);
+    let end = min(id.x * params.window + params.window, params.len);
+
+    while position < end {
+        let name = position;
+        var hash = 0u;
+        while position < params.len && byte_at(position) != params.delimiter {
+            hash = hash * 31u + byte_at(position);
+            position += 1u;
+        }
+        hash = max(hash, 1u);
+        let len = position - name;
+        while position < params.len && byte_at(position) != 10u {
+            position += 1u;
+        }
+        position += 1u;
+
+        // Only missing from a full table, which fails anyway
+        var index = hash & params.mask;
+        var probes = 0u;
+        while atomicLoad(&table[index].key) != hash {
+            index = (index + 1u) & params.mask;
+            probes += 1u;
+            if probes > params.mask {
+                return;
+            }
+        }
+        if len > NAME_BYTES || !same_name(index, name, len) {
+            atomicStore(&full, 2u);
+            return;
+        }
+    }
+}
@@ -1,90 +1,330 @@
 //! Hash Table
 //!
-//! Code taken from [The Algorithms - Rust](https://github.com/TheAlgorithms/Rust)
+//! Open addressing with Robin Hood probing: while probing for a free slot an
+//! entry that is further away from its home slot than the resident one takes
+//! its place. This keeps probe sequences short even at high load factors.
+//! Keys are hashes of names and may collide, so a resident with the key is
+//! only the entry looked for if the caller says it is, probing goes on past
+//! the others. Removing shifts the entries after the removed one back until
+//! one is at home, so lookups still stop at the first empty slot.
 
-use std::vec::IntoIter;
+use std::{fmt, iter::Flatten, marker::PhantomData, mem, vec};
+
+use crate::memory::Counted;
+
+use serde::{
+    de::{MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+// Slots are cache line aligned so a lookup touches a single line
+#[repr(align(64))]
+struct Slot<T> {
+    key: u64,
+    // Distance from the home slot of `key`
+    distance: u32,
+    value: T,
+}
+
+// Slots of a new table
+pub(crate) const CAPACITY: usize = 1 << 14;
 
 pub struct HashTable<T> {
-    buckets: Vec<Vec<(u64, T)>>,
+    slots: Vec<Option<Slot<T>>>,
     size: usize,
+    mask: usize,
+    resizes: u32,
+    // The slots, a field of its own so tables borrowing names can drop
+    // after what they borrow from
+    counted: Counted,
+}
+
+// How well the table copes with its keys, see `--hash-stats`
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub len: usize,
+    pub capacity: usize,
+    // Longest distance of an entry from its home slot
+    pub longest_probe: u32,
+    pub resizes: u32,
 }
 
 impl<T> HashTable<T> {
-    #[inline(always)]
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
     pub fn new() -> Self {
-        let initial_size = 1 << 16;
-        let mut buckets = Vec::with_capacity(initial_size);
+        Self::with_capacity(CAPACITY)
+    }
 
-        for _ in 0..initial_size {
-            buckets.push(Vec::new());
-        }
+    // Room for `stations` before it grows at 7/8 load
+    pub(crate) fn for_stations(stations: usize) -> Self {
+        Self::with_capacity(stations * 8 / 7 + 1)
+    }
+
+    // Most stations a table sized with `for_stations` holds within `bytes`
+    pub(crate) fn stations_within(bytes: usize) -> usize {
+        let slots = (bytes / mem::size_of::<Option<Slot<T>>>()).max(2);
+        let slots: usize = 1 << (usize::BITS - 1 - slots.leading_zeros());
+        (slots * 7 / 8).saturating_sub(1).max(1)
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.next_power_of_two();
+        let mut slots = Vec::with_capacity(capacity);
+        slots.resize_with(capacity, || None);
+        let counted = Counted::new(slots.capacity() * mem::size_of::<Option<Slot<T>>>());
 
-        Self { buckets, size: 0 }
+        Self {
+            slots,
+            size: 0,
+            mask: capacity - 1,
+            resizes: 0,
+            counted,
+        }
     }
 
-    #[inline(always)]
+    // Update the entry of `key` that `is` the one looked for, or insert the
+    // provided one after those that are not
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
     pub fn insert_or_update(
         &mut self,
         key: impl Hash,
+        mut is: impl FnMut(&T) -> bool,
         modify: impl FnOnce(&mut T),
         provide: impl FnOnce() -> T,
     ) {
-        if self.size >= self.buckets.len() * 3 / 4 {
+        // Resize at 7/8 load, robin hood probing copes well with that
+        if self.size * 8 >= self.slots.len() * 7 {
             self.resize();
         }
-        let index = key.hash() as usize % self.buckets.len();
-        if let Some(record) = self.buckets[index]
-            .iter_mut()
-            .find(|(k, _)| k.hash() == key.hash())
-            .map(|(_, v)| v)
-        {
-            modify(record);
-        } else {
-            self.buckets[index].push((key.hash(), provide()));
-            self.size += 1;
+        let key = key.hash();
+        let mut index = key as usize & self.mask;
+        let mut distance = 0;
+        loop {
+            match &mut self.slots[index] {
+                Some(slot) if slot.key == key && is(&slot.value) => {
+                    modify(&mut slot.value);
+                    return;
+                }
+                // Poorer than the resident, so take its slot and move it on
+                Some(slot) if slot.distance < distance => {
+                    let new = Slot {
+                        key,
+                        distance,
+                        value: provide(),
+                    };
+                    let mut displaced = mem::replace(slot, new);
+                    displaced.distance += 1;
+                    self.place(displaced, (index + 1) & self.mask);
+                    self.size += 1;
+                    return;
+                }
+                Some(_) => {}
+                empty => {
+                    *empty = Some(Slot {
+                        key,
+                        distance,
+                        value: provide(),
+                    });
+                    self.size += 1;
+                    return;
+                }
+            }
+            index = (index + 1) & self.mask;
+            distance += 1;
         }
     }
 
-    #[inline(always)]
-    fn resize(&mut self) {
-        let new_size = self.buckets.len() * 2;
-        let mut new_buckets = Vec::with_capacity(new_size);
+    // Insert `value` as an entry of its own, even if another has `key`
+    pub fn push(&mut self, key: u64, value: T) {
+        self.insert_or_update(key, |_| false, |_| {}, || value);
+    }
 
-        for _ in 0..new_size {
-            new_buckets.push(Vec::new());
+    // Take out the entry of `key` that `is` the one looked for, and shift the
+    // entries probed past it back a slot so no tombstone is left behind
+    pub fn remove(&mut self, key: impl Hash, mut is: impl FnMut(&T) -> bool) -> Option<T> {
+        let key = key.hash();
+        let mut index = key as usize & self.mask;
+        let mut distance = 0;
+        loop {
+            match &self.slots[index] {
+                Some(slot) if slot.key == key && is(&slot.value) => break,
+                // An entry of `key` would have taken this slot
+                Some(slot) if slot.distance < distance => return None,
+                Some(_) => {}
+                None => return None,
+            }
+            index = (index + 1) & self.mask;
+            distance += 1;
         }
+        let removed = self.slots[index].take().map(|slot| slot.value);
+        loop {
+            let next = (index + 1) & self.mask;
+            match self.slots[next].take() {
+                Some(mut slot) if slot.distance > 0 => {
+                    slot.distance -= 1;
+                    self.slots[index] = Some(slot);
+                }
+                // Empty or at home, the chain ends here
+                slot => {
+                    self.slots[next] = slot;
+                    break;
+                }
+            }
+            index = next;
+        }
+        self.size -= 1;
+        removed
+    }
 
-        for bucket in self.buckets.drain(..) {
-            for (key, value) in bucket {
-                let index = key.hash() as usize % new_size;
-                new_buckets[index].push((key, value));
+    // Probe from `index` for a free slot, displacing richer entries on the way
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn place(&mut self, mut slot: Slot<T>, mut index: usize) {
+        loop {
+            match &mut self.slots[index] {
+                Some(resident) => {
+                    if resident.distance < slot.distance {
+                        mem::swap(resident, &mut slot);
+                    }
+                }
+                empty => {
+                    *empty = Some(slot);
+                    return;
+                }
             }
+            index = (index + 1) & self.mask;
+            slot.distance += 1;
+        }
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn resize(&mut self) {
+        let mut resized = Self::with_capacity(self.slots.len() * 2);
+
+        for mut slot in self.slots.drain(..).flatten() {
+            let index = slot.key as usize & resized.mask;
+            slot.distance = 0;
+            resized.place(slot, index);
         }
+        resized.size = self.size;
+        resized.resizes = self.resizes + 1;
 
-        self.buckets = new_buckets;
+        *self = resized;
     }
 
-    #[inline(always)]
-    pub fn key_set(&self) -> impl Iterator<Item = &(u64, T)> {
-        self.buckets.iter().flat_map(|bucket| bucket.iter())
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    pub fn key_set(&self) -> impl Iterator<Item = (u64, &T)> {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|slot| (slot.key, &slot.value))
+    }
+}
+
+impl<T> HashTable<T> {
+    pub fn stats(&self) -> Stats {
+        Stats {
+            len: self.size,
+            capacity: self.slots.len(),
+            longest_probe: self
+                .slots
+                .iter()
+                .flatten()
+                .map(|slot| slot.distance)
+                .max()
+                .unwrap_or(0),
+            resizes: self.resizes,
+        }
+    }
+}
+
+impl<T> Default for HashTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct IntoIter<T> {
+    slots: Flatten<vec::IntoIter<Option<Slot<T>>>>,
+    _counted: Counted,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = (u64, T);
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots.next().map(|slot| (slot.key, slot.value))
     }
 }
 
 impl<T> IntoIterator for HashTable<T> {
     type Item = (u64, T);
-    type IntoIter = IntoIter<(u64, T)>;
+    type IntoIter = IntoIter<T>;
 
-    #[inline(always)]
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
     fn into_iter(self) -> Self::IntoIter {
-        let mut records = Vec::new();
+        IntoIter {
+            slots: self.slots.into_iter().flatten(),
+            _counted: self.counted,
+        }
+    }
+}
+
+// Every value is an entry of its own, like in the table collected from
+impl<T> FromIterator<(u64, T)> for HashTable<T> {
+    fn from_iter<I: IntoIterator<Item = (u64, T)>>(iter: I) -> Self {
+        let mut table = HashTable::new();
+        for (key, value) in iter {
+            table.push(key, value);
+        }
+        table
+    }
+}
+
+// Serialized as a map from key to value, slot layout is not preserved
+impl<T: Serialize> Serialize for HashTable<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.size))?;
+        for (key, value) in self.key_set() {
+            map.serialize_entry(&key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for HashTable<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TableVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for TableVisitor<T> {
+            type Value = HashTable<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of hash keys to values")
+            }
 
-        for bucket in self.buckets {
-            for item in bucket {
-                records.push(item);
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut table = HashTable::new();
+                while let Some((key, value)) = map.next_entry::<u64, T>()? {
+                    table.push(key, value);
+                }
+                Ok(table)
             }
         }
 
-        records.into_iter()
+        deserializer.deserialize_map(TableVisitor(PhantomData))
     }
 }
 
@@ -93,14 +333,14 @@ pub trait Hash {
 }
 
 impl Hash for u64 {
-    #[inline(always)]
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
     fn hash(&self) -> u64 {
         *self
     }
 }
 
 impl Hash for &[u8] {
-    #[inline(always)]
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
     fn hash(&self) -> u64 {
         let mut hash = 0u64;
 
@@ -0,0 +1,146 @@
+//! Heap usage per phase of a run, with the `heap-profile` feature
+//!
+//! A global allocator counts the bytes in use and the most there were since
+//! the last `phase` started, so the end of a run shows which phase peaked,
+//! e.g. merging that clones tables. Phases follow each other in time, the
+//! merging of shards that overlaps aggregating counts towards aggregating.
+//! Without the feature `phase` does nothing and `print` prints nothing, and
+//! `alloc-check` brings its own allocator, which wins.
+
+// Bytes of the heap during a phase
+#[derive(Debug, Clone, Copy)]
+pub struct Phase {
+    pub name: &'static str,
+    pub start: usize,
+    pub peak: usize,
+    pub end: usize,
+    pub allocations: u64,
+}
+
+#[cfg(all(feature = "heap-profile", not(feature = "alloc-check")))]
+pub use counting::{phase, phases};
+
+#[cfg(all(feature = "heap-profile", not(feature = "alloc-check")))]
+mod counting {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        sync::{
+            atomic::{AtomicU64, AtomicUsize, Ordering},
+            Mutex,
+        },
+    };
+
+    use super::Phase;
+
+    static IN_USE: AtomicUsize = AtomicUsize::new(0);
+    // Of the current phase
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+    static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+    // The phases so far and the one going on, if any
+    static PHASES: Mutex<(Vec<Phase>, Option<Phase>)> = Mutex::new((Vec::new(), None));
+
+    struct Counting;
+
+    impl Counting {
+        fn grow(bytes: usize) {
+            let in_use = IN_USE.fetch_add(bytes, Ordering::Relaxed) + bytes;
+            PEAK.fetch_max(in_use, Ordering::Relaxed);
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Counting only, allocating in the allocator is not allowed
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            Self::grow(layout.size());
+            System.alloc(layout)
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            Self::grow(layout.size());
+            System.alloc_zeroed(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+            IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+            Self::grow(size);
+            System.realloc(ptr, layout, size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    // End the current phase and start `name`
+    pub fn phase(name: &'static str) {
+        let mut phases = PHASES.lock().unwrap();
+        let (done, current) = &mut *phases;
+        done.extend(current.take().map(end));
+        let start = IN_USE.load(Ordering::Relaxed);
+        PEAK.store(start, Ordering::Relaxed);
+        *current = Some(Phase {
+            name,
+            start,
+            peak: start,
+            end: start,
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        });
+    }
+
+    // All phases, the current one ends
+    pub fn phases() -> Vec<Phase> {
+        let mut phases = PHASES.lock().unwrap();
+        let (done, current) = &mut *phases;
+        done.extend(current.take().map(end));
+        done.clone()
+    }
+
+    // `phase` with what happened since it started, its `allocations` were
+    // the total at the start
+    fn end(phase: Phase) -> Phase {
+        Phase {
+            peak: PEAK.load(Ordering::Relaxed),
+            end: IN_USE.load(Ordering::Relaxed),
+            allocations: ALLOCATIONS.load(Ordering::Relaxed) - phase.allocations,
+            ..phase
+        }
+    }
+}
+
+#[cfg(not(all(feature = "heap-profile", not(feature = "alloc-check"))))]
+pub fn phase(_name: &'static str) {}
+
+#[cfg(not(all(feature = "heap-profile", not(feature = "alloc-check"))))]
+pub fn phases() -> Vec<Phase> {
+    Vec::new()
+}
+
+// A line per phase on stderr, in MiB
+pub fn print() {
+    let phases = phases();
+    let Some(peak) = phases.iter().map(|phase| phase.peak).max() else {
+        return;
+    };
+    let mib = |bytes: usize| bytes as f64 / (1 << 20) as f64;
+    eprintln!("heap: peak {:.1} MiB", mib(peak));
+    let width = phases
+        .iter()
+        .map(|phase| phase.name.len())
+        .max()
+        .unwrap_or(0);
+    for phase in phases {
+        eprintln!(
+            "  {:<width$}  {:>9.1} MiB at the start, {:>9.1} peak, {:>9.1} at the end, {} allocations",
+            phase.name,
+            mib(phase.start),
+            mib(phase.peak),
+            mib(phase.end),
+            phase.allocations
+        );
+    }
+}
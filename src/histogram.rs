@@ -0,0 +1,87 @@
+//! Values of each station counted in bins, see `--histogram`
+//!
+//! Every station gets the same `--bins` bins of equal width from -100 to
+//! 100 degrees, so its histograms can be plotted side by side without
+//! reading the file again. Edges are converted to the output unit, which
+//! keeps them equally wide.
+
+use std::{collections::BTreeMap, fs::File, io, io::Write};
+
+use serde::Serialize;
+
+use onebrc::{
+    aggregate_with,
+    aggregator::{Aggregator, Histogram},
+    options::{Format, Options},
+    Skipped,
+};
+
+use crate::{load_input, output};
+
+// A station as JSON, `counts[i]` of values from `edges[i]` below `edges[i + 1]`
+#[derive(Serialize)]
+struct JsonHistogram<'a> {
+    name: &'a str,
+    edges: &'a [f64],
+    counts: &'a [u32],
+}
+
+// Aggregate the input file into histograms and write them in name order,
+// returns the lines skipped
+pub fn run(file: &File, options: &Options) -> io::Result<Skipped> {
+    let input = load_input(file, options)?;
+    let (result, skipped) = aggregate_with::<Histogram>(&input, options)?;
+    let stations = result
+        .key_set()
+        .map(|(_, station)| (&station.name[..], station.stats.finalize(options)))
+        .collect::<BTreeMap<_, _>>();
+
+    let factor = 10f64.powi(options.precision as i32);
+    let edges = (0..=options.bins)
+        .map(|bin| options.unit.convert(Histogram::edge(bin, options)))
+        .map(|edge| (edge * factor).round() / factor)
+        .collect::<Vec<_>>();
+    output::emit_with(options, |out| match options.format {
+        Format::JsonLines => write_json_lines(&stations, &edges, out),
+        Format::Csv => write_csv(&stations, &edges, options, out),
+        _ => unreachable!("rejected while parsing options"),
+    })?;
+    Ok(skipped)
+}
+
+fn write_json_lines(
+    stations: &BTreeMap<&[u8], Vec<u32>>,
+    edges: &[f64],
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    for (name, counts) in stations {
+        let histogram = JsonHistogram {
+            name: &String::from_utf8_lossy(name),
+            edges,
+            counts,
+        };
+        serde_json::to_writer(&mut *out, &histogram)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+// A line per bin of each station
+fn write_csv(
+    stations: &BTreeMap<&[u8], Vec<u32>>,
+    edges: &[f64],
+    options: &Options,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let precision = options.precision;
+    writeln!(out, "station,from,to,count")?;
+    for (name, counts) in stations {
+        let name = String::from_utf8_lossy(name);
+        let name = output::csv_field(&name);
+        for (bounds, count) in edges.windows(2).zip(counts) {
+            let (from, to) = (bounds[0], bounds[1]);
+            writeln!(out, "{name},{from:.precision$},{to:.precision$},{count}")?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+//! What this build found on this machine, see `info`
+//!
+//! Numbers from different machines are only comparable with this next to
+//! them: the features the CPU has, the scanner picked for them and the
+//! defaults that follow from the topology.
+
+use std::thread;
+
+use onebrc::{options::Options, scan, Io, Sum};
+
+use crate::numa;
+
+pub fn print(options: &Options) {
+    let parallelism = thread::available_parallelism().map_or(1, usize::from);
+    let nodes = numa::nodes();
+    let page_size = page_size().map_or("unknown".to_string(), |size| size.to_string());
+
+    println!("version: {}", env!("CARGO_PKG_VERSION"));
+    println!(
+        "target: {}-{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    );
+    println!("cpu features: {}", list(&cpu_features()));
+    println!("compiled for: {}", list(&compiled_features()));
+    // Picked at runtime, the rest of the build only uses what it was
+    // compiled for
+    let scanner = options.scanner.unwrap_or_else(scan::detect);
+    println!("scanner: {}", scanner.name());
+    println!("table: {}", options.table.name());
+    println!("page size: {page_size}");
+    match nodes.len() {
+        0 => println!("numa nodes: none found"),
+        _ => println!("numa nodes: {} ({})", nodes.len(), nodes.join(", ")),
+    }
+    println!("available parallelism: {parallelism}");
+
+    // Defaults, or what the config file and environment chose
+    let threads = options.threads.unwrap_or(parallelism);
+    println!("threads: {threads}");
+    let io = match options.io {
+        Io::Mmap => "mmap",
+        Io::Read => "read",
+    };
+    println!("io: {io}");
+    let madvise = options.madvise.map_or("none".to_string(), |advice| {
+        format!("{advice:?}").to_lowercase()
+    });
+    println!("madvise: {madvise}");
+    println!("sum: {} bits", Sum::BITS);
+    println!("allocator: {}", allocator());
+    println!("build features: {}", list(&build_features()));
+}
+
+fn list(items: &[&str]) -> String {
+    match items {
+        [] => "none".to_string(),
+        items => items.join(" "),
+    }
+}
+
+// Features of the CPU that matter for scanning bytes
+pub fn cpu_features() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut features = Vec::new();
+    #[cfg(target_arch = "x86_64")]
+    {
+        macro_rules! detect {
+            ($($feature:tt),*) => {
+                $(if is_x86_feature_detected!($feature) {
+                    features.push($feature);
+                })*
+            };
+        }
+        detect!("sse2", "sse4.2", "popcnt", "bmi2", "avx", "avx2", "avx512f", "avx512bw");
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            features.push("neon");
+        }
+        if std::arch::is_aarch64_feature_detected!("sve") {
+            features.push("sve");
+        }
+    }
+    features
+}
+
+// Features the compiler was allowed to use everywhere, e.g. with
+// `RUSTFLAGS="-C target-cpu=native"`
+fn compiled_features() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut features = Vec::new();
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    macro_rules! compiled {
+        ($($feature:tt),*) => {
+            $(if cfg!(target_feature = $feature) {
+                features.push($feature);
+            })*
+        };
+    }
+    #[cfg(target_arch = "x86_64")]
+    compiled!("sse2", "sse4.2", "popcnt", "bmi2", "avx", "avx2", "avx512f", "avx512bw");
+    #[cfg(target_arch = "aarch64")]
+    compiled!("neon", "sve");
+    features
+}
+
+fn build_features() -> Vec<&'static str> {
+    macro_rules! enabled {
+        ($($feature:tt),*) => {
+            [$($feature),*]
+                .into_iter()
+                .zip([$(cfg!(feature = $feature)),*])
+                .filter_map(|(feature, enabled)| enabled.then_some(feature))
+                .collect()
+        };
+    }
+    enabled!(
+        "arrow",
+        "parquet",
+        "collate",
+        "sqlite",
+        "strict",
+        "wide-sum",
+        "gpu",
+        "unsafe-fast",
+        "hashbrown",
+        "alloc-check",
+        "mimalloc",
+        "jemalloc",
+        "zstd",
+        "gzip"
+    )
+}
+
+// The global allocator of this binary, see `main`
+fn allocator() -> &'static str {
+    if cfg!(feature = "alloc-check") {
+        "system, checked"
+    } else if cfg!(all(feature = "mimalloc", not(target_family = "wasm"))) {
+        "mimalloc"
+    } else if cfg!(all(feature = "jemalloc", not(target_family = "wasm"))) {
+        "jemalloc"
+    } else {
+        "system"
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn page_size() -> Option<usize> {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => Some(size as usize),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn page_size() -> Option<usize> {
+    None
+}
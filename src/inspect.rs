@@ -0,0 +1,190 @@
+//! Profile of a measurements file, see the `inspect` command
+//!
+//! A quick look before aggregating: blocks spread evenly over the file are
+//! read in full lines, a small file as a whole, and their rows counted,
+//! measured and checked like `validate` does. Counts for the whole file are
+//! extrapolated from the bytes sampled.
+
+use std::{fs::File, io};
+
+use onebrc::{data_start, options::Options};
+
+use crate::{load_input, validate};
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+// Blocks sampled, and bytes per block, files up to both are read whole
+const BLOCKS: usize = 64;
+const BLOCK: usize = 64 * 1024;
+
+// What the sampled rows look like
+#[derive(Default)]
+struct Profile {
+    bytes: usize,
+    rows: u64,
+    malformed: u64,
+    // Well formed rows by the length of their name, at most 100
+    name_lengths: Vec<u64>,
+    // Of well formed rows
+    min: f64,
+    max: f64,
+    unusual_decimals: u64,
+    not_utf8: u64,
+    control: u64,
+    crlf: u64,
+}
+
+impl Profile {
+    fn add(&mut self, line: &[u8], options: &Options) {
+        self.rows += 1;
+        let line = match line.strip_suffix(b"\r") {
+            Some(line) => {
+                self.crlf += 1;
+                line
+            }
+            None => line,
+        };
+        let Some(delimiter) = line.iter().position(|&c| c == options.delimiter) else {
+            self.malformed += 1;
+            return;
+        };
+        let (name, value) = (&line[..delimiter], &line[delimiter + 1..]);
+        // Counted whether or not the encoding takes them
+        if std::str::from_utf8(name).is_err() {
+            self.not_utf8 += 1;
+        }
+        if name.iter().any(u8::is_ascii_control) {
+            self.control += 1;
+        }
+        if validate::check(line, options).is_err() {
+            self.malformed += 1;
+            return;
+        }
+        self.name_lengths[name.len()] += 1;
+        let decimals = value.iter().position(|&c| c == b'.');
+        if decimals.map_or(0, |dot| value.len() - dot - 1) != 1 {
+            self.unusual_decimals += 1;
+        }
+        // Checked to be a number
+        let value = std::str::from_utf8(value).unwrap().parse::<f64>().unwrap();
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    // The shortest name length at least `fraction` of the names have
+    fn name_length(&self, fraction: f64) -> usize {
+        let names = self.name_lengths.iter().sum::<u64>();
+        let wanted = (names as f64 * fraction).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (len, &count) in self.name_lengths.iter().enumerate() {
+            seen += count;
+            if seen >= wanted {
+                return len;
+            }
+        }
+        self.name_lengths.len() - 1
+    }
+}
+
+pub fn run(options: &Options) -> io::Result<()> {
+    let input = load_input(&File::open(&options.file_name)?, options)?;
+    let start = data_start(&input, options);
+    let data = &input[start..];
+
+    let mut profile = Profile {
+        name_lengths: vec![0; 101],
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+        ..Profile::default()
+    };
+    let whole = data.len() <= BLOCKS * BLOCK;
+    let blocks = if whole { 1 } else { BLOCKS };
+    for block in 0..blocks {
+        let (from, to) = if whole {
+            (0, data.len())
+        } else {
+            let from = block * (data.len() - BLOCK) / (BLOCKS - 1);
+            (from, from + BLOCK)
+        };
+        // Whole lines only, from the first that starts in the block
+        let from = match from {
+            0 => 0,
+            _ => match data[from - 1..to].iter().position(|&c| c == b'\n') {
+                Some(i) => from + i,
+                None => continue,
+            },
+        };
+        let to = match data[from..to].iter().rposition(|&c| c == b'\n') {
+            Some(i) => from + i + 1,
+            None if to == data.len() => to,
+            None => continue,
+        };
+        let lines = &data[from..to];
+        profile.bytes += lines.len();
+        let lines = lines.strip_suffix(b"\n").unwrap_or(lines);
+        for line in lines.split(|&c| c == b'\n') {
+            let bare = line.strip_suffix(b"\r").unwrap_or(line);
+            if !(options.lenient && matches!(bare.first(), None | Some(b'#'))) {
+                profile.add(line, options);
+            }
+        }
+    }
+    print(&profile, &input[..start], input.len(), whole);
+    Ok(())
+}
+
+fn print(profile: &Profile, skipped: &[u8], len: usize, whole: bool) {
+    let rows = profile.rows.max(1) as f64;
+    if whole {
+        println!("file: {len} bytes, read whole");
+    } else {
+        println!(
+            "file: {len} bytes, {BLOCKS} blocks of {} KiB sampled",
+            BLOCK >> 10
+        );
+    }
+    let bytes_per_row = profile.bytes as f64 / rows;
+    let estimate = if whole {
+        format!("{}", profile.rows)
+    } else {
+        format!("about {:.0}", (len - skipped.len()) as f64 / bytes_per_row)
+    };
+    println!("rows: {estimate}, {bytes_per_row:.1} bytes each");
+    let bom = skipped.starts_with(UTF8_BOM);
+    let header = &skipped[if bom { UTF8_BOM.len() } else { 0 }..];
+    if !header.is_empty() {
+        let header = header.strip_suffix(b"\n").unwrap_or(header);
+        println!("header: {}", String::from_utf8_lossy(header).trim_end());
+    }
+    let named = profile.rows - profile.malformed;
+    if named > 0 {
+        println!(
+            "names: {} to {} bytes, median {}, 99th percentile {}",
+            profile.name_length(0.0),
+            profile.name_length(1.0),
+            profile.name_length(0.5),
+            profile.name_length(0.99)
+        );
+        println!(
+            "values: {:.2} to {:.2}, {} without exactly one decimal",
+            profile.min, profile.max, profile.unusual_decimals
+        );
+    }
+    println!(
+        "encoding: {}, {} names not UTF-8, {} with control characters, {} CRLF lines",
+        if bom {
+            "byte order mark"
+        } else {
+            "no byte order mark"
+        },
+        profile.not_utf8,
+        profile.control,
+        profile.crlf
+    );
+    println!(
+        "malformed: {} of {} rows, {:.2}%",
+        profile.malformed,
+        profile.rows,
+        profile.malformed as f64 / rows * 100.0
+    );
+}
@@ -0,0 +1,1758 @@
+//! One billion row challenge
+//!
+//! The aggregation engine: splits the input into chunks at line boundaries,
+//! aggregates each chunk on its own thread and merges the results.
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    hint, io, iter,
+    marker::PhantomData,
+    mem,
+    ops::{Deref, Range},
+    path::Path,
+    str,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self},
+    time::{Duration, Instant},
+};
+
+use aggregator::{Aggregator, MinMeanMax, Station};
+use arena::Arena;
+use hash_table::HashTable;
+use log::{debug, info, trace, warn};
+use name::Name;
+use options::{Columns, Dedup, Encoding, Header, Options, Period};
+use perfect::Perfect;
+use scan::{Scalar, Scan, SHORT};
+use serde::{Deserialize, Serialize};
+use table::{Backend, Table};
+
+pub mod aggregator;
+mod alloc_check;
+pub mod arena;
+mod config;
+mod estimate;
+pub mod ffi;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod hash_table;
+pub mod heap;
+pub mod memory;
+pub mod name;
+pub mod options;
+pub mod parts;
+pub mod perfect;
+mod pool;
+mod processor;
+#[cfg(feature = "python")]
+mod python;
+pub mod scan;
+pub mod sniff;
+mod spill;
+pub mod state;
+pub mod table;
+
+pub use processor::{process_iter, Processor, ProcessorBuilder};
+
+// Sum of all values in fixed point, see `Options::scale`
+#[cfg(not(feature = "wide-sum"))]
+pub type Sum = i64;
+#[cfg(feature = "wide-sum")]
+pub type Sum = i128;
+
+// Overflow is checked in debug builds, with the `strict` feature and in
+// strict runs, otherwise release builds wrap like plain integer arithmetic
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn accumulate(sum: Sum, value: Sum, strict: bool) -> Sum {
+    if strict || cfg!(any(debug_assertions, feature = "strict")) {
+        sum.checked_add(value)
+            .expect("sum overflowed, build with the `wide-sum` feature")
+    } else {
+        sum.wrapping_add(value)
+    }
+}
+
+// Counts of measurements are checked like sums, a station past `u32::MAX`
+// of them would otherwise wrap in release builds
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn tally(count: u32, more: u32, strict: bool) -> u32 {
+    if strict || cfg!(any(debug_assertions, feature = "strict")) {
+        count
+            .checked_add(more)
+            .expect("count overflowed, a station has more than u32::MAX measurements")
+    } else {
+        count.wrapping_add(more)
+    }
+}
+
+// Values are tenths of a degree within -99.9..=99.9, or hundredths of any
+// value below ten million with flexible values, so min and max are i32 and
+// the whole accumulator stays well within a cache line. The name borrows
+// from the mapped input unless it had to be transcoded, owned names up to
+// `name::INLINE` bytes are stored inline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StationStats<'a> {
+    #[serde(borrow)]
+    pub name: Name<'a>,
+    pub sum: Sum,
+    pub count: u32,
+    pub min: i32,
+    pub max: i32,
+}
+
+impl<'a> StationStats<'a> {
+    pub fn new(name: Name<'a>) -> Self {
+        Self {
+            name,
+            sum: 0,
+            count: 0,
+            min: i32::MAX,
+            max: i32::MIN,
+        }
+    }
+
+    pub fn update(&mut self, value: i32) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum = accumulate(self.sum, value as Sum, false);
+        self.count = tally(self.count, 1, false);
+    }
+
+    // Key of the name as stored, for tables of stations read back from
+    // saved or transcoded states that no longer have the raw input bytes
+    pub fn key(&self) -> u64 {
+        scan::key::<Scalar>(&self.name)
+    }
+
+    pub fn into_owned(self) -> StationStats<'static> {
+        StationStats {
+            name: self.name.into_owned(),
+            sum: self.sum,
+            count: self.count,
+            min: self.min,
+            max: self.max,
+        }
+    }
+
+    pub fn merge(&mut self, other: &StationStats) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum = accumulate(self.sum, other.sum, false);
+        self.count = tally(self.count, other.count, false);
+    }
+}
+
+impl<'a> From<Station<'a, MinMeanMax>> for StationStats<'a> {
+    fn from(station: Station<'a, MinMeanMax>) -> Self {
+        let MinMeanMax {
+            sum,
+            count,
+            min,
+            max,
+        } = station.stats;
+        Self {
+            name: station.name,
+            sum,
+            count,
+            min,
+            max,
+        }
+    }
+}
+
+// How the input file gets into memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Io {
+    // Map the file, where the platform supports that
+    Mmap,
+    // Read the whole file into a buffer
+    Read,
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        if cfg!(target_family = "wasm") {
+            Io::Read
+        } else {
+            Io::Mmap
+        }
+    }
+}
+
+impl Io {
+    pub fn name(self) -> &'static str {
+        match self {
+            Io::Mmap => "mmap",
+            Io::Read => "read",
+        }
+    }
+
+    // Pipes and other files that are not regular cannot be mapped, so they
+    // are read whatever `self` is, and neither can empty files
+    pub fn load(self, file: &File) -> io::Result<Input> {
+        let metadata = file.metadata()?;
+        match self {
+            #[cfg(not(target_family = "wasm"))]
+            Io::Mmap if metadata.is_file() && metadata.len() > 0 => {
+                unsafe { memmap::Mmap::map(file) }.map(Input::Mapped)
+            }
+            _ => {
+                let len = metadata.len() as usize;
+                memory::check(len, "the input")?;
+                let mut input = Vec::with_capacity(len);
+                io::Read::read_to_end(&mut &*file, &mut input)?;
+                // The length of a pipe is only known once it is read
+                memory::check(input.len().saturating_sub(len), "the input")?;
+                memory::allocate(input.len());
+                Ok(Input::Read(input))
+            }
+        }
+    }
+
+    // `len` bytes of the file from `offset`, see `aggregate_windowed`
+    pub fn load_range(self, file: &File, offset: u64, len: usize) -> io::Result<Input> {
+        match self {
+            #[cfg(not(target_family = "wasm"))]
+            Io::Mmap => unsafe { memmap::MmapOptions::new().offset(offset).len(len).map(file) }
+                .map(Input::Mapped),
+            _ => {
+                let mut input = pool::take(len, "a window")?;
+                let mut file = file;
+                io::Seek::seek(&mut file, io::SeekFrom::Start(offset))?;
+                if let Err(error) = io::Read::read_exact(&mut file, &mut input) {
+                    pool::give(input);
+                    return Err(error);
+                }
+                Ok(Input::Pooled(input))
+            }
+        }
+    }
+}
+
+// The whole input file in memory, see `Io`
+pub enum Input {
+    #[cfg(not(target_family = "wasm"))]
+    Mapped(memmap::Mmap),
+    Read(Vec<u8>),
+    // A window read into a buffer of the pool, see `pool`
+    Pooled(Vec<u8>),
+}
+
+impl Drop for Input {
+    fn drop(&mut self) {
+        match self {
+            Input::Read(read) => memory::free(read.len()),
+            Input::Pooled(buffer) => pool::give(mem::take(buffer)),
+            #[cfg(not(target_family = "wasm"))]
+            Input::Mapped(_) => {}
+        }
+    }
+}
+
+impl Deref for Input {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(not(target_family = "wasm"))]
+            Input::Mapped(mapped) => mapped,
+            Input::Read(read) | Input::Pooled(read) => read,
+        }
+    }
+}
+
+// How the kernel should expect a mapped input to be accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    Normal,
+    Sequential,
+    Random,
+    WillNeed,
+    HugePage,
+}
+
+impl Input {
+    // Only mapped inputs on Linux take advice, others ignore it
+    pub fn advise(&self, advice: Advice) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        if let Input::Mapped(mapped) = self {
+            let advice = match advice {
+                Advice::Normal => libc::MADV_NORMAL,
+                Advice::Sequential => libc::MADV_SEQUENTIAL,
+                Advice::Random => libc::MADV_RANDOM,
+                Advice::WillNeed => libc::MADV_WILLNEED,
+                Advice::HugePage => libc::MADV_HUGEPAGE,
+            };
+            let result = unsafe { libc::madvise(mapped.as_ptr() as *mut _, mapped.len(), advice) };
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = advice;
+        Ok(())
+    }
+}
+
+pub fn load(file: &File) -> io::Result<Input> {
+    Io::default().load(file)
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+// Returns the end of data if `char` does not occur, so a last line without
+// a trailing newline ends there
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn find_next(data: &[u8], position: usize, char: u8) -> usize {
+    Scalar::find(data, position, char)
+}
+
+// Bounds checked, unless built with `unsafe-fast`. That is only sound for
+// input that is well formed, e.g. every line has a delimiter.
+//
+// # Safety
+//
+// With `unsafe-fast` `range` must be within `data`
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+unsafe fn slice(data: &[u8], range: Range<usize>) -> &[u8] {
+    #[cfg(feature = "unsafe-fast")]
+    return unsafe { data.get_unchecked(range) };
+    #[cfg(not(feature = "unsafe-fast"))]
+    &data[range]
+}
+
+// See `slice`
+//
+// # Safety
+//
+// With `unsafe-fast` `index` must be within `data`
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+unsafe fn byte(data: &[u8], index: usize) -> u8 {
+    #[cfg(feature = "unsafe-fast")]
+    return unsafe { *data.get_unchecked(index) };
+    #[cfg(not(feature = "unsafe-fast"))]
+    data[index]
+}
+
+// Value of every ASCII digit, bytes that are not digits are never looked up
+const DIGITS: [i32; 256] = {
+    let mut digits = [0; 256];
+    let mut digit = 0;
+    while digit < 10 {
+        digits[b'0' as usize + digit] = digit as i32;
+        digit += 1;
+    }
+    digits
+};
+// Indexed with whether a value had a minus sign
+const SIGNS: [i32; 2] = [1, -1];
+
+// Lines a chunk aggregates between telling its prefetcher where it is
+const PUBLISH_EVERY: usize = 4096;
+// Cursor of a chunk that is done, so its prefetcher stops
+const DONE: usize = usize::MAX;
+// Pages are at least this large, touching more often would be wasted
+const PAGE_SIZE: usize = 4096;
+// Aggregating threads per merger, merging a table takes far less than
+// aggregating a chunk into it
+const THREADS_PER_MERGER: usize = 4;
+
+// Why `--lenient` skipped a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Malformed {
+    BadNumber,
+    MissingDelimiter,
+    OverlongName,
+}
+
+impl Malformed {
+    pub const ALL: [Malformed; 3] = [
+        Malformed::BadNumber,
+        Malformed::MissingDelimiter,
+        Malformed::OverlongName,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Malformed::BadNumber => "bad number",
+            Malformed::MissingDelimiter => "missing delimiter",
+            Malformed::OverlongName => "overlong name",
+        }
+    }
+}
+
+// Lines a run skipped, returned with its stations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Skipped {
+    // By `--lenient`, by why
+    pub malformed: [u64; 3],
+    // Found again by `--dedup`, whether skipped or counted. Each chunk only
+    // remembers its own lines, which are a share of a window with
+    // `--window`, so a line seen in another one is not found again.
+    pub duplicates: u64,
+}
+
+impl Skipped {
+    pub fn malformed(&self, why: Malformed) -> u64 {
+        self.malformed[why as usize]
+    }
+
+    fn add(&mut self, other: &Skipped) {
+        for (total, malformed) in self.malformed.iter_mut().zip(other.malformed) {
+            *total += malformed;
+        }
+        self.duplicates += other.duplicates;
+    }
+}
+
+// Why `line` of a name and a value is malformed, if it is. Empty names are
+// fine, they only make an odd station; values are numbers like `validate`
+// takes them.
+fn malformed_line(line: &[u8], options: &Options) -> Option<Malformed> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let Some(delimiter) = line.iter().position(|&c| c == options.delimiter) else {
+        return Some(Malformed::MissingDelimiter);
+    };
+    if delimiter > 100 {
+        return Some(Malformed::OverlongName);
+    }
+    (!is_value(&line[delimiter + 1..], options)).then_some(Malformed::BadNumber)
+}
+
+// Whether `value` is a number of the shape the parser takes
+fn is_value(value: &[u8], options: &Options) -> bool {
+    let digits = value.strip_prefix(b"-").unwrap_or(value);
+    let (whole, decimals) = match digits.iter().position(|&c| c == b'.') {
+        Some(dot) => (&digits[..dot], Some(&digits[dot + 1..])),
+        None => (digits, None),
+    };
+    let decimals_allowed = match (options.flexible_values, decimals) {
+        (true, None) => true,
+        (true, Some(decimals)) => (1..=2).contains(&decimals.len()),
+        (false, decimals) => decimals.is_some_and(|decimals| decimals.len() == 1),
+    };
+    let is_number = |digits: &[u8]| digits.iter().all(u8::is_ascii_digit);
+    // Flexible values of up to seven whole digits fit an i32 in hundredths
+    let whole_digits = if options.flexible_values { 7 } else { 2 };
+    (1..=whole_digits).contains(&whole.len())
+        && decimals_allowed
+        && is_number(whole)
+        && decimals.is_none_or(is_number)
+}
+
+// Past `--max-stations` a run fails instead of growing its tables without
+// bound, like past `--max-memory`
+fn check_stations(stations: usize, options: &Options) -> io::Result<()> {
+    match options.max_stations {
+        Some(max) if stations > max => Err(io::Error::other(format!(
+            "more than {max} distinct stations, past --max-stations; is the key column \
+             nearly unique? --spill <dir> aggregates them on disk"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// Keep the first reason a chunk fails and end it after the current line
+#[cold]
+fn fail(failed: &mut Option<io::Error>, end: &mut usize, error: io::Error) {
+    failed.get_or_insert(error);
+    *end = 0;
+}
+
+// With `--strict-utf8` a name that is not UTF-8 fails the run with its line,
+// `name` is a slice of it in `data`
+#[cold]
+fn not_utf8(name: &[u8], data: &[u8]) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "the station name `{}` is not UTF-8, in the line `{}`",
+            name.escape_ascii(),
+            line_of(name, data).escape_ascii()
+        ),
+    )
+}
+
+// Values of other formats than the challenge are checked before the parser,
+// which only takes its shape, `value` is a slice of the line in `data`
+#[cold]
+fn not_a_value(value: &[u8], data: &[u8]) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "the value `{}` is not a number like 12.3, in the line `{}`; --flexible-values \
+             takes others",
+            value.escape_ascii(),
+            line_of(value, data).escape_ascii()
+        ),
+    )
+}
+
+// The line in `data` that `field` is a slice of, without its line ending
+fn line_of<'a>(field: &[u8], data: &'a [u8]) -> &'a [u8] {
+    let start = field.as_ptr() as usize - data.as_ptr() as usize;
+    let line_start = data[..start]
+        .iter()
+        .rposition(|&c| c == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let line_end = find_next(data, start, b'\n');
+    let line = &data[line_start..line_end];
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+// Touch a byte of every page up to `distance` ahead of `cursor`, so the
+// page faults of a chunk are taken on this thread instead of its own
+fn prefetch(data: &[u8], cursor: &AtomicUsize, distance: usize) {
+    let mut next = cursor.load(Ordering::Relaxed);
+    while next < data.len() {
+        let position = cursor.load(Ordering::Relaxed);
+        if position == DONE {
+            return;
+        }
+        let ahead = position.saturating_add(distance).min(data.len());
+        if next >= ahead {
+            thread::sleep(Duration::from_micros(100));
+        }
+        while next < ahead {
+            hint::black_box(data[next]);
+            next += PAGE_SIZE;
+        }
+    }
+}
+
+struct Chunk<'a, A = MinMeanMax, T = HashTable<Station<'a, A>>> {
+    data: &'a [u8],
+    options: &'a Options,
+    end: usize,
+    position: usize,
+    // Lines aggregated so far
+    rows: u64,
+    // Lines whose key is that of another station, kept apart from it, and
+    // the same for stations of later chunks. Only counted with `--hash-stats`.
+    collisions: u64,
+    // Of the timestamp of the last line, with `--group-by` a period
+    period: &'a [u8],
+    // Lines so far, only kept with `--dedup`
+    seen: HashSet<&'a [u8]>,
+    skipped: Skipped,
+    // Inserted into `result`, see `--max-stations`
+    stations: usize,
+    // Why the chunk ended early, the run fails with it
+    failed: Option<io::Error>,
+    result: T,
+    aggregator: PhantomData<A>,
+}
+
+impl<'a, A: Aggregator, T: Backend<Station<'a, A>>> Chunk<'a, A, T> {
+    fn new(
+        data: &'a [u8],
+        options: &'a Options,
+        start: usize,
+        end: usize,
+        stations: Option<usize>,
+    ) -> Self {
+        Self {
+            data,
+            options,
+            end,
+            position: start,
+            rows: 0,
+            collisions: 0,
+            period: &[],
+            seen: HashSet::new(),
+            skipped: Skipped::default(),
+            stations: 0,
+            failed: None,
+            result: T::new(options, stations),
+            aggregator: PhantomData,
+        }
+    }
+
+    // Aggregate all lines, with `--debug-threads` report how that went
+    fn aggregate(&mut self, thread: usize) {
+        let (start, started) = (self.position, Instant::now());
+        match self.options.prefetch {
+            None => while self.parse_lines(usize::MAX) {},
+            Some(distance) => {
+                let cursor = AtomicUsize::new(self.position);
+                let data = &self.data[..self.end];
+                thread::scope(|scope| {
+                    scope.spawn(|| prefetch(data, &cursor, distance));
+                    // Publish the position every few lines, not on every one
+                    while self.parse_lines(PUBLISH_EVERY) {
+                        cursor.store(self.position, Ordering::Relaxed);
+                    }
+                    cursor.store(DONE, Ordering::Relaxed);
+                });
+            }
+        }
+        if self.options.debug_threads {
+            let malformed = match self.options.lenient {
+                true => format!(", {} malformed", self.skipped.malformed.iter().sum::<u64>()),
+                false => String::new(),
+            };
+            eprintln!(
+                "thread {thread}: {} rows, {} bytes, {:.3}s, {} stations{malformed}",
+                self.rows,
+                self.position - start,
+                started.elapsed().as_secs_f64(),
+                self.result.stats().len
+            );
+        }
+    }
+
+    // Aggregate up to `lines` lines with the widest scanner there is, or the
+    // one asked for, returns whether the chunk has more
+    fn parse_lines(&mut self, lines: usize) -> bool {
+        let scanner = self
+            .options
+            .scanner
+            .filter(|scanner| scanner.is_supported())
+            .unwrap_or_else(scan::detect);
+        match scanner {
+            #[cfg(target_arch = "x86_64")]
+            scan::Scanner::Avx512 => unsafe { self.parse_lines_avx512(lines) },
+            #[cfg(target_arch = "x86_64")]
+            scan::Scanner::Avx2 => unsafe { self.parse_lines_avx2(lines) },
+            #[cfg(target_arch = "aarch64")]
+            scan::Scanner::Neon => unsafe { self.parse_lines_neon(lines) },
+            _ => self.parse_lines_with::<Scalar>(lines),
+        }
+    }
+
+    // The whole loop is compiled for the features of the scanner
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn parse_lines_avx2(&mut self, lines: usize) -> bool {
+        self.parse_lines_with::<scan::Avx2>(lines)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2,avx512f,avx512bw")]
+    unsafe fn parse_lines_avx512(&mut self, lines: usize) -> bool {
+        self.parse_lines_with::<scan::Avx512>(lines)
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[target_feature(enable = "neon")]
+    unsafe fn parse_lines_neon(&mut self, lines: usize) -> bool {
+        self.parse_lines_with::<scan::Neon>(lines)
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn parse_lines_with<S: Scan>(&mut self, lines: usize) -> bool {
+        for _ in 0..lines {
+            if !self.parse_line::<S>() {
+                return false;
+            }
+        }
+        true
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn parse_line<S: Scan>(&mut self) -> bool {
+        let record = alloc_check::forbid("parsing a line", || self.parse_record::<S>());
+        if let Some((name, value)) = record {
+            self.insert::<S>(name, value);
+            self.rows += 1;
+        }
+        self.position < self.end
+    }
+
+    // Pass every record to `visitor` instead of aggregating
+    fn visit(&mut self, visitor: &mut impl FnMut(&[u8], i32)) -> io::Result<()> {
+        loop {
+            match self.parse_record::<Scalar>() {
+                Some((name, _)) if self.options.strict_utf8 && str::from_utf8(name).is_err() => {
+                    return Err(not_utf8(name, self.data));
+                }
+                Some((name, value)) if self.options.quotes && name.contains(&b'"') => {
+                    visitor(&unescape(name), value)
+                }
+                Some((name, value)) => visitor(name, value),
+                None => {}
+            }
+            if self.position >= self.end {
+                return self.failed.take().map_or(Ok(()), Err);
+            }
+        }
+    }
+
+    // Parse the next line into its raw name and value, `None` if it was
+    // skipped
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn parse_record<S: Scan>(&mut self) -> Option<(&'a [u8], i32)> {
+        let (name, value) = self.split_record::<S>()?;
+        let fields = self.options.columns.is_some() || self.options.quotes;
+        if fields && !self.options.flexible_values && !is_value(value, self.options) {
+            if self.options.lenient {
+                self.skipped.malformed[Malformed::BadNumber as usize] += 1;
+                return None;
+            }
+            let error = alloc_check::allow(|| not_a_value(value, self.data));
+            fail(&mut self.failed, &mut self.end, error);
+            return None;
+        }
+        let value = if self.options.flexible_values {
+            self.parse_flexible_value(value)
+        } else {
+            self.parse_value(value)
+        };
+        Some((name, value))
+    }
+
+    // Raw name and value fields of the next line, `None` if it was skipped
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn split_record<S: Scan>(&mut self) -> Option<(&'a [u8], &'a [u8])> {
+        if self.options.lenient && (self.skip_ignored_line() || self.skip_malformed_line()) {
+            return None;
+        }
+        if self.options.dedup.is_some() && self.skip_duplicate_line() {
+            return None;
+        }
+        let (name, value) = match self.options.columns {
+            None if !self.options.quotes => {
+                // Find next delimiter, names are 1 to 100 bytes and never contain one.
+                // Within the first 16 bytes for most, without a vector search.
+                let delimiter = self.options.delimiter;
+                let split_pos = match scan::words(self.data, self.position) {
+                    Some(words) => match scan::find_in_words(words, delimiter) {
+                        Some(i) => self.position + i,
+                        None => S::find(self.data, self.position + SHORT, delimiter),
+                    },
+                    None => S::find(self.data, self.position, delimiter),
+                };
+                // SAFETY: `find` returns at most the length of the data and
+                // never less than where it started
+                let name = unsafe { slice(self.data, self.position..split_pos) };
+                // Find next newline after the value
+                let line_end = S::find(self.data, split_pos + 1, b'\n');
+                self.position = line_end + 1;
+                // Drop the carriage return of CRLF endings without branching.
+                // SAFETY: a well formed line has a delimiter, so the newline
+                // is at least one byte after it and `line_end - 1` as well as
+                // the value are within the data
+                let value = unsafe {
+                    let value_end = line_end - (byte(self.data, line_end - 1) == b'\r') as usize;
+                    slice(self.data, split_pos + 1..value_end)
+                };
+                (name, value)
+            }
+            columns => match self.split_columns(columns.unwrap_or_default()) {
+                Ok(fields) => fields,
+                // Also an unterminated quote, which takes in the rest of the line
+                Err(_) if self.options.lenient => {
+                    self.skipped.malformed[Malformed::MissingDelimiter as usize] += 1;
+                    return None;
+                }
+                Err(error) => {
+                    fail(&mut self.failed, &mut self.end, error);
+                    return None;
+                }
+            },
+        };
+        Some((name, value))
+    }
+
+    // Names are slices of the data, so a short one is hashed from the words
+    // at its start, which `split_record` just loaded
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn key<S: Scan>(&self, name: &[u8]) -> u64 {
+        if name.len() < SHORT {
+            let start = name.as_ptr() as usize - self.data.as_ptr() as usize;
+            if let Some(words) = scan::words(self.data, start) {
+                return scan::short_key(words, name.len());
+            }
+        }
+        scan::key::<S>(name)
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn insert<S: Scan>(&mut self, name: &'a [u8], value: i32) {
+        if self.options.group_by.is_some() {
+            return self.insert_grouped::<S>(name, value);
+        }
+        // Update or insert new result
+        let key = self.key::<S>(name);
+        let (data, options) = (self.data, self.options);
+        let (collisions, stations) = (&mut self.collisions, &mut self.stations);
+        let (failed, end) = (&mut self.failed, &mut self.end);
+        self.result.insert_or_update(
+            key,
+            |station: &Station<A>| {
+                let same = is_station_name(&station.name, name, options);
+                *collisions += (options.hash_stats && !same) as u64;
+                same
+            },
+            // Only a resize of the table may allocate
+            |station: &mut Station<A>| {
+                alloc_check::forbid("updating a station", || {
+                    station.stats.update(value, options)
+                })
+            },
+            || {
+                *stations += 1;
+                // Tables and arenas only grow for new stations
+                if let Err(error) = check_stations(*stations, options).and(memory::check_used()) {
+                    fail(failed, end, error);
+                }
+                if options.strict_utf8 && str::from_utf8(name).is_err() {
+                    fail(failed, end, not_utf8(name, data));
+                }
+                alloc_check::forbid("inserting a station", || {
+                    let mut stats = A::new();
+                    stats.update(value, options);
+                    Station {
+                        name: station_name(name, options),
+                        stats,
+                    }
+                })
+            },
+        );
+    }
+
+    // Like `insert`, for the station in the period of the line
+    #[inline(never)]
+    fn insert_grouped<S: Scan>(&mut self, name: &'a [u8], value: i32) {
+        let period = self.period;
+        let key = scan::key::<S>(name) ^ scan::mix(scan::key::<S>(period));
+        let (data, options) = (self.data, self.options);
+        let (collisions, stations) = (&mut self.collisions, &mut self.stations);
+        let (failed, end) = (&mut self.failed, &mut self.end);
+        // Names of groups are made, which allocates like a transcoded name
+        self.result.insert_or_update(
+            key,
+            |station: &Station<A>| {
+                let same = station
+                    .name
+                    .strip_suffix(period)
+                    .and_then(|name| name.strip_suffix(b"@"))
+                    .is_some_and(|station| is_station_name(station, name, options));
+                *collisions += (options.hash_stats && !same) as u64;
+                same
+            },
+            |station: &mut Station<A>| {
+                alloc_check::forbid("updating a station", || {
+                    station.stats.update(value, options)
+                })
+            },
+            || {
+                *stations += 1;
+                // Tables and arenas only grow for new stations
+                if let Err(error) = check_stations(*stations, options).and(memory::check_used()) {
+                    fail(failed, end, error);
+                }
+                if options.strict_utf8 && str::from_utf8(name).is_err() {
+                    fail(failed, end, not_utf8(name, data));
+                }
+                alloc_check::forbid("inserting a station", || {
+                    let mut stats = A::new();
+                    stats.update(value, options);
+                    Station {
+                        name: alloc_check::allow(|| group_name(name, period, options)),
+                        stats,
+                    }
+                })
+            },
+        );
+    }
+
+    // Name and value of a line with any number of columns
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn split_columns(&mut self, columns: Columns) -> io::Result<(&'a [u8], &'a [u8])> {
+        let line_end = find_next(self.data, self.position, b'\n');
+        let value_end = line_end - (self.data[line_end - 1] == b'\r') as usize;
+        let line = &self.data[self.position..value_end];
+        self.position = line_end + 1;
+
+        let (mut name, mut value) = (None, None);
+        let time = columns.time.filter(|_| self.options.group_by.is_some());
+        let last = columns.key.max(columns.value).max(time.unwrap_or(0));
+        for (column, field) in Fields::new(line, self.options).enumerate().take(last + 1) {
+            if column == columns.key {
+                name = Some(field);
+            } else if column == columns.value {
+                value = Some(field);
+            } else if Some(column) == time {
+                let prefix = self.options.group_by.map_or(0, Period::prefix);
+                self.period = &field[..prefix.min(field.len())];
+            }
+        }
+        name.zip(value).ok_or_else(|| {
+            alloc_check::allow(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "the line `{}` has fewer columns than --key-col and --value-col",
+                        line.escape_ascii()
+                    ),
+                )
+            })
+        })
+    }
+
+    // Remember the next line, and skip it if it was seen before and
+    // `--dedup skip`, returns whether it was skipped
+    #[inline(never)]
+    fn skip_duplicate_line(&mut self) -> bool {
+        let line_end = find_next(self.data, self.position, b'\n');
+        let line = &self.data[self.position..line_end];
+        // The set grows with the distinct lines
+        if alloc_check::allow(|| self.seen.insert(line)) {
+            return false;
+        }
+        self.skipped.duplicates += 1;
+        if self.options.dedup == Some(Dedup::Count) {
+            return false;
+        }
+        self.position = line_end + 1;
+        true
+    }
+
+    // Skip the next line and count why if it is malformed, returns whether
+    // it was. Only lines of a name and a value are checked, fields of other
+    // formats are not where the fast path would trip over them.
+    #[inline(never)]
+    fn skip_malformed_line(&mut self) -> bool {
+        if self.options.columns.is_some() || self.options.quotes {
+            return false;
+        }
+        let line_end = find_next(self.data, self.position, b'\n');
+        let line = &self.data[self.position..line_end];
+        let Some(why) = malformed_line(line, self.options) else {
+            return false;
+        };
+        self.skipped.malformed[why as usize] += 1;
+        self.position = line_end + 1;
+        true
+    }
+
+    // Skip a blank or `#` comment line, returns whether one was skipped
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn skip_ignored_line(&mut self) -> bool {
+        match self.data[self.position] {
+            b'\n' | b'\r' | b'#' => {
+                self.position = find_next(self.data, self.position, b'\n') + 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Accepts `d.d` and `dd.d` with an optional minus sign, the only values of
+    // the challenge, and returns tenths. Positions are selected with
+    // arithmetic instead of per digit branches, so mixed lengths in the input
+    // do not cost mispredictions.
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn parse_value(&self, data: &[u8]) -> i32 {
+        // SAFETY: well formed values are 3 to 5 bytes, so there is a digit
+        // before the dot and one after it
+        unsafe {
+            let neg = (byte(data, 0) == b'-') as usize;
+            let digits = slice(data, neg..data.len());
+            // 1 for `dd.d`, then the leading digit counts as tens
+            let long = (digits.len() == 4) as usize;
+            let tens = DIGITS[byte(digits, 0) as usize] * long as i32;
+            let ones = DIGITS[byte(digits, long) as usize];
+            let tenths = DIGITS[byte(digits, digits.len() - 1) as usize];
+            (tens * 100 + ones * 10 + tenths) * SIGNS[neg]
+        }
+    }
+
+    // Accepts `12`, `12.3` and `12.34` and returns hundredths
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn parse_flexible_value(&self, data: &[u8]) -> i32 {
+        let neg = data[0] == b'-';
+        let mut result: i32 = 0;
+        let mut decimals = None;
+        for &digit in &data[neg as usize..] {
+            match (digit, decimals) {
+                (b'.', None) => decimals = Some(0),
+                // Digits past the hundredths are truncated
+                (_, Some(2)) => break,
+                (_, _) => {
+                    result = result * 10 + (digit - b'0') as i32;
+                    decimals = decimals.map(|decimals| decimals + 1);
+                }
+            }
+        }
+        result *= match decimals {
+            None | Some(0) => 100,
+            Some(1) => 10,
+            _ => 1,
+        };
+        if neg {
+            -result
+        } else {
+            result
+        }
+    }
+}
+
+// Offset of the first line of data, after the byte order mark and the
+// header, if any
+pub fn data_start(data: &[u8], options: &Options) -> usize {
+    let start = if data.starts_with(UTF8_BOM) {
+        UTF8_BOM.len()
+    } else {
+        0
+    };
+    let line_end = find_next(data, start, b'\n');
+    let header = match options.header {
+        Header::Present => true,
+        Header::Absent => false,
+        Header::Auto => is_header(&data[start..line_end], options),
+    };
+    if header {
+        debug!(
+            "skipping the header `{}`",
+            String::from_utf8_lossy(&data[start..line_end])
+        );
+        (line_end + 1).min(data.len())
+    } else {
+        start
+    }
+}
+
+// Values of data always start with a digit or a minus sign
+fn is_header(line: &[u8], options: &Options) -> bool {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let column = options.columns.map_or(1, |columns| columns.value);
+    let value = Fields::new(line, options).nth(column).unwrap_or_default();
+    let digits = value.strip_prefix(b"-").unwrap_or(value);
+    !digits.first().is_some_and(u8::is_ascii_digit)
+}
+
+// Fields of a line split at the delimiter. With quotes, one in double quotes
+// may contain the delimiter and `""` for a quote, it is yielded without its
+// quotes but still escaped. Lines themselves never span newlines.
+struct Fields<'a> {
+    rest: Option<&'a [u8]>,
+    delimiter: u8,
+    quotes: bool,
+}
+
+impl<'a> Fields<'a> {
+    fn new(line: &'a [u8], options: &Options) -> Self {
+        Self {
+            rest: Some(line),
+            delimiter: options.delimiter,
+            quotes: options.quotes,
+        }
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = &'a [u8];
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let rest = self.rest?;
+        if self.quotes && rest.first() == Some(&b'"') {
+            // The closing quote is the first one that is not doubled, an
+            // unterminated field runs to the end of the line
+            let mut from = 1;
+            let end = loop {
+                match rest[from..].iter().position(|&c| c == b'"') {
+                    Some(i) if rest.get(from + i + 1) == Some(&b'"') => from += i + 2,
+                    Some(i) => break from + i,
+                    None => break rest.len(),
+                }
+            };
+            // Anything between the closing quote and the delimiter is dropped
+            self.rest = rest[end..]
+                .iter()
+                .position(|&c| c == self.delimiter)
+                .map(|i| &rest[end + i + 1..]);
+            return Some(&rest[1..end]);
+        }
+        match rest.iter().position(|&c| c == self.delimiter) {
+            Some(i) => {
+                self.rest = Some(&rest[i + 1..]);
+                Some(&rest[..i])
+            }
+            None => {
+                self.rest = None;
+                Some(rest)
+            }
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn station_name<'a>(name: &'a [u8], options: &Options) -> Name<'a> {
+    if options.quotes && name.contains(&b'"') {
+        // Made rather than borrowed, like a transcoded name
+        alloc_check::allow(|| Name::from(options.encoding.decode(&unescape(name)).into_owned()))
+    } else {
+        options.encoding.decode(name).into()
+    }
+}
+
+// Whether `stored` is the station name of the raw `name`, without making
+// that name where it is the raw one
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn is_station_name(stored: &[u8], name: &[u8], options: &Options) -> bool {
+    let quoted = options.quotes && name.contains(&b'"');
+    if !quoted && (options.encoding == Encoding::Utf8 || name.is_ascii()) {
+        return same_bytes(stored, name);
+    }
+    alloc_check::allow(|| stored == &*station_name(name, options))
+}
+
+// `stored == name` with a word of each end for names up to 16 bytes, the
+// ends overlap for shorter ones, instead of a call to compare memory on
+// every line
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn same_bytes(stored: &[u8], name: &[u8]) -> bool {
+    let len = name.len();
+    if stored.len() != len {
+        return false;
+    }
+    let word = |bytes: &[u8], at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+    let half = |bytes: &[u8], at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    match len {
+        8..=16 => word(stored, 0) == word(name, 0) && word(stored, len - 8) == word(name, len - 8),
+        4..=7 => half(stored, 0) == half(name, 0) && half(stored, len - 4) == half(name, len - 4),
+        _ => stored == name,
+    }
+}
+
+// Name of a station in a period of `--group-by`, like `Hamburg@2024-03`
+fn group_name(name: &[u8], period: &[u8], options: &Options) -> Name<'static> {
+    let mut group = station_name(name, options).to_vec();
+    group.push(b'@');
+    group.extend_from_slice(period);
+    Name::from(group)
+}
+
+// Undo the `""` escapes of a quoted field
+fn unescape(field: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(field.len());
+    let mut quote = false;
+    for &byte in field {
+        // Keep every other quote
+        if byte == b'"' {
+            quote = !quote;
+            if !quote {
+                continue;
+            }
+        }
+        unescaped.push(byte);
+    }
+    unescaped
+}
+
+// Split `data` into about `parts` ranges of whole lines, each including its
+// final newline. The first one starts at `data_start`.
+pub fn line_ranges(data: &[u8], parts: usize, options: &Options) -> Vec<Range<usize>> {
+    let chunk_size = data.len() / parts.max(1);
+    let mut ranges = Vec::new();
+    let mut next_start = data_start(data, options);
+    while next_start < data.len() {
+        let next_end = find_next(data, next_start + chunk_size, b'\n');
+        ranges.push(next_start..(next_end + 1).min(data.len()));
+        next_start = next_end + 1;
+    }
+    ranges
+}
+
+// Chunks of `data` for parallel work, their tables sized for `stations` if
+// they are known
+fn chunks<'a, A: Aggregator, T: Backend<Station<'a, A>>>(
+    data: &'a [u8],
+    options: &'a Options,
+    stations: Option<usize>,
+) -> Vec<Chunk<'a, A, T>> {
+    let ranges = line_ranges(data, thread_count(options), options);
+    debug!("{} bytes in {} chunks", data.len(), ranges.len());
+    for (i, range) in ranges.iter().enumerate() {
+        trace!("chunk {i}: bytes {range:?}");
+    }
+    // A chunk ends at the newline of its last line
+    ranges
+        .into_iter()
+        .map(|range| Chunk::new(data, options, range.start, range.end - 1, stations))
+        .collect()
+}
+
+// Within `--max-memory` the table of each thread starts with room for at
+// most the stations of its share of half of it, the rest is left for the
+// input and the merged tables
+fn budgeted_stations<T>(options: &Options) -> Option<usize> {
+    let share = options.max_memory? / 2 / thread_count(options);
+    let stations = HashTable::<T>::stations_within(share);
+    Some(stations.min(hash_table::CAPACITY * 7 / 8))
+}
+
+// Threads to aggregate on, one per core by default
+fn thread_count(options: &Options) -> usize {
+    options
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, usize::from))
+}
+
+// Call `visitor` with the raw name and the value of every line in order,
+// values are fixed point like sums, see `Options::scale`
+pub fn visit(
+    data: &[u8],
+    options: &Options,
+    mut visitor: impl FnMut(&[u8], i32),
+) -> io::Result<()> {
+    let options = Options {
+        threads: Some(1),
+        ..options.clone()
+    };
+    for mut chunk in chunks::<MinMeanMax, HashTable<_>>(data, &options, None) {
+        chunk.visit(&mut visitor)?;
+    }
+    Ok(())
+}
+
+// Like `visit`, but chunks are visited in parallel, each with its own state
+// from `init`. Returns the states in the order of their chunks so they can
+// be combined.
+pub fn visit_parallel<S: Send>(
+    data: &[u8],
+    options: &Options,
+    init: impl Fn() -> S + Sync,
+    visitor: impl Fn(&mut S, &[u8], i32) + Sync,
+) -> io::Result<Vec<S>> {
+    let (init, visitor) = (&init, &visitor);
+    thread::scope(|scope| {
+        let handles = chunks::<MinMeanMax, HashTable<_>>(data, options, None)
+            .into_iter()
+            .map(|mut chunk| {
+                scope.spawn(move || {
+                    let mut state = init();
+                    chunk.visit(&mut |name, value| visitor(&mut state, name, value))?;
+                    Ok(state)
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+// Number of newlines in `data`, counted in parallel like lines are
+// aggregated but without parsing them
+pub fn count_lines(data: &[u8], options: &Options) -> u64 {
+    let threads = thread_count(options);
+    let part = data.len().div_ceil(threads.max(1)).max(1);
+    thread::scope(|scope| {
+        let handles = data
+            .chunks(part)
+            .map(|part| scope.spawn(move || part.iter().filter(|&&c| c == b'\n').count() as u64))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum()
+    })
+}
+
+// Number of distinct station names, values are not even parsed
+pub fn count_stations(data: &[u8], options: &Options) -> io::Result<usize> {
+    // Names borrow from the input
+    let tables = thread::scope(|scope| {
+        let handles = chunks::<MinMeanMax, HashTable<_>>(data, options, None)
+            .into_iter()
+            .map(|mut chunk| {
+                scope.spawn(move || {
+                    let mut names = HashTable::new();
+                    while chunk.position < chunk.end {
+                        if let Some((name, _)) = chunk.split_record::<Scalar>() {
+                            let key = scan::key::<Scalar>(name);
+                            names.insert_or_update(key, |&known| known == name, |_| {}, || name);
+                        }
+                    }
+                    chunk.failed.map_or(Ok(names), Err)
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<io::Result<Vec<_>>>()
+    })?;
+    let mut names = HashTable::new();
+    for table in tables {
+        for (key, name) in table {
+            names.insert_or_update(key, |&known| known == name, |_| {}, || name);
+        }
+    }
+    Ok(names.len())
+}
+
+// Aggregate all lines of `data`, names borrow from it. The lines skipped
+// are only counted by `aggregate_with`.
+pub fn aggregate<'a>(
+    data: &'a [u8],
+    options: &'a Options,
+) -> io::Result<HashTable<StationStats<'a>>> {
+    let (result, _) = aggregate_with::<MinMeanMax>(data, options)?;
+    Ok(result
+        .into_iter()
+        .map(|(key, station)| (key, station.into()))
+        .collect())
+}
+
+// Aggregate all lines of `data` with the tables of threads written to files
+// under `dir` whenever they outgrow their share of `--max-memory`, for more
+// stations than the tables of all threads can hold, see `spill`
+pub fn aggregate_spilled(
+    data: &[u8],
+    dir: &Path,
+    options: &Options,
+) -> io::Result<(HashTable<StationStats<'static>>, Skipped)> {
+    spill::aggregate(data, dir, options)
+}
+
+// Aggregate the file `window` bytes at a time, each window is only in memory
+// while its lines are aggregated and the next one is loaded. Every thread
+// keeps its stations across windows, their names copied into its arena, so
+// files larger than the address space work too, e.g. on 32-bit targets, and
+// so do pipes, which are read one window after the other.
+pub fn aggregate_windowed(
+    file: &File,
+    window: usize,
+    options: &Options,
+) -> io::Result<(HashTable<StationStats<'static>>, Skipped)> {
+    let metadata = file.metadata()?;
+    // Lines after the first window are all data
+    let rest = Options {
+        header: Header::Absent,
+        ..options.clone()
+    };
+    let windows: Box<dyn Iterator<Item = _>> = if metadata.is_file() {
+        Box::new(windows(file, metadata.len(), window, options, &rest))
+    } else {
+        Box::new(streamed_windows(file, window, options, &rest))
+    };
+    let mut error = None;
+    let windows = windows.map_while(|window| {
+        window
+            .map_err(|window_error| error = Some(window_error))
+            .ok()
+    });
+    let threads = thread_count(options);
+
+    let mut result = HashTable::new();
+    let (mut collisions, mut skipped) = (0, Skipped::default());
+    let mut merge = |aggregated: io::Result<(HashTable<StationStats<'static>>, u64, Skipped)>| {
+        let (stations, worker_collisions, worker_skipped) = aggregated?;
+        collisions += worker_collisions;
+        skipped.add(&worker_skipped);
+        for (key, stats) in stations {
+            result.insert_or_update(
+                key,
+                |merged: &StationStats| merged.name == stats.name,
+                |merged| merged.merge(&stats),
+                || stats.clone(),
+            );
+        }
+        io::Result::Ok(())
+    };
+    // Without threads, e.g. on WASI, windows are aggregated in place
+    if threads == 1 {
+        merge(aggregate_windows(
+            0,
+            windows.flat_map(|(input, len, options)| {
+                line_ranges(&input[..len], 1, options)
+                    .into_iter()
+                    .map(move |lines| Window {
+                        input: Arc::clone(&input),
+                        len,
+                        lines,
+                        options,
+                    })
+            }),
+        ))?;
+    } else {
+        thread::scope(|scope| {
+            let (senders, workers): (Vec<_>, Vec<_>) = (0..threads)
+                .map(|thread| {
+                    let (sender, windows) = mpsc::sync_channel(1);
+                    (
+                        sender,
+                        scope.spawn(move || aggregate_windows(thread, windows)),
+                    )
+                })
+                .unzip();
+            for (input, len, options) in windows {
+                for (sender, lines) in
+                    senders
+                        .iter()
+                        .zip(line_ranges(&input[..len], threads, options))
+                {
+                    let window = Window {
+                        input: Arc::clone(&input),
+                        len,
+                        lines,
+                        options,
+                    };
+                    // A worker that failed stops taking windows
+                    let _ = sender.send(window);
+                }
+            }
+            drop(senders);
+            workers
+                .into_iter()
+                .try_for_each(|worker| merge(worker.join().unwrap()))
+        })?;
+    }
+    if let Some(error) = error {
+        return Err(error);
+    }
+    check_stations(result.len(), options)?;
+    memory::check_used()?;
+    if options.hash_stats {
+        report_hash_stats(result.stats(), collisions);
+    }
+    Ok((result, skipped))
+}
+
+// The windows of the file with the options for their lines, each cut after
+// its last complete line
+fn windows<'a>(
+    file: &'a File,
+    len: u64,
+    window: usize,
+    options: &'a Options,
+    rest: &'a Options,
+) -> impl Iterator<Item = io::Result<(Arc<Input>, usize, &'a Options)>> + 'a {
+    let mut offset = 0;
+    iter::from_fn(move || {
+        if offset >= len {
+            return None;
+        }
+        let size = (len - offset).min(window as u64) as usize;
+        let input = match options.io.load_range(file, offset, size) {
+            Ok(input) => input,
+            Err(error) => {
+                offset = len;
+                return Some(Err(error));
+            }
+        };
+        if let Some(advice) = options.madvise {
+            if let Err(error) = input.advise(advice) {
+                warn!("ignoring --madvise: {error}");
+            }
+        }
+        // A partial last line is left for the next window
+        let end = if offset + size as u64 == len {
+            size
+        } else {
+            match input.iter().rposition(|&c| c == b'\n') {
+                Some(i) => i + 1,
+                None => {
+                    let error = io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("a line at byte {offset} is longer than the window"),
+                    );
+                    offset = len;
+                    return Some(Err(error));
+                }
+            }
+        };
+        debug!("window: bytes {offset}..{}", offset + end as u64);
+        let window_options = if offset == 0 { options } else { rest };
+        offset += end as u64;
+        Some(Ok((Arc::new(input), end, window_options)))
+    })
+}
+
+// The windows of a pipe or another file that cannot be mapped or read at an
+// offset, read one after the other. The partial last line of a window is
+// carried over to the start of the next one.
+fn streamed_windows<'a>(
+    file: &'a File,
+    window: usize,
+    options: &'a Options,
+    rest: &'a Options,
+) -> impl Iterator<Item = io::Result<(Arc<Input>, usize, &'a Options)>> + 'a {
+    let (mut offset, mut carry, mut done) = (0, Vec::new(), false);
+    iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let mut input = match pool::take(window, "a window") {
+            Ok(input) => input,
+            Err(error) => {
+                done = true;
+                return Some(Err(error));
+            }
+        };
+        input[..carry.len()].copy_from_slice(&carry);
+        let mut filled = carry.len();
+        while filled < window {
+            match io::Read::read(&mut &*file, &mut input[filled..]) {
+                Ok(0) => {
+                    done = true;
+                    break;
+                }
+                Ok(read) => filled += read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => {
+                    done = true;
+                    pool::give(input);
+                    return Some(Err(error));
+                }
+            }
+        }
+        let end = if done {
+            filled
+        } else {
+            match input[..filled].iter().rposition(|&c| c == b'\n') {
+                Some(i) => i + 1,
+                None => {
+                    done = true;
+                    pool::give(input);
+                    return Some(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("a line at byte {offset} is longer than the window"),
+                    )));
+                }
+            }
+        };
+        carry.clear();
+        carry.extend_from_slice(&input[end..filled]);
+        if end == 0 {
+            pool::give(input);
+            return None;
+        }
+        debug!("window: bytes {offset}..{}", offset + end as u64);
+        let window_options = if offset == 0 { options } else { rest };
+        offset += end as u64;
+        Some(Ok((Arc::new(Input::Pooled(input)), end, window_options)))
+    })
+}
+
+// Lines of a window for one thread of `aggregate_windowed`
+struct Window<'a> {
+    input: Arc<Input>,
+    // Up to the newline of its last complete line
+    len: usize,
+    lines: Range<usize>,
+    options: &'a Options,
+}
+
+// The stations of all windows one thread got, its collisions and the lines
+// it skipped
+fn aggregate_windows<'a>(
+    thread: usize,
+    windows: impl IntoIterator<Item = Window<'a>>,
+) -> io::Result<(HashTable<StationStats<'static>>, u64, Skipped)> {
+    let arena = Arena::new();
+    let mut stations = None;
+    let (mut collisions, mut skipped) = (0, Skipped::default());
+    for window in windows {
+        let data = &window.input[..window.len];
+        let (start, end) = (window.lines.start, window.lines.end - 1);
+        let budgeted = budgeted_stations::<Station<MinMeanMax>>(window.options);
+        let stations = stations
+            .get_or_insert_with(|| budgeted.map_or_else(HashTable::new, HashTable::for_stations));
+        let mut chunk = Chunk::<MinMeanMax>::new(data, window.options, start, end, budgeted);
+        chunk.aggregate(thread);
+        if let Some(error) = chunk.failed {
+            return Err(error);
+        }
+        collisions += chunk.collisions;
+        skipped.add(&chunk.skipped);
+        for (key, station) in chunk.result {
+            let stats = StationStats::from(station);
+            stations.insert_or_update(
+                key,
+                |merged: &StationStats| merged.name == stats.name,
+                |merged| merged.merge(&stats),
+                || {
+                    let mut interned = StationStats::new(Name::borrowed(arena.alloc(&stats.name)));
+                    interned.merge(&stats);
+                    interned
+                },
+            );
+        }
+        check_stations(stations.len(), window.options)?;
+        memory::check_used()?;
+    }
+    debug!("thread {thread}: {} bytes of names", arena.allocated());
+    let stations = stations
+        .into_iter()
+        .flatten()
+        .map(|(key, stats)| (key, stats.into_owned()))
+        .collect();
+    Ok((stations, collisions, skipped))
+}
+
+// Aggregate all lines of `data` with custom statistics, and count the lines
+// skipped
+pub fn aggregate_with<'a, A: Aggregator>(
+    data: &'a [u8],
+    options: &'a Options,
+) -> io::Result<(HashTable<Station<'a, A>>, Skipped)> {
+    if options.station_list.is_some() {
+        return aggregate_into::<A, Perfect<_>>(data, options);
+    }
+    if options.reserved.is_some() {
+        return aggregate_into::<A, table::Reserved<_>>(data, options);
+    }
+    match options.table {
+        Table::Robin => aggregate_into::<A, HashTable<_>>(data, options),
+        Table::Std => aggregate_into::<A, std::collections::HashMap<_, Vec<_>>>(data, options),
+        Table::Sharded => aggregate_into::<A, table::Sharded<_>>(data, options),
+        #[cfg(feature = "hashbrown")]
+        Table::Hashbrown => aggregate_into::<A, table::Hashbrown<_>>(data, options),
+        #[cfg(not(feature = "hashbrown"))]
+        Table::Hashbrown => unreachable!("rejected while parsing options"),
+    }
+}
+
+// Aggregate with chunks of the table `T`, merged into the own table
+fn aggregate_into<'a, A: Aggregator, T: Backend<Station<'a, A>>>(
+    data: &'a [u8],
+    options: &'a Options,
+) -> io::Result<(HashTable<Station<'a, A>>, Skipped)> {
+    heap::phase("aggregate");
+    // HyperLogLog is off by a few percent, so leave some room
+    let stations = options
+        .estimate
+        .then(|| estimate::stations(data, options))
+        .map(|stations| stations + stations / 16)
+        .or_else(|| budgeted_stations::<Station<A>>(options));
+    let mut chunks = chunks::<A, T>(data, options, stations);
+
+    // A single chunk, e.g. without threads on WASI, is aggregated in place
+    if chunks.len() == 1 {
+        info!("aggregating on the calling thread");
+        let mut chunk = chunks.pop().unwrap();
+        chunk.aggregate(0);
+        if let Some(error) = chunk.failed {
+            return Err(error);
+        }
+        if options.hash_stats {
+            report_hash_stats(chunk.result.stats(), chunk.collisions);
+        }
+        return Ok((chunk.result.into_table(), chunk.skipped));
+    }
+
+    // Each merger owns the stations of a shard, so chunks that are done merge
+    // at once instead of waiting for a single table
+    let shards = chunks.len().div_ceil(THREADS_PER_MERGER);
+    info!(
+        "aggregating on {} threads, merging into {shards} shards",
+        chunks.len()
+    );
+    let shard_stations = stations.map(|stations| stations.div_ceil(shards));
+    let collisions = AtomicU64::new(0);
+    // Start threads for each chunk and shard, the scope awaits all of them
+    let (tables, skipped) = thread::scope(|scope| {
+        let collisions = &collisions;
+        let (senders, mergers): (Vec<_>, Vec<_>) = (0..shards)
+            .map(|_| {
+                let (sender, batches) = mpsc::channel();
+                let merger =
+                    scope.spawn(move || merge_shard(batches, shard_stations, collisions, options));
+                (sender, merger)
+            })
+            .unzip();
+        let workers = chunks
+            .into_iter()
+            .enumerate()
+            .map(|(thread, mut chunk)| {
+                let senders = senders.clone();
+                scope.spawn(move || {
+                    chunk.aggregate(thread);
+                    if let Some(error) = chunk.failed {
+                        return Err(error);
+                    }
+                    collisions.fetch_add(chunk.collisions, Ordering::Relaxed);
+
+                    let mut batches = iter::repeat_with(Vec::new).take(shards).collect::<Vec<_>>();
+                    for (key, station) in chunk.result.into_table() {
+                        batches[shard(key, shards)].push((key, station));
+                    }
+                    for (sender, batch) in senders.iter().zip(batches) {
+                        // Mergers only stop early by panicking, which the scope raises
+                        let _ = sender.send(batch);
+                    }
+                    Ok(chunk.skipped)
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(senders);
+        let tables = mergers
+            .into_iter()
+            .map(|merger| merger.join().unwrap())
+            .collect::<Vec<_>>();
+        let mut skipped = Skipped::default();
+        for worker in workers {
+            skipped.add(&worker.join().unwrap()?);
+        }
+        io::Result::Ok((tables, skipped))
+    })?;
+
+    // Shards have disjoint keys
+    heap::phase("merge");
+    let mut result = stations.map_or_else(HashTable::new, HashTable::for_stations);
+    for (key, station) in tables.into_iter().flatten() {
+        result.push(key, station);
+    }
+    check_stations(result.len(), options)?;
+    memory::check_used()?;
+    if options.hash_stats {
+        report_hash_stats(result.stats(), collisions.into_inner());
+    }
+    Ok((result, skipped))
+}
+
+// The shard of the merger `key` goes to
+fn shard(key: u64, shards: usize) -> usize {
+    // The low bits of the key also pick its slot, so they are mixed first
+    scan::mix(key) as usize % shards
+}
+
+// Merge the stations of a shard sent by the chunks into one table
+fn merge_shard<'a, A: Aggregator>(
+    batches: mpsc::Receiver<Vec<(u64, Station<'a, A>)>>,
+    stations: Option<usize>,
+    collisions: &AtomicU64,
+    options: &Options,
+) -> HashTable<Station<'a, A>> {
+    let mut shard = stations.map_or_else(HashTable::new, HashTable::for_stations);
+    let mut shard_collisions = 0;
+    for batch in batches {
+        for (key, value) in batch {
+            shard.insert_or_update(
+                key,
+                |station: &Station<A>| {
+                    let same = station.name == value.name;
+                    shard_collisions += (options.hash_stats && !same) as u64;
+                    same
+                },
+                |station| station.stats.merge(&value.stats, options),
+                || value.clone(),
+            );
+        }
+    }
+    collisions.fetch_add(shard_collisions, Ordering::Relaxed);
+    shard
+}
+
+fn report_hash_stats(stats: hash_table::Stats, collisions: u64) {
+    eprintln!(
+        "hash table: {} entries, {} slots, load {:.3}, longest probe {}, {} resizes, {} collisions",
+        stats.len,
+        stats.capacity,
+        stats.len as f64 / stats.capacity as f64,
+        stats.longest_probe,
+        stats.resizes,
+        collisions
+    );
+}
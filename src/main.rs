@@ -1,184 +1,790 @@
 use std::{
-    env::args,
+    env::{self, args},
     fs::File,
-    sync::{Arc, Mutex},
-    thread::{self},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
 };
 
-use hash_table::HashTable;
+use finalize::{finish, finish_flagged, Stations};
+use log::{debug, info, warn, LevelFilter};
+use onebrc::{
+    aggregate, aggregate_spilled, aggregate_windowed, aggregate_with,
+    aggregator::MinMeanMax,
+    count_lines, count_stations,
+    hash_table::HashTable,
+    heap, memory,
+    options::{Command, Dedup, Header, Options, USAGE},
+    sniff, state, Input, Io, Malformed, Skipped, StationStats,
+};
+use outliers::Spreads;
+use output::Row;
 
-mod hash_table;
+mod bench;
+mod cache;
+mod diff;
+mod distributed;
+mod distribution;
+mod finalize;
+mod generate;
+mod histogram;
+mod info;
+mod inspect;
+mod numa;
+mod outliers;
+mod output;
+mod page_cache;
+#[cfg(all(feature = "flamegraph", unix))]
+mod profile;
+mod report;
+mod serve;
+mod sliding;
+mod validate;
 
-#[derive(Debug, Clone)]
-struct Result {
-    name: String,
-    min: i32,
-    max: i32,
-    mean: i64,
-    count: i64,
-}
+// Windows a pipe is read in, see `stream_window`
+const STREAM_WINDOW: usize = 64 << 20;
+
+// Merging and output allocate per station, the allocator shows with many
+#[cfg(all(
+    feature = "mimalloc",
+    not(any(feature = "alloc-check", feature = "heap-profile")),
+    not(target_family = "wasm")
+))]
+#[global_allocator]
+static ALLOCATOR: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+#[cfg(all(
+    feature = "jemalloc",
+    not(any(
+        feature = "mimalloc",
+        feature = "alloc-check",
+        feature = "heap-profile"
+    )),
+    not(target_family = "wasm")
+))]
+#[global_allocator]
+static ALLOCATOR: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
-impl Result {
-    fn new(name: &[u8]) -> Self {
-        Self {
-            name: String::from_utf8_lossy(name).to_string(),
-            min: i32::MAX,
-            max: i32::MIN,
-            mean: 0,
-            count: 0,
+fn main() {
+    let mut options = match Options::parse(args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{error}");
+            eprintln!("{}", usage());
+            std::process::exit(1);
         }
+    };
+    init_logging(&options);
+    memory::set_limit(options.max_memory);
+    let reads_measurements = matches!(
+        options.command,
+        Command::Run | Command::Inspect | Command::Bench | Command::Serve | Command::CountStations
+    );
+    if options.sniff && reads_measurements {
+        sniff(&mut options);
     }
-
-    fn update(&mut self, value: i32) {
-        self.min = self.min.min(value);
-        self.max = self.max.max(value);
-        self.mean += value as i64;
-        self.count += 1;
+    #[cfg(all(feature = "flamegraph", unix))]
+    let profile = options.flamegraph.as_deref().and_then(profile::start);
+    match options.command {
+        Command::Run => run(&options),
+        Command::Generate => {
+            if let Err(error) = generate::run(&options) {
+                eprintln!("failed to generate: {error}");
+                std::process::exit(1);
+            }
+        }
+        Command::Validate => match validate::run(&options) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(error) => {
+                eprintln!("failed to validate: {error}");
+                std::process::exit(2);
+            }
+        },
+        Command::Inspect => {
+            if let Err(error) = inspect::run(&options) {
+                eprintln!("failed to inspect: {error}");
+                std::process::exit(1);
+            }
+        }
+        Command::Merge => {
+            let result = merge_states(&mut options);
+            finish(&result, &options);
+        }
+        Command::Worker => {
+            if let Err(error) = distributed::work(&options) {
+                eprintln!("failed to work: {error}");
+                std::process::exit(1);
+            }
+        }
+        Command::Coordinate => match distributed::coordinate(&options) {
+            Ok(result) => finish(&result, &options),
+            Err(error) => {
+                eprintln!("failed to coordinate: {error}");
+                std::process::exit(1);
+            }
+        },
+        Command::CountStations => {
+            let file = open(&options);
+            let input = match load_input(&file, &options) {
+                Ok(input) => input,
+                Err(error) => {
+                    eprintln!("failed to load the input: {error}");
+                    std::process::exit(1);
+                }
+            };
+            match count_stations(&input, &options) {
+                Ok(stations) => println!("{stations}"),
+                Err(error) => {
+                    eprintln!("failed to count the stations: {error}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Diff => match diff::run(&options) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(error) => {
+                eprintln!("failed to compare: {error}");
+                std::process::exit(2);
+            }
+        },
+        Command::Bench => {
+            if let Err(error) = bench::run(&options) {
+                eprintln!("failed to benchmark: {error}");
+                std::process::exit(1);
+            }
+        }
+        Command::BenchParts => bench::parts(&options),
+        Command::Serve => {
+            let scan = || {
+                let file = File::open(&options.file_name)?;
+                let input = load_input(&file, &options)?;
+                let result = aggregate(&input, &options)?;
+                Ok(serve::Scan {
+                    rows: owned_rows(&result, &options),
+                    bytes: input.len() as u64,
+                })
+            };
+            if let Err(error) = serve::run(&options, scan) {
+                eprintln!("failed to serve: {error}");
+                std::process::exit(1);
+            }
+        }
+        Command::Info => info::print(&options),
+        Command::Help => println!("{}", usage()),
     }
-
-    fn merge(&mut self, other: &Self) {
-        self.min = self.min.min(other.min);
-        self.max = self.max.max(other.max);
-        self.mean += other.mean;
-        self.count += other.count;
+    debug!("at most {} MiB counted in use", memory::peak() >> 20);
+    heap::print();
+    #[cfg(all(feature = "flamegraph", unix))]
+    if let Some(profile) = profile {
+        profile.finish();
     }
+}
 
-    fn to_string(&self) -> String {
-        let min = self.min as f64 / 10.0;
-        let max = self.max as f64 / 10.0;
-        let mean = self.mean as f64 / self.count as f64 / 10.0;
-        format!("{}={:.1}/{:.1}/{:.1}", self.name, min, max, mean)
+// Warnings by default, more with each `-v` and nothing with `--quiet`.
+// `RUST_LOG` overrides this unless quiet, e.g. to also see what the GPU
+// backend logs.
+fn init_logging(options: &Options) {
+    if options.quiet {
+        return;
     }
+    let level = match options.verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let mut builder = env_logger::Builder::new();
+    match env::var("RUST_LOG") {
+        Ok(filters) => builder.parse_filters(&filters),
+        Err(_) => builder
+            .filter_level(LevelFilter::Warn)
+            .filter_module("onebrc", level)
+            .filter_module("one_billion_lines", level),
+    };
+    builder
+        .format(|out, record| {
+            let level = record.level().as_str().to_lowercase();
+            writeln!(out, "{level}: {}", record.args())
+        })
+        .init();
+}
+
+fn usage() -> String {
+    let bin = args().next().unwrap();
+    format!(
+        "Usage: {bin} [run] [options] <file>
+       {bin} generate [options] <output>
+       {bin} validate [options] <file>
+       {bin} inspect [options] <file>
+       {bin} bench [options] <file>
+       {bin} bench-parts [options]
+       {bin} merge [options] <state>...
+       {bin} serve [options] <file>
+       {bin} worker [options]
+       {bin} coordinate --workers <addresses> [options] <file>
+       {bin} count-stations [options] <file>
+       {bin} diff [options] <expected> <actual>
+       {bin} info [options]
+
+{USAGE}"
+    )
 }
 
-fn find_next(data: &[u8], position: usize, char: u8) -> usize {
-    for i in position..data.len() {
-        if data[i] == char {
-            return i;
+// Aggregate the input file and write the result
+fn run(options: &Options) {
+    let file = open(options);
+    if options.cold {
+        if let Err(error) = page_cache::evict(&file) {
+            eprintln!("failed to evict the file from the page cache: {error}");
+            std::process::exit(1);
         }
     }
-    return position;
-}
+    if options.histogram {
+        match histogram::run(&file, options) {
+            Ok(skipped) => {
+                report_duplicates(&skipped, options);
+                report_malformed(&skipped, options);
+            }
+            Err(error) => {
+                eprintln!("failed to aggregate: {error}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    // A stream would never be read to its end
+    if options.follow && !file.metadata().is_ok_and(|metadata| metadata.is_file()) {
+        follow(HashTable::new(), file, None, Duration::ZERO, options);
+    }
+    let window = options
+        .window
+        .or_else(|| spill_window(&file, options))
+        .or_else(|| stream_window(&file, options));
+    if let Some(window) = window {
+        heap::phase("aggregate");
+        match aggregate_windowed(&file, window, options) {
+            Ok((result, skipped)) => {
+                heap::phase("output");
+                finish(&result, options);
+                report_duplicates(&skipped, options);
+                report_malformed(&skipped, options);
+            }
+            Err(error) => {
+                eprintln!("failed to aggregate: {error}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let started = Instant::now();
 
-struct Chunk {
-    data: Arc<memmap::Mmap>,
-    end: usize,
-    position: usize,
-    result: HashTable<Result>,
-}
+    // Map file to memory
+    heap::phase("load");
+    let input = match load_input(&file, options) {
+        Ok(input) => input,
+        Err(error) => {
+            eprintln!("failed to load the input: {error}");
+            std::process::exit(1);
+        }
+    };
+    let loaded = started.elapsed();
+    let mut data = &input[..];
+    if options.dry_run {
+        let lines = count_lines(data, options);
+        let seconds = started.elapsed().as_secs_f64();
+        println!(
+            "{lines} lines, {} bytes in {seconds:.3}s, {:.2} GB/s",
+            data.len(),
+            data.len() as f64 / seconds / 1e9
+        );
+        return;
+    }
+    if options.follow {
+        // A partially written last line is picked up once it is complete
+        let complete = data.iter().rposition(|&c| c == b'\n').map_or(0, |i| i + 1);
+        data = &data[..complete];
+    }
 
-impl Chunk {
-    fn new(data: Arc<memmap::Mmap>, start: usize, end: usize) -> Self {
-        Self {
-            data,
-            end,
-            position: start,
-            result: HashTable::new(),
+    // Appended lines would invalidate the cache anyway
+    let cached = match &options.cache {
+        Some(dir) if !options.follow => match cache::path(Path::new(dir), &file, data, options) {
+            Ok(path) => Some(path),
+            Err(error) => {
+                warn!("not using the cache: {error}");
+                None
+            }
+        },
+        _ => None,
+    };
+    // Write the result, then what was asked for about the run
+    let complete = |result: &HashTable<StationStats>, spreads, skipped: Skipped| {
+        let aggregated = started.elapsed();
+        heap::phase("output");
+        finish_flagged(result, spreads, options);
+        let finished = started.elapsed();
+        report_duplicates(&skipped, options);
+        report_malformed(&skipped, options);
+        if let Some(path) = &options.distribution {
+            if let Err(error) = distribution::write(data, Path::new(path), options) {
+                eprintln!("failed to write the distribution: {error}");
+            }
+        }
+        if options.summary {
+            let summary =
+                output::write_summary(result, data, finished, options, &mut io::stderr().lock());
+            if let Err(error) = summary {
+                eprintln!("failed to write the summary: {error}");
+            }
+        }
+        if let Some(path) = &options.report {
+            let timings = report::Timings {
+                load: loaded,
+                aggregate: aggregated - loaded,
+                output: finished - aggregated,
+            };
+            let bytes = data.len() as u64;
+            if let Err(error) = report::write(Path::new(path), result, bytes, timings, options) {
+                eprintln!("failed to write the report: {error}");
+            }
+        }
+    };
+    // A cache that is missing is a miss, one that does not load is stored again
+    let reused = cached.as_deref().and_then(|path| match cache::load(path) {
+        Ok(result) => Some(result),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => {
+            warn!("ignoring the cached result: {error}");
+            None
         }
+    });
+    if let Some(result) = reused {
+        info!("using the cached result");
+        complete(&result, Spreads::default(), Skipped::default());
+        return;
     }
 
-    #[inline(always)]
-    fn parse_line(&mut self) -> bool {
-        // Find next semicolon, skipped 3 bytes because town is at least 3 bytes
-        let split_pos = find_next(&self.data, self.position + 3, b';');
-        let name = &self.data[self.position..split_pos];
-        // Find next newline, skipped 3 bytes because temp at lest 3 bytes (x.x)
-        self.position = find_next(&self.data, split_pos + 3, b'\n') + 1;
-        let value = self.parse_value(&self.data[split_pos + 1..self.position - 1]);
-        // Simple hash function, stolen from java
-        let mut key: u64 = 0;
-        for i in 0..name.len() {
-            key *= 31;
-            key += name[i] as u64;
+    let store = |result: &HashTable<StationStats>| {
+        if let Some(path) = &cached {
+            if let Err(error) = cache::store(path, result, options) {
+                eprintln!("failed to cache the result: {error}");
+            }
         }
-        // Update or insert new result
-        self.result.insert_or_update(
-            key,
-            |fu: &mut Result| fu.update(value),
-            || Result::new(name),
+    };
+    // A single node gains nothing from worker processes
+    let nodes = if options.numa {
+        numa::nodes()
+    } else {
+        Vec::new()
+    };
+    if nodes.len() > 1 {
+        info!(
+            "aggregating with a worker process on each of {} NUMA nodes",
+            nodes.len()
         );
-        return self.position < self.end;
+        match distributed::per_node(options, &nodes) {
+            Ok(result) => {
+                store(&result);
+                complete(&result, Spreads::default(), Skipped::default());
+            }
+            Err(error) => {
+                eprintln!("failed to aggregate per node: {error}");
+                std::process::exit(1);
+            }
+        }
+        return;
     }
 
-    #[inline(always)]
-    fn parse_value(&self, data: &[u8]) -> i32 {
-        let neg = data[0] == b'-';
-        let mut result: i32 = 0;
-        // bool can be converted to usize because it is always 0 or 1
-        for i in neg as usize..data.len() - 2 {
-            // Convert ascii to int
-            result = result * 10 + (data[i] - b'0') as i32;
+    if let Some(dir) = &options.spill {
+        match aggregate_spilled(data, Path::new(dir), options) {
+            Ok((result, skipped)) => {
+                store(&result);
+                complete(&result, Spreads::default(), skipped);
+            }
+            Err(error) => {
+                eprintln!("failed to aggregate with --spill: {error}");
+                std::process::exit(1);
+            }
         }
-        // Convert to decimal (it's faster then using floats)
-        result = result * 10 + (data[data.len() - 1] - b'0') as i32;
-        if neg {
-            -result
-        } else {
-            result
+        return;
+    }
+
+    if options.flag_outliers || options.stddev {
+        let (result, spreads, skipped) = match outliers::aggregate(data, options) {
+            Ok(aggregated) => aggregated,
+            Err(error) => {
+                eprintln!("failed to aggregate: {error}");
+                std::process::exit(1);
+            }
+        };
+        store(&result);
+        complete(&result, spreads, skipped);
+        return;
+    }
+
+    let (result, skipped) = match aggregate_with_fallback(data, options) {
+        Ok(aggregated) => aggregated,
+        Err(error) => {
+            eprintln!("failed to aggregate: {error}");
+            std::process::exit(1);
         }
+    };
+    store(&result);
+    if options.follow {
+        follow(
+            result,
+            file,
+            Some(data.len()),
+            started.elapsed() - loaded,
+            options,
+        );
+    } else {
+        complete(&result, Spreads::default(), skipped);
     }
 }
 
-fn main() {
-    let file_name = args().nth(1);
-    if file_name.is_none() {
-        eprintln!("Usage: {} <file>", args().next().unwrap());
+// How many malformed lines `--lenient` skipped, by why
+fn report_malformed(skipped: &Skipped, options: &Options) {
+    if !options.lenient || options.quiet {
         return;
     }
-    let file_name = file_name.unwrap();
-    let file = File::open(file_name).unwrap();
+    let counts = Malformed::ALL
+        .map(|why| format!("{} {}", skipped.malformed(why), why.name()))
+        .join(", ");
+    eprintln!("skipped malformed lines: {counts}");
+}
 
-    // Map file to memory
-    let mmaped = unsafe { memmap::Mmap::map(&file).unwrap() };
-    let mmaped = Arc::new(mmaped);
+// How many lines `--dedup` found again, if it was given
+fn report_duplicates(skipped: &Skipped, options: &Options) {
+    if options.quiet {
+        return;
+    }
+    let handled = match options.dedup {
+        Some(Dedup::Skip) => "skipped",
+        Some(Dedup::Count) => "aggregated anyway",
+        None => return,
+    };
+    eprintln!("{} duplicate lines, {handled}", skipped.duplicates);
+}
 
-    // Calculate chunk size for each thread
-    let max_threads: usize = thread::available_parallelism().unwrap().into();
-    let chunk_size = mmaped.len() / max_threads;
+// Print the result, then keep reading lines appended to the file and print
+// the updated result whenever there were new ones. A stream is followed from
+// its start, without `offset`, until it ends.
+fn follow(
+    result: HashTable<StationStats>,
+    mut file: File,
+    offset: Option<usize>,
+    took: Duration,
+    options: &Options,
+) -> ! {
+    let followed = options
+        .metrics
+        .map(|port| match serve::Followed::serve(port, options) {
+            Ok(followed) => followed,
+            Err(error) => {
+                eprintln!("failed to serve the metrics: {error}");
+                std::process::exit(1);
+            }
+        });
+    if let (Some(followed), Some(offset)) = (&followed, offset) {
+        followed.count(offset as u64, lines(&result), took);
+    }
+    // Names can no longer borrow from the mapping once it is remapped
+    let mut result = result
+        .into_iter()
+        .map(|(key, value)| (key, value.into_owned()))
+        .collect::<HashTable<_>>();
+    let mut panes = options.sliding.map(sliding::Panes::new);
+    if let Some(panes) = &mut panes {
+        panes.push(std::mem::take(&mut result));
+    }
+    let mut source = match offset {
+        Some(offset) => match file.seek(SeekFrom::Start(offset as u64)) {
+            Ok(_) => Appended::File(file),
+            Err(error) => {
+                eprintln!("failed to follow the file: {error}");
+                std::process::exit(1);
+            }
+        },
+        None => Appended::stream(file),
+    };
 
-    // Split file into chunks by finding newlines at the end of each chunk
-    let mut chunks = Vec::new();
-    let mut next_start = 0;
-    while next_start < mmaped.len() {
-        let mut next_end = find_next(&mmaped, next_start + chunk_size, b'\n');
-        if next_end > mmaped.len() {
-            next_end = mmaped.len();
+    // Appended lines are all data, a stream may start with a header
+    let mut appended = Options {
+        header: offset.map_or(options.header, |_| Header::Absent),
+        ..options.clone()
+    };
+    let mut pending = Vec::new();
+    let mut updated = true;
+    loop {
+        if let Some(panes) = &panes {
+            if updated {
+                result = panes.merged();
+            }
+        }
+        if updated {
+            finish(&result, options);
+            if let Some(followed) = &followed {
+                followed.show(owned_rows(&result, options), options);
+            }
+        }
+        let open = match source.read(&mut pending, options.interval) {
+            Ok(open) => open,
+            Err(error) => {
+                eprintln!("failed to follow the file: {error}");
+                std::process::exit(1);
+            }
+        };
+        // A stream may end without a newline
+        let complete = match open {
+            true => pending
+                .iter()
+                .rposition(|&c| c == b'\n')
+                .map_or(0, |i| i + 1),
+            false => pending.len(),
+        };
+        updated = complete > 0;
+        if updated {
+            let started = Instant::now();
+            let stations = match aggregate(&pending[..complete], &appended) {
+                Ok(stations) => stations,
+                Err(error) => {
+                    eprintln!("failed to aggregate the appended lines: {error}");
+                    std::process::exit(1);
+                }
+            };
+            if let Some(followed) = &followed {
+                followed.count(complete as u64, lines(&stations), started.elapsed());
+            }
+            let stations = stations
+                .into_iter()
+                .map(|(key, value)| (key, value.into_owned()));
+            match &mut panes {
+                Some(panes) => panes.push(stations.collect()),
+                None => {
+                    for (key, value) in stations {
+                        result.insert_or_update(
+                            key,
+                            |stats: &StationStats| stats.name == value.name,
+                            |stats| stats.merge(&value),
+                            || value.clone(),
+                        );
+                    }
+                }
+            }
+            pending.drain(..complete);
+            appended.header = Header::Absent;
+        }
+        if let Some(panes) = &mut panes {
+            updated |= panes.expire();
+        }
+        if !open {
+            if let Some(panes) = &panes {
+                result = panes.merged();
+            }
+            if updated {
+                finish(&result, options);
+            }
+            std::process::exit(0);
         }
-        let chunk = Chunk::new(mmaped.clone(), next_start, next_end);
-        chunks.push(chunk);
-        next_start = next_end + 1;
     }
+}
+
+// Measurements of all stations of `result`
+fn lines(result: &HashTable<StationStats>) -> u64 {
+    result.key_set().map(|(_, stats)| stats.count as u64).sum()
+}
+
+// Rows of `result` that outlive it
+fn owned_rows(result: &HashTable<StationStats>, options: &Options) -> Vec<Row<'static>> {
+    Stations::merge([result])
+        .rows(options)
+        .into_iter()
+        .map(Row::into_owned)
+        .collect()
+}
 
-    let result = Arc::new(Mutex::new(HashTable::new()));
-    // Start threads for each chunk
-    let mut threads = Vec::new();
-    for mut chunk in chunks {
-        let result = result.clone();
-        threads.push(thread::spawn(move || {
-            while chunk.parse_line() {}
+// Where `follow` gets new lines from
+enum Appended {
+    // Read up to its end at every poll
+    File(File),
+    // Read on a thread of its own as lines come, so a poll never blocks
+    Stream(mpsc::Receiver<io::Result<Vec<u8>>>),
+}
 
-            let mut result = result.lock().unwrap();
-            for (key, value) in chunk.result.key_set() {
-                result.insert_or_update(
-                    key.clone(),
-                    |fu: &mut Result| fu.merge(&value),
-                    || value.clone(),
-                );
+impl Appended {
+    fn stream(mut file: File) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = vec![0; 1 << 16];
+            loop {
+                let read = match file.read(&mut buffer) {
+                    Ok(0) => return,
+                    Ok(read) => Ok(buffer[..read].to_vec()),
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(error) => Err(error),
+                };
+                let failed = read.is_err();
+                if sender.send(read).is_err() || failed {
+                    return;
+                }
             }
-        }));
+        });
+        Self::Stream(receiver)
+    }
+
+    // Wait `interval` and add what was appended since to `pending`, returns
+    // whether more may come
+    fn read(&mut self, pending: &mut Vec<u8>, interval: Duration) -> io::Result<bool> {
+        thread::sleep(interval);
+        match self {
+            Self::File(file) => file.read_to_end(pending).map(|_| true),
+            Self::Stream(receiver) => loop {
+                match receiver.try_recv() {
+                    Ok(read) => pending.extend_from_slice(&read?),
+                    Err(mpsc::TryRecvError::Empty) => return Ok(true),
+                    Err(mpsc::TryRecvError::Disconnected) => return Ok(false),
+                }
+            },
+        }
+    }
+}
+
+// A file that would be read into memory past `--max-memory` is read a
+// window at a time instead, a quarter of the limit each: one is aggregated,
+// one waits for the threads and one is being read
+fn spill_window(file: &File, options: &Options) -> Option<usize> {
+    let limit = options.max_memory?;
+    let len = file.metadata().ok()?.len();
+    // The other half is left for the tables
+    if options.io != Io::Read || len <= limit as u64 / 2 {
+        return None;
+    }
+    if let Some(flag) = options.whole_file() {
+        info!("reading the whole file, {flag} needs it");
+        return None;
+    }
+    let window = (limit / 4).max(1 << 20);
+    info!(
+        "reading {} MiB windows to stay within --max-memory",
+        window >> 20
+    );
+    Some(window)
+}
+
+// The input file, or exit with why it could not be opened
+fn open(options: &Options) -> File {
+    match File::open(&options.file_name) {
+        Ok(file) => file,
+        Err(error) => {
+            eprintln!("failed to open `{}`: {error}", options.file_name);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Guess the format from the start of the file, see `sniff`. A pipe could not
+// be read again, and a file that fails to open fails later with a message.
+fn sniff(options: &mut Options) {
+    let Ok(file) = File::open(&options.file_name) else {
+        return;
+    };
+    if !file.metadata().is_ok_and(|metadata| metadata.is_file()) {
+        return;
+    }
+    let mut sample = Vec::with_capacity(sniff::SAMPLE);
+    match file.take(sniff::SAMPLE as u64).read_to_end(&mut sample) {
+        Ok(_) => sniff::apply(&sample, options),
+        Err(error) => warn!("not sniffing the format: {error}"),
+    }
+}
+
+// A pipe, e.g. from process substitution, is read a window at a time unless
+// something needs all of it, a quarter of `--max-memory` each if there is one
+fn stream_window(file: &File, options: &Options) -> Option<usize> {
+    if file.metadata().ok()?.is_file() {
+        return None;
+    }
+    if let Some(flag) = options.whole_file() {
+        info!("reading the whole stream, {flag} needs it");
+        return None;
     }
+    let window = options.max_memory.map_or(STREAM_WINDOW, |limit| {
+        (limit / 4).clamp(1 << 20, STREAM_WINDOW)
+    });
+    info!("reading the stream in {} MiB windows", window >> 20);
+    Some(window)
+}
 
-    // Await all threads
-    for thread in threads {
-        if !thread.is_finished() {
-            thread.join().unwrap();
+// Load the input as configured, advice the kernel does not take is only
+// worth a warning
+fn load_input(file: &File, options: &Options) -> io::Result<Input> {
+    let input = options.io.load(file)?;
+    debug!(
+        "loaded {} bytes with {}",
+        input.len(),
+        format!("{:?}", options.io).to_lowercase()
+    );
+    if let Some(advice) = options.madvise {
+        if let Err(error) = input.advise(advice) {
+            warn!("ignoring --madvise: {error}");
         }
     }
+    Ok(input)
+}
 
-    let result = result.lock().unwrap();
+fn aggregate_with_fallback<'a>(
+    data: &'a [u8],
+    options: &'a Options,
+) -> io::Result<(HashTable<StationStats<'a>>, Skipped)> {
+    #[cfg(feature = "gpu")]
+    if options.gpu {
+        match onebrc::gpu::aggregate(data, options) {
+            Some(result) => return Ok((result, Skipped::default())),
+            None => warn!("aggregating on the CPU, the GPU is unavailable for this input"),
+        }
+    }
+    let (result, skipped) = aggregate_with::<MinMeanMax>(data, options)?;
     let result = result
-        .key_set()
-        .map(|(_, value)| value.to_string())
-        .collect::<Vec<String>>();
-    println!("{{{}}}", result.join(", "));
+        .into_iter()
+        .map(|(key, station)| (key, station.into()))
+        .collect();
+    Ok((result, skipped))
+}
+
+// Combine saved states exactly, the scale of values must match
+fn merge_states(options: &mut Options) -> HashTable<StationStats<'static>> {
+    let mut result = HashTable::new();
+    let mut merged_scale = None;
+    for path in &options.states {
+        let (scale, stations) = match state::read(Path::new(path)) {
+            Ok(state) => state,
+            Err(error) => {
+                eprintln!("failed to read the state `{path}`: {error}");
+                std::process::exit(1);
+            }
+        };
+        if *merged_scale.get_or_insert(scale) != scale {
+            eprintln!("`{path}` was saved with a different --flexible-values setting");
+            std::process::exit(1);
+        }
+        // Names may be transcoded, so key them by name rather than by the
+        // hash of the raw input bytes
+        for stats in stations {
+            result.insert_or_update(
+                stats.key(),
+                |merged: &StationStats| merged.name == stats.name,
+                |merged| merged.merge(&stats),
+                || stats.clone(),
+            );
+        }
+    }
+    // Values are scaled like the runs that saved them
+    options.flexible_values = merged_scale == Some(100);
+    // Recorded as the input by the sqlite output
+    options.file_name = options.states.join(" ");
+    result
 }
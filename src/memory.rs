@@ -0,0 +1,103 @@
+//! Approximate memory usage, see `--max-memory`
+//!
+//! Only the big allocations are counted: hash tables, name arenas and input
+//! read into memory. A mapped file is left out, its pages are the kernel's
+//! to drop, and so are names and other small allocations. Past the limit an
+//! input fails to load, and a run whose tables or arenas grew past it fails
+//! once it adds the next station, instead of getting killed.
+
+use std::{
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static USED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+const MIB: usize = 1024 * 1024;
+
+// Bytes all counted allocations may take, no limit with `None`
+pub fn set_limit(limit: Option<usize>) {
+    LIMIT.store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+// Bytes in use now
+pub fn used() -> usize {
+    USED.load(Ordering::Relaxed)
+}
+
+// Most bytes in use at once so far
+pub fn peak() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+// Whether `bytes` more for `what` stay within the limit, for allocations
+// that can fail with an error
+pub(crate) fn check(bytes: usize, what: &str) -> io::Result<()> {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    if used().saturating_add(bytes) <= limit {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::OutOfMemory,
+        format!(
+            "{what} of {} MiB would exceed --max-memory of {} MiB",
+            bytes.div_ceil(MIB),
+            limit / MIB
+        ),
+    ))
+}
+
+// Count `bytes` more in use, for allocations that cannot fail, see
+// `check_used`
+pub(crate) fn allocate(bytes: usize) {
+    let used = USED.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    PEAK.fetch_max(used, Ordering::Relaxed);
+}
+
+// Whether what is in use stays within the limit, checked where tables and
+// arenas may have grown
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+pub(crate) fn check_used() -> io::Result<()> {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    if used() <= limit {
+        return Ok(());
+    }
+    Err(past_limit(limit))
+}
+
+#[cold]
+fn past_limit(limit: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::OutOfMemory,
+        format!(
+            "{} MiB of tables, names and input in use, past --max-memory of {} MiB",
+            used().div_ceil(MIB),
+            limit / MIB
+        ),
+    )
+}
+
+// Bytes counted until it is dropped, for what cannot free them itself
+pub(crate) struct Counted(usize);
+
+impl Counted {
+    pub(crate) fn new(bytes: usize) -> Self {
+        allocate(bytes);
+        Self(bytes)
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        free(self.0);
+    }
+}
+
+// Inputs built outside of `Io` were never counted, so this stops at zero
+pub(crate) fn free(bytes: usize) {
+    let _ = USED.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+        Some(used.saturating_sub(bytes))
+    });
+}
@@ -0,0 +1,182 @@
+//! Station names
+//!
+//! Names borrow from the input while it is mapped. Once they have to outlive
+//! it, e.g. when results are merged or kept for later, the short ones are
+//! stored inline and only names longer than `INLINE` bytes are allocated.
+//! The name stays the size of a slice, so accumulators still fit a cache
+//! line.
+
+use std::{borrow::Cow, fmt, hash, ops::Deref};
+
+use serde::{
+    de::{SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+// Longest name stored without an allocation
+pub const INLINE: usize = 22;
+
+#[derive(Clone)]
+pub struct Name<'a>(Repr<'a>);
+
+#[derive(Clone)]
+enum Repr<'a> {
+    Borrowed(&'a [u8]),
+    Inline { len: u8, bytes: [u8; INLINE] },
+    Heap(Box<[u8]>),
+}
+
+// Inline names take as much as a slice and a word only with 64-bit pointers
+#[cfg(target_pointer_width = "64")]
+const _: () = assert!(size_of::<Name>() == size_of::<&[u8]>() + 8);
+
+impl<'a> Name<'a> {
+    pub fn borrowed(name: &'a [u8]) -> Self {
+        Self(Repr::Borrowed(name))
+    }
+
+    // A copy of `name` that borrows nothing, inline if it is short enough
+    pub fn copied(name: &[u8]) -> Name<'static> {
+        match name.len() {
+            len @ 0..=INLINE => {
+                let mut bytes = [0; INLINE];
+                bytes[..len].copy_from_slice(name);
+                Name(Repr::Inline {
+                    len: len as u8,
+                    bytes,
+                })
+            }
+            _ => Name(Repr::Heap(name.into())),
+        }
+    }
+
+    pub fn into_owned(self) -> Name<'static> {
+        match self.0 {
+            Repr::Borrowed(name) => Name::copied(name),
+            Repr::Inline { len, bytes } => Name(Repr::Inline { len, bytes }),
+            Repr::Heap(name) => Name(Repr::Heap(name)),
+        }
+    }
+
+    // Whether the name is stored without borrowing or allocating
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+}
+
+impl Deref for Name<'_> {
+    type Target = [u8];
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Borrowed(name) => name,
+            Repr::Inline { len, bytes } => &bytes[..*len as usize],
+            Repr::Heap(name) => name,
+        }
+    }
+}
+
+impl AsRef<[u8]> for Name<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<'a> From<&'a [u8]> for Name<'a> {
+    fn from(name: &'a [u8]) -> Self {
+        Self::borrowed(name)
+    }
+}
+
+// Short names move inline and free the vector
+impl From<Vec<u8>> for Name<'_> {
+    fn from(name: Vec<u8>) -> Self {
+        match name.len() {
+            0..=INLINE => Name::copied(&name),
+            _ => Name(Repr::Heap(name.into_boxed_slice())),
+        }
+    }
+}
+
+impl<'a> From<Cow<'a, [u8]>> for Name<'a> {
+    fn from(name: Cow<'a, [u8]>) -> Self {
+        match name {
+            Cow::Borrowed(name) => Self::borrowed(name),
+            Cow::Owned(name) => name.into(),
+        }
+    }
+}
+
+// Names are equal if their bytes are, however they are stored
+impl PartialEq for Name<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Name<'_> {}
+
+impl hash::Hash for Name<'_> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl fmt::Debug for Name<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&String::from_utf8_lossy(self), f)
+    }
+}
+
+impl Serialize for Name<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self)
+    }
+}
+
+// Borrows from the serialized data where the format allows that
+impl<'de: 'a, 'a> Deserialize<'de> for Name<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NameVisitor;
+
+        impl<'de> Visitor<'de> for NameVisitor {
+            type Value = Name<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("the bytes of a name")
+            }
+
+            fn visit_borrowed_bytes<E>(self, name: &'de [u8]) -> Result<Self::Value, E> {
+                Ok(Name::borrowed(name))
+            }
+
+            fn visit_bytes<E>(self, name: &[u8]) -> Result<Self::Value, E> {
+                Ok(Name::copied(name))
+            }
+
+            fn visit_byte_buf<E>(self, name: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(name.into())
+            }
+
+            fn visit_borrowed_str<E>(self, name: &'de str) -> Result<Self::Value, E> {
+                Ok(Name::borrowed(name.as_bytes()))
+            }
+
+            fn visit_str<E>(self, name: &str) -> Result<Self::Value, E> {
+                Ok(Name::copied(name.as_bytes()))
+            }
+
+            // Formats without bytes, like JSON, write them as a sequence
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut name = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    name.push(byte);
+                }
+                Ok(name.into())
+            }
+        }
+
+        deserializer.deserialize_bytes(NameVisitor)
+    }
+}
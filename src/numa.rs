@@ -0,0 +1,62 @@
+//! NUMA topology
+//!
+//! Nodes are read from sysfs, so everywhere but Linux there are none and
+//! everything runs in a single process.
+
+use std::{fs, io};
+
+// CPU list of every node with CPUs, e.g. `0-7,16-23`
+pub fn nodes() -> Vec<String> {
+    let Ok(entries) = fs::read_dir("/sys/devices/system/node") else {
+        return Vec::new();
+    };
+    let mut nodes = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let id = name.strip_prefix("node")?.parse::<usize>().ok()?;
+            let cpus = fs::read_to_string(entry.path().join("cpulist")).ok()?;
+            let cpus = cpus.trim();
+            (!cpus.is_empty()).then(|| (id, cpus.to_string()))
+        })
+        .collect::<Vec<_>>();
+    nodes.sort_unstable();
+    nodes.into_iter().map(|(_, cpus)| cpus).collect()
+}
+
+// Restrict the current process to the CPUs of a CPU list. Memory is then
+// allocated on the local node on first touch.
+#[cfg(target_os = "linux")]
+pub fn pin(cpus: &str) -> io::Result<()> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid CPU list `{cpus}`"),
+        )
+    };
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    for range in cpus.split(',') {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let first = first.parse::<usize>().map_err(|_| invalid())?;
+        let last = last.parse::<usize>().map_err(|_| invalid())?;
+        for cpu in first..=last {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(invalid());
+            }
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+    }
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    match unsafe { libc::sched_setaffinity(0, size, &set) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin(_cpus: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "pinning to CPUs needs Linux",
+    ))
+}
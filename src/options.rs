@@ -0,0 +1,1431 @@
+//! Command line options
+
+use std::{borrow::Cow, path::Path, str::FromStr, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    alloc_check, config,
+    perfect::{self, StationList},
+    scan::Scanner,
+    table::{Table, SHARDS},
+    Advice, Io,
+};
+
+pub const USAGE: &str = "Commands:
+    run                     Aggregate a measurements file, the default
+    generate                Write a random measurements file
+    validate                Check the format of a measurements file
+    inspect                 Profile a measurements file from a sample
+    bench                   Aggregate a file repeatedly and report timings
+    bench-parts             Time the stages of aggregating on generated data
+    merge                   Combine states saved with --save-state
+    serve                   Answer queries about a file over HTTP
+    worker                  Aggregate parts of files for a coordinator
+    coordinate              Split a file across workers
+    count-stations          Count the distinct stations of a file
+    diff                    Compare two results with a tolerance
+    info                    Show what was detected about this machine
+    help                    Show this help
+
+Options:
+    --config <path>         Read options from a TOML file, defaults to
+                            1brc.toml if there is one
+    --no-<flag>             Turn off a flag without a value that came
+                            before, like one of the config file
+    --threads <count>       Threads to aggregate with, defaults to the
+                            available parallelism
+    --io mmap|read          Map the file or read it into memory, defaults
+                            to mmap
+    --window <MiB>          Load and aggregate the file this much at a time,
+                            for files larger than the address space, pipes
+                            are read 64 MiB at a time by default
+    --window <time>|<count>rows
+                            With --follow, only aggregate the lines read in
+                            the last period like 30s, 5m or 1h, or the last
+                            lines like 100000rows, and print them as they
+                            roll
+    --max-memory <MiB>      Fail instead of using more for tables and input
+                            read into memory, the file is read a window at a
+                            time where that fits and nothing needs it whole
+    --spill <dir>           Write the stations of a thread to files here
+                            when its table outgrows its share of
+                            --max-memory, or of 1 GiB, and merge them at the
+                            end, for more stations than fit in the tables
+    --max-stations <count>  Fail past this many distinct stations instead of
+                            growing the tables for a key column that is
+                            nearly unique, with --spill spill past them
+    --prefetch <MiB>        Touch the pages this far ahead of each thread in
+                            the background, to hide page faults on cold runs
+    --scanner scalar|neon|avx2|avx512
+                            Byte search to aggregate with, defaults to the
+                            widest the CPU supports
+    --table robin|std|hashbrown|sharded
+                            Hash table to aggregate into, defaults to robin,
+                            the others are baselines, hashbrown needs the
+                            `hashbrown` feature
+    --shards <count>        Tables of --table sharded, a power of two up to
+                            256, defaults to 16
+    --stations <path>       Names of all stations, one per line, to look
+                            them up with a perfect hash instead of probing,
+                            others still work but are slower
+    --reserve <path>        Names of the expected stations, one per line, to
+                            size the table for and reserve their entries up
+                            front, so it does not grow while aggregating
+    --estimate              Count the stations of a sample of the file first
+                            and size the hash tables for them
+    --encoding utf8|latin1  Encoding of station names, defaults to utf8
+    --strict-utf8           Fail on a station name that is not UTF-8 and
+                            show its line, instead of replacing the invalid
+                            bytes in the output
+    --madvise normal|sequential|random|willneed|hugepage
+                            Access pattern to advise for a mapped file,
+                            Linux only
+    --lenient               Skip blank lines and lines starting with #, and
+                            malformed lines, printing how many of each kind
+    --dedup <skip|count>    Skip lines seen before in the chunk of a thread,
+                            or only count them, and print how many there were
+    --flexible-values       Accept values like 12, 12.3 and 12.34
+    --delimiter <char>      Field delimiter, defaults to ;
+    --key-col <index>       Column of the station name in files with more
+                            than two columns, defaults to 0
+    --value-col <index>     Column of the value, defaults to 1
+    --time-col <index>      Column of an ISO 8601 timestamp, for --group-by
+    --group-by station[,year|month|day|hour]
+                            Aggregate each station per period of its
+                            timestamps, named like `Hamburg@2024-03`,
+                            defaults to station
+    --quotes                Allow fields in double quotes, which may contain
+                            the delimiter and \"\" for a quote
+    --no-sniff              Do not guess the delimiter and value format from
+                            the first lines, also off with --delimiter,
+                            --flexible-values, --key-col, --value-col,
+                            --time-col or --quotes
+    --header                Skip the first line, which is detected as a
+                            header if its value is not a number by default
+    --no-header             Never skip the first line
+    --precision <digits>    Decimals in the output, defaults to 1
+    --unit c|f              Output unit, defaults to Celsius
+    --format plain|table|jsonl|csv|arrow|parquet|sqlite
+                            Output format, defaults to plain
+    --histogram             Count the values of each station in bins of
+                            equal width from -100 to 100 degrees instead,
+                            written with --format jsonl or csv
+    --bins <count>          Bins of --histogram, defaults to 20
+    --flag-outliers         Mark stations whose min or max is more than
+                            --sigmas standard deviations from their mean,
+                            in plain, table, jsonl and csv output
+    --sigmas <k>            Standard deviations of --flag-outliers, defaults
+                            to 5
+    --stddev                Add a column with the standard deviation of each
+                            station to arrow and parquet output
+    --color auto|always|never
+                            Colored table output, defaults to auto
+    --sort name|min|mean|max|count
+                            Sort the output, by name in byte order by default
+    --desc                  Sort in descending order
+    --collate bytes|unicode Order of names when sorting, defaults to bytes
+    --output <path>         Write the result to a file instead of stdout,
+                            sqlite appends to the database at this path
+    --compress zstd|gzip    Compress the --output file, zstd and gzip need
+                            the features of the same name
+    --save-state <path>     Also save the merged accumulators for `merge`
+    --follow                Keep reading lines appended to the file and print
+                            the updated result, a pipe until it closes
+    --interval <seconds>    How often to check for new lines, defaults to 1
+    --metrics <port>        With --follow, answer the queries of `serve` on
+                            this port of --host, including /metrics, with
+                            the result so far
+    --cache <dir>           Reuse the result of an earlier run on the same
+                            unchanged file
+    --dry-run               Only count the lines, to measure how fast the
+                            file can be read
+    --cold                  Evict the file from the page cache first, so it
+                            is read from the disk, Linux only
+    --summary               Print totals and throughput to stderr
+    --report <path>         Write timings, throughput and the CPU features
+                            as JSON
+    --distribution <path>   Also write how often each value occurs over all
+                            rows as CSV, e.g. to check generated files
+    -v, --verbose           Log chunks, the chosen backends and fallbacks to
+                            stderr, -vv for more detail
+    -q, --quiet             Print nothing but errors to stderr, stdout only
+                            ever gets the result
+    --debug-threads         Print what each thread aggregated to stderr
+    --hash-stats            Print how full the hash table got and how many
+                            names collided to stderr
+    --flamegraph <path>     Sample the stacks of the run and write them as an
+                            SVG flamegraph when it is done, needs the
+                            `flamegraph` feature
+    --numa                  Run one process per NUMA node, each pinned to
+                            the CPUs of its node
+    --gpu                   Aggregate on the GPU if there is one, experimental
+
+Environment:
+    ONEBRC_THREADS, ONEBRC_IO, ONEBRC_FORMAT and ONEBRC_MADVISE set their
+    options, the command line takes precedence
+
+Serve options:
+    --host <address>        Address to listen on, defaults to 127.0.0.1
+    --port <port>           Port to listen on, defaults to 8080
+    --rescan                Aggregate the file again for every request
+
+Worker options:
+    --host <address>        Address to listen on, defaults to 127.0.0.1
+    --port <port>           Port to listen on, defaults to 8080
+
+Generate options:
+    --rows <count>          Rows to write, defaults to 1000000000
+    --stations <count>      Distinct stations, up to 10000, defaults to 413
+    --seed <number>         Seed of the random values, defaults to 1
+
+Bench options:
+    --runs <count>          Timed runs, defaults to 10
+    --warmup <count>        Untimed runs before those, defaults to 2
+    --matrix <axes>         Bench every combination of the comma separated
+                            table, scanner, io and threads and compare them,
+                            the scanner also hashes the names
+
+Bench-parts options:
+    --rows <count>          Rows to generate, defaults to 1000000
+    --stations <count>      Distinct stations, up to 10000, defaults to 413
+    --seed <number>         Seed of the random values, defaults to 1
+    --runs <count>          Timed runs of each stage, defaults to 10
+
+Diff options:
+    --tolerance <value>     Largest difference of values that still match,
+                            defaults to 0.1
+
+Coordinate options:
+    --workers <addresses>   Comma separated workers, e.g. a:8080,b:8080";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    // Aggregate a measurements file
+    Run,
+    // Write a random measurements file
+    Generate,
+    // Check the format of a measurements file
+    Validate,
+    // Report the rows, names and values of a sample of a measurements file
+    Inspect,
+    // Combine states saved with `--save-state`
+    Merge,
+    // Answer queries about a measurements file over HTTP
+    Serve,
+    // Aggregate ranges of files for a coordinator
+    Worker,
+    // Split a file across workers and merge their results
+    Coordinate,
+    // Only count the distinct stations of a measurements file
+    CountStations,
+    // Aggregate a measurements file repeatedly and report timings
+    Bench,
+    // Time the stages of the aggregation one by one on generated data
+    BenchParts,
+    // Compare two results station by station
+    Diff,
+    // Print CPU features, topology and the defaults they lead to
+    Info,
+    Help,
+}
+
+// Subcommands by name, `run` is the default
+const COMMANDS: [(&str, Command); 14] = [
+    ("run", Command::Run),
+    ("generate", Command::Generate),
+    ("validate", Command::Validate),
+    ("inspect", Command::Inspect),
+    ("bench", Command::Bench),
+    ("bench-parts", Command::BenchParts),
+    ("merge", Command::Merge),
+    ("serve", Command::Serve),
+    ("worker", Command::Worker),
+    ("coordinate", Command::Coordinate),
+    ("count-stations", Command::CountStations),
+    ("diff", Command::Diff),
+    ("info", Command::Info),
+    ("help", Command::Help),
+];
+
+// Flags that set what `sniff` would otherwise guess
+const GUESSED: [&str; 6] = [
+    "--flexible-values",
+    "--delimiter",
+    "--key-col",
+    "--value-col",
+    "--time-col",
+    "--quotes",
+];
+
+// Flags without a value `--no-<flag>` turns off again
+const TOGGLES: [&str; 19] = [
+    "strict-utf8",
+    "lenient",
+    "flexible-values",
+    "quotes",
+    "desc",
+    "histogram",
+    "flag-outliers",
+    "stddev",
+    "follow",
+    "rescan",
+    "dry-run",
+    "cold",
+    "summary",
+    "quiet",
+    "debug-threads",
+    "hash-stats",
+    "estimate",
+    "numa",
+    "gpu",
+];
+
+// Flags that only apply to some commands
+const COMMAND_FLAGS: [(&str, &[Command]); 25] = [
+    ("--host", &[Command::Serve, Command::Worker, Command::Run]),
+    ("--port", &[Command::Serve, Command::Worker]),
+    ("--rescan", &[Command::Serve]),
+    ("--pipe", &[Command::Worker]),
+    ("--cpus", &[Command::Worker]),
+    ("--workers", &[Command::Coordinate]),
+    ("--runs", &[Command::Bench, Command::BenchParts]),
+    ("--warmup", &[Command::Bench]),
+    ("--matrix", &[Command::Bench]),
+    ("--tolerance", &[Command::Diff]),
+    ("--window", &[Command::Run]),
+    ("--rows", &[Command::Generate, Command::BenchParts]),
+    (
+        "--stations",
+        &[
+            Command::Generate,
+            Command::BenchParts,
+            Command::Run,
+            Command::Bench,
+        ],
+    ),
+    ("--seed", &[Command::Generate, Command::BenchParts]),
+    ("--reserve", &[Command::Run, Command::Bench]),
+    ("--spill", &[Command::Run]),
+    ("--max-stations", &[Command::Run, Command::Bench]),
+    ("--dedup", &[Command::Run, Command::Bench]),
+    ("--histogram", &[Command::Run]),
+    ("--bins", &[Command::Run]),
+    ("--flag-outliers", &[Command::Run]),
+    ("--sigmas", &[Command::Run]),
+    ("--metrics", &[Command::Run]),
+    ("--stddev", &[Command::Run]),
+    (
+        "--group-by",
+        &[Command::Run, Command::Bench, Command::Coordinate],
+    ),
+];
+
+impl Command {
+    pub fn name(self) -> &'static str {
+        COMMANDS
+            .iter()
+            .find(|&&(_, command)| command == self)
+            .map_or("run", |&(name, _)| name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+}
+
+impl Encoding {
+    // Convert a station name to UTF-8, borrowing it when it already is
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    pub fn decode(self, name: &[u8]) -> Cow<'_, [u8]> {
+        match self {
+            Encoding::Utf8 => Cow::Borrowed(name),
+            Encoding::Latin1 if name.is_ascii() => Cow::Borrowed(name),
+            Encoding::Latin1 => alloc_check::allow(|| {
+                let mut decoded = Vec::with_capacity(name.len() * 2);
+                for &byte in name {
+                    if byte < 0x80 {
+                        decoded.push(byte);
+                    } else {
+                        // Latin-1 maps directly to the first 256 code points
+                        decoded.push(0xC0 | (byte >> 6));
+                        decoded.push(0x80 | (byte & 0x3F));
+                    }
+                }
+                Cow::Owned(decoded)
+            }),
+        }
+    }
+}
+
+// Whether the first line names the columns instead of holding data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Header {
+    // If its value is not a number
+    Auto,
+    Present,
+    Absent,
+}
+
+// Zero based columns of lines with more than name and value, fields are
+// split at every delimiter outside of double quotes with `--quotes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Columns {
+    pub key: usize,
+    pub value: usize,
+    // Timestamps, only read with `--group-by`
+    pub time: Option<usize>,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self {
+            key: 0,
+            value: 1,
+            time: None,
+        }
+    }
+}
+
+// Periods of `--group-by`, stations are aggregated per prefix of their ISO
+// 8601 timestamps like `2024-03-15T12:00:00`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Period {
+    Year,
+    Month,
+    Day,
+    Hour,
+}
+
+impl Period {
+    // Bytes of a timestamp that name the period
+    pub fn prefix(self) -> usize {
+        match self {
+            Self::Year => 4,
+            Self::Month => 7,
+            Self::Day => 10,
+            Self::Hour => 13,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl Unit {
+    // Convert from Celsius, linear so it applies to the mean as is
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    pub fn convert(self, celsius: f64) -> f64 {
+        match self {
+            Unit::Celsius => celsius,
+            Unit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    // Convert a difference of degrees from Celsius, like a standard deviation
+    pub fn convert_difference(self, celsius: f64) -> f64 {
+        match self {
+            Unit::Celsius => celsius,
+            Unit::Fahrenheit => celsius * 9.0 / 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // `{name=min/max/mean, ...}` on a single line
+    Plain,
+    // Aligned columns with a header
+    Table,
+    // One JSON object per station and line
+    JsonLines,
+    // A header and one line per station
+    Csv,
+    // Arrow IPC file
+    Arrow,
+    Parquet,
+    // Rows appended to a `stations` table
+    Sqlite,
+}
+
+// A rolling window of `--follow`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sliding {
+    // Lines read within this long
+    Time(Duration),
+    // At least as many of the last lines
+    Rows(u64),
+}
+
+impl Sliding {
+    // A window like `5m` or `1000rows`, `None` for one in MiB
+    fn parse(window: &str) -> Option<Result<Self, String>> {
+        if let Some(rows) = window.strip_suffix("rows") {
+            let rows = rows
+                .parse::<u64>()
+                .ok()
+                .filter(|&rows| rows > 0)
+                .map(Self::Rows)
+                .ok_or_else(|| format!("window `{window}` is not a positive number of rows"));
+            return Some(rows);
+        }
+        let (count, seconds) = match window.char_indices().last()? {
+            (i, 's') => (&window[..i], 1.0),
+            (i, 'm') => (&window[..i], 60.0),
+            (i, 'h') => (&window[..i], 3600.0),
+            _ => return None,
+        };
+        let time = count
+            .parse::<f64>()
+            .ok()
+            .and_then(|count| Duration::try_from_secs_f64(count * seconds).ok())
+            .filter(|time| !time.is_zero())
+            .map(Self::Time)
+            .ok_or_else(|| format!("window `{window}` is not a positive duration"));
+        Some(time)
+    }
+}
+
+// A strategy `--matrix` compares all choices of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Axis {
+    Table,
+    Scanner,
+    Io,
+    // Powers of two up to one per core
+    Threads,
+}
+
+impl Axis {
+    pub fn name(self) -> &'static str {
+        match self {
+            Axis::Table => "table",
+            Axis::Scanner => "scanner",
+            Axis::Io => "io",
+            Axis::Threads => "threads",
+        }
+    }
+}
+
+// What to do with a line seen before, see `--dedup`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Dedup {
+    Skip,
+    // Aggregate it anyway
+    Count,
+}
+
+// Of the output file, see `--compress`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zstd,
+    Gzip,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    // Only when writing to a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Min,
+    Mean,
+    Max,
+    Count,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, String> {
+        match key {
+            "name" => Ok(SortKey::Name),
+            "min" => Ok(SortKey::Min),
+            "mean" => Ok(SortKey::Mean),
+            "max" => Ok(SortKey::Max),
+            "count" => Ok(SortKey::Count),
+            other => Err(format!("unknown sort key `{other}`")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collation {
+    // Byte-wise, which is code point order for UTF-8
+    Bytes,
+    // Unicode collation algorithm with the root locale
+    Unicode,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub command: Command,
+    pub file_name: String,
+    // Inputs of `merge`
+    pub states: Vec<String>,
+    // Expected and actual result of `diff`
+    pub results: Vec<String>,
+    pub tolerance: f64,
+    pub encoding: Encoding,
+    // Names that are not UTF-8 end the run instead of being replaced
+    pub strict_utf8: bool,
+    // Skip blank lines and `#` comments
+    pub lenient: bool,
+    // Duplicate lines, kept as they are if `None`
+    pub dedup: Option<Dedup>,
+    // Accept integers and up to two decimals instead of exactly one
+    pub flexible_values: bool,
+    pub delimiter: u8,
+    // Guess the delimiter and value format from the start of the file
+    pub sniff: bool,
+    // Only set for `--key-col` and `--value-col`, lines are exactly name and
+    // value otherwise
+    pub columns: Option<Columns>,
+    // Stations are aggregated per period of their timestamps
+    pub group_by: Option<Period>,
+    // Fields may be quoted, see `--quotes`
+    pub quotes: bool,
+    pub header: Header,
+    // Decimals in the output
+    pub precision: usize,
+    pub unit: Unit,
+    pub format: Format,
+    pub color: Color,
+    pub sort: Option<SortKey>,
+    pub descending: bool,
+    pub collation: Collation,
+    pub output: Option<String>,
+    pub compress: Option<Compression>,
+    pub save_state: Option<String>,
+    // Count values in bins instead of the statistics, see `Histogram`
+    pub histogram: bool,
+    pub bins: usize,
+    // Mark stations with values far from their mean, see `Spread`
+    pub flag_outliers: bool,
+    pub sigmas: f64,
+    // Write the standard deviation too, see `Spread`
+    pub stddev: bool,
+    pub follow: bool,
+    pub interval: Duration,
+    // Port to serve the result of `--follow` on, see `serve`
+    pub metrics: Option<u16>,
+    pub cache: Option<String>,
+    pub host: String,
+    pub port: u16,
+    pub rescan: bool,
+    pub workers: Vec<String>,
+    // Scan for newlines instead of aggregating
+    pub dry_run: bool,
+    // Evict the input from the page cache before reading it
+    pub cold: bool,
+    // Totals and throughput on stderr
+    pub summary: bool,
+    // JSON report of the run
+    pub report: Option<String>,
+    // Counts of every value over all stations, as CSV
+    pub distribution: Option<String>,
+    // How much is logged to stderr, 0 for only warnings
+    pub verbosity: u8,
+    // Not even warnings and status lines on stderr, only errors
+    pub quiet: bool,
+    // Rows, bytes, time and stations of each thread on stderr
+    pub debug_threads: bool,
+    // Hash table diagnostics on stderr, also compares names on every line
+    pub hash_stats: bool,
+    // SVG the stacks sampled during the run are written to
+    pub flamegraph: Option<String>,
+    pub numa: bool,
+    pub gpu: bool,
+    // Defaults to the available parallelism
+    pub threads: Option<usize>,
+    pub io: Io,
+    // Bytes of the file in memory at a time, all of it if `None`
+    pub window: Option<usize>,
+    // Lines `--follow` keeps aggregates of, all of them if `None`
+    pub sliding: Option<Sliding>,
+    // Bytes the counted allocations may take, see `memory`
+    pub max_memory: Option<usize>,
+    // Directory for tables that outgrow their budget, see `spill`
+    pub spill: Option<String>,
+    // Distinct stations a run may have, any number if `None`
+    pub max_stations: Option<usize>,
+    // Bytes ahead of each chunk whose pages a thread touches in advance
+    pub prefetch: Option<usize>,
+    // Detected at runtime if `None`, see `scan`
+    pub scanner: Option<Scanner>,
+    pub table: Table,
+    // Tables of `--table sharded`
+    pub shards: usize,
+    // Known stations, looked up with a perfect hash, see `--stations`
+    pub station_list: Option<Arc<StationList>>,
+    // Keys of the stations to reserve table entries for, see `--reserve`
+    pub reserved: Option<Arc<[u64]>>,
+    // Size the tables for the stations of a sample, see `estimate`
+    pub estimate: bool,
+    pub madvise: Option<Advice>,
+    // Check sums for overflow in release builds too
+    pub strict: bool,
+    // Worker answering a single task on stdin, see `--numa`
+    pub pipe: bool,
+    pub cpus: Option<String>,
+    // Timed and untimed runs of `bench`
+    pub runs: usize,
+    pub warmup: usize,
+    // Strategies `bench` varies, a single run of the chosen ones if empty
+    pub matrix: Vec<Axis>,
+    // What `generate` writes
+    pub rows: u64,
+    pub stations: usize,
+    pub seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            command: Command::Run,
+            file_name: String::new(),
+            states: Vec::new(),
+            results: Vec::new(),
+            tolerance: 0.1,
+            encoding: Encoding::Utf8,
+            strict_utf8: false,
+            lenient: false,
+            dedup: None,
+            flexible_values: false,
+            delimiter: b';',
+            sniff: true,
+            columns: None,
+            group_by: None,
+            quotes: false,
+            header: Header::Auto,
+            precision: 1,
+            unit: Unit::Celsius,
+            format: Format::Plain,
+            color: Color::Auto,
+            sort: None,
+            descending: false,
+            collation: Collation::Bytes,
+            output: None,
+            compress: None,
+            save_state: None,
+            histogram: false,
+            bins: 20,
+            flag_outliers: false,
+            sigmas: 5.0,
+            stddev: false,
+            follow: false,
+            interval: Duration::from_secs(1),
+            metrics: None,
+            cache: None,
+            host: "127.0.0.1".into(),
+            port: 8080,
+            rescan: false,
+            workers: Vec::new(),
+            dry_run: false,
+            cold: false,
+            summary: false,
+            report: None,
+            distribution: None,
+            verbosity: 0,
+            quiet: false,
+            debug_threads: false,
+            hash_stats: false,
+            flamegraph: None,
+            numa: false,
+            gpu: false,
+            threads: None,
+            io: Io::default(),
+            window: None,
+            sliding: None,
+            max_memory: None,
+            spill: None,
+            max_stations: None,
+            prefetch: None,
+            scanner: None,
+            table: Table::default(),
+            shards: SHARDS,
+            station_list: None,
+            reserved: None,
+            estimate: false,
+            madvise: None,
+            strict: false,
+            pipe: false,
+            cpus: None,
+            runs: 10,
+            warmup: 2,
+            matrix: Vec::new(),
+            rows: 1_000_000_000,
+            stations: 413,
+            seed: 1,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut file_name = None;
+        let mut args = args.peekable();
+        let command = args
+            .peek()
+            .and_then(|arg| COMMANDS.iter().find(|(name, _)| name == arg))
+            .map(|&(_, command)| command);
+        if let Some(command) = command {
+            options.command = command;
+            args.next();
+        }
+        // Generated in memory, and each stage is timed again and again
+        if options.command == Command::BenchParts {
+            options.rows = 1_000_000;
+        }
+
+        // Options from the config file come first, then the environment, so
+        // the command line wins
+        let mut args = args.collect::<Vec<_>>();
+        let config = match args.iter().position(|arg| arg == "--config") {
+            Some(i) if i + 1 < args.len() => {
+                let path = args.remove(i + 1);
+                args.remove(i);
+                Some(path)
+            }
+            Some(_) => return Err("missing value for `--config`".into()),
+            None => Path::new(config::DEFAULT_PATH)
+                .exists()
+                .then(|| config::DEFAULT_PATH.to_string()),
+        };
+        args.splice(0..0, config::env_args());
+        if let Some(path) = config {
+            let command = options.command.name();
+            args.splice(0..0, config::args(Path::new(&path), command)?);
+        }
+        // A negated flag drops the flag wherever it came before
+        let mut kept = Vec::with_capacity(args.len());
+        for arg in args {
+            match arg
+                .strip_prefix("--no-")
+                .filter(|flag| TOGGLES.contains(flag))
+            {
+                Some(flag) => {
+                    kept.retain(|earlier: &String| earlier.strip_prefix("--") != Some(flag))
+                }
+                None => kept.push(arg),
+            }
+        }
+        let mut args = kept.into_iter();
+
+        while let Some(arg) = args.next() {
+            if let Some((_, commands)) = COMMAND_FLAGS.iter().find(|(flag, _)| *flag == arg) {
+                if !commands.contains(&options.command) {
+                    let command = options.command.name();
+                    return Err(format!("`{arg}` does not apply to `{command}`"));
+                }
+            }
+            if GUESSED.contains(&arg.as_str()) {
+                options.sniff = false;
+            }
+            match arg.as_str() {
+                "--help" | "-h" => {
+                    options.command = Command::Help;
+                    return Ok(options);
+                }
+                "--encoding" => {
+                    options.encoding = match value(&mut args, &arg)?.as_str() {
+                        "utf8" | "utf-8" => Encoding::Utf8,
+                        "latin1" | "iso-8859-1" => Encoding::Latin1,
+                        other => return Err(format!("unknown encoding `{other}`")),
+                    }
+                }
+                "--threads" => {
+                    options.threads = Some(
+                        value(&mut args, &arg)?
+                            .parse()
+                            .ok()
+                            .filter(|&threads| threads > 0)
+                            .ok_or("threads must be a positive number")?,
+                    );
+                }
+                "--io" => {
+                    options.io = match value(&mut args, &arg)?.as_str() {
+                        "mmap" => Io::Mmap,
+                        "read" => Io::Read,
+                        other => return Err(format!("unknown I/O backend `{other}`")),
+                    }
+                }
+                "--window" => {
+                    let window = value(&mut args, &arg)?;
+                    if let Some(sliding) = Sliding::parse(&window) {
+                        options.sliding = Some(sliding?);
+                        continue;
+                    }
+                    let mib = window
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&mib| mib > 0)
+                        .ok_or("the window must be a positive number of MiB")?;
+                    options.window =
+                        Some(mib.checked_mul(1 << 20).ok_or("the window is too large")?);
+                }
+                "--max-memory" => {
+                    let mib = value(&mut args, &arg)?
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&mib| mib > 0)
+                        .ok_or("the memory limit must be a positive number of MiB")?;
+                    options.max_memory = Some(
+                        mib.checked_mul(1 << 20)
+                            .ok_or("the memory limit is too large")?,
+                    );
+                }
+                "--max-stations" => {
+                    options.max_stations = Some(
+                        value(&mut args, &arg)?
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|&stations| stations > 0)
+                            .ok_or("the station limit must be a positive number")?,
+                    );
+                }
+                "--prefetch" => {
+                    let mib = value(&mut args, &arg)?
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&mib| mib > 0)
+                        .ok_or("the prefetch distance must be a positive number of MiB")?;
+                    options.prefetch = Some(
+                        mib.checked_mul(1 << 20)
+                            .ok_or("the prefetch distance is too large")?,
+                    );
+                }
+                "--scanner" => {
+                    let scanner = value(&mut args, &arg)?.parse::<Scanner>()?;
+                    if !scanner.is_supported() {
+                        return Err(format!(
+                            "this CPU does not support the {} scanner",
+                            scanner.name()
+                        ));
+                    }
+                    options.scanner = Some(scanner);
+                }
+                "--table" => options.table = value(&mut args, &arg)?.parse()?,
+                "--shards" => {
+                    options.shards = value(&mut args, &arg)?
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&shards| shards.is_power_of_two() && shards <= 256)
+                        .ok_or("the shards must be a power of two up to 256")?;
+                }
+                "--madvise" => {
+                    options.madvise = Some(match value(&mut args, &arg)?.as_str() {
+                        "normal" => Advice::Normal,
+                        "sequential" => Advice::Sequential,
+                        "random" => Advice::Random,
+                        "willneed" => Advice::WillNeed,
+                        "hugepage" => Advice::HugePage,
+                        other => return Err(format!("unknown madvise strategy `{other}`")),
+                    })
+                }
+                "--strict-utf8" => options.strict_utf8 = true,
+                "--lenient" => options.lenient = true,
+                "--dedup" => {
+                    options.dedup = Some(match value(&mut args, &arg)?.as_str() {
+                        "skip" => Dedup::Skip,
+                        "count" => Dedup::Count,
+                        other => return Err(format!("unknown duplicate handling `{other}`")),
+                    })
+                }
+                "--flexible-values" => options.flexible_values = true,
+                "--delimiter" => {
+                    options.delimiter = match value(&mut args, &arg)?.as_str() {
+                        "\\t" | "tab" => b'\t',
+                        other if other.len() == 1 => other.as_bytes()[0],
+                        other => return Err(format!("delimiter `{other}` is not a single byte")),
+                    }
+                }
+                "--key-col" => {
+                    options.columns.get_or_insert_with(Columns::default).key =
+                        value(&mut args, &arg)?
+                            .parse()
+                            .map_err(|_| "key column must be an index")?;
+                }
+                "--value-col" => {
+                    options.columns.get_or_insert_with(Columns::default).value =
+                        value(&mut args, &arg)?
+                            .parse()
+                            .map_err(|_| "value column must be an index")?;
+                }
+                "--time-col" => {
+                    options.columns.get_or_insert_with(Columns::default).time = Some(
+                        value(&mut args, &arg)?
+                            .parse()
+                            .map_err(|_| "time column must be an index")?,
+                    );
+                }
+                "--group-by" => {
+                    let keys = value(&mut args, &arg)?;
+                    options.group_by = match keys.strip_prefix("station") {
+                        Some("") => None,
+                        Some(",year") => Some(Period::Year),
+                        Some(",month") => Some(Period::Month),
+                        Some(",day") => Some(Period::Day),
+                        Some(",hour") => Some(Period::Hour),
+                        _ => return Err(format!("unknown grouping `{keys}`")),
+                    }
+                }
+                "--quotes" => options.quotes = true,
+                "--no-sniff" => options.sniff = false,
+                "--header" => options.header = Header::Present,
+                "--no-header" => options.header = Header::Absent,
+                "--precision" => {
+                    options.precision = value(&mut args, &arg)?
+                        .parse()
+                        .map_err(|_| "precision must be a number of digits")?;
+                }
+                "--unit" => {
+                    options.unit = match value(&mut args, &arg)?.as_str() {
+                        "c" | "celsius" => Unit::Celsius,
+                        "f" | "fahrenheit" => Unit::Fahrenheit,
+                        other => return Err(format!("unknown unit `{other}`")),
+                    }
+                }
+                "--format" => {
+                    options.format = match value(&mut args, &arg)?.as_str() {
+                        "plain" => Format::Plain,
+                        "table" => Format::Table,
+                        "jsonl" => Format::JsonLines,
+                        "csv" => Format::Csv,
+                        "arrow" if cfg!(feature = "arrow") => Format::Arrow,
+                        "arrow" => return Err("built without the `arrow` feature".into()),
+                        "parquet" if cfg!(feature = "parquet") => Format::Parquet,
+                        "parquet" => return Err("built without the `parquet` feature".into()),
+                        "sqlite" if cfg!(feature = "sqlite") => Format::Sqlite,
+                        "sqlite" => return Err("built without the `sqlite` feature".into()),
+                        other => return Err(format!("unknown format `{other}`")),
+                    }
+                }
+                "--color" => {
+                    options.color = match value(&mut args, &arg)?.as_str() {
+                        "auto" => Color::Auto,
+                        "always" => Color::Always,
+                        "never" => Color::Never,
+                        other => return Err(format!("unknown color choice `{other}`")),
+                    }
+                }
+                "--sort" => {
+                    options.sort = Some(value(&mut args, &arg)?.parse()?);
+                }
+                "--desc" => options.descending = true,
+                "--collate" => {
+                    options.collation = match value(&mut args, &arg)?.as_str() {
+                        "bytes" => Collation::Bytes,
+                        "unicode" if cfg!(feature = "collate") => Collation::Unicode,
+                        "unicode" => return Err("built without the `collate` feature".into()),
+                        other => return Err(format!("unknown collation `{other}`")),
+                    }
+                }
+                "--output" => options.output = Some(value(&mut args, &arg)?),
+                "--compress" => {
+                    options.compress = Some(match value(&mut args, &arg)?.as_str() {
+                        "zstd" if cfg!(feature = "zstd") => Compression::Zstd,
+                        "zstd" => return Err("built without the `zstd` feature".into()),
+                        "gzip" if cfg!(feature = "gzip") => Compression::Gzip,
+                        "gzip" => return Err("built without the `gzip` feature".into()),
+                        other => return Err(format!("unknown compression `{other}`")),
+                    })
+                }
+                "--save-state" => options.save_state = Some(value(&mut args, &arg)?),
+                "--histogram" => options.histogram = true,
+                "--bins" => {
+                    options.bins = value(&mut args, &arg)?
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|bins| (1..=2000).contains(bins))
+                        .ok_or("the bins must be a number from 1 to 2000")?;
+                }
+                "--flag-outliers" => options.flag_outliers = true,
+                "--stddev" => options.stddev = true,
+                "--sigmas" => {
+                    options.sigmas = value(&mut args, &arg)?
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|sigmas| sigmas.is_finite() && *sigmas > 0.0)
+                        .ok_or("the sigmas must be a positive number")?;
+                }
+                "--follow" => options.follow = true,
+                "--cache" => options.cache = Some(value(&mut args, &arg)?),
+                "--spill" => options.spill = Some(value(&mut args, &arg)?),
+                "--host" => options.host = value(&mut args, &arg)?,
+                "--port" => {
+                    options.port = value(&mut args, &arg)?
+                        .parse()
+                        .map_err(|_| "port must be a number")?;
+                }
+                "--metrics" => {
+                    let port = value(&mut args, &arg)?.parse();
+                    options.metrics = Some(port.map_err(|_| "port must be a number")?);
+                }
+                "--rescan" => options.rescan = true,
+                "--dry-run" => options.dry_run = true,
+                "--cold" => options.cold = true,
+                "--summary" => options.summary = true,
+                "--report" => options.report = Some(value(&mut args, &arg)?),
+                "--distribution" => options.distribution = Some(value(&mut args, &arg)?),
+                "-v" | "--verbose" => options.verbosity += 1,
+                "-vv" => options.verbosity += 2,
+                "-q" | "--quiet" => options.quiet = true,
+                "--debug-threads" => options.debug_threads = true,
+                "--hash-stats" => options.hash_stats = true,
+                "--flamegraph" if cfg!(all(feature = "flamegraph", unix)) => {
+                    options.flamegraph = Some(value(&mut args, &arg)?)
+                }
+                "--flamegraph" => return Err("built without the `flamegraph` feature".into()),
+                "--estimate" => options.estimate = true,
+                "--numa" => options.numa = true,
+                "--gpu" if cfg!(feature = "gpu") => options.gpu = true,
+                "--gpu" => return Err("built without the `gpu` feature".into()),
+                "--pipe" => options.pipe = true,
+                "--cpus" => options.cpus = Some(value(&mut args, &arg)?),
+                "--runs" => {
+                    options.runs = value(&mut args, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|&runs| runs > 0)
+                        .ok_or("runs must be a positive number")?;
+                }
+                "--warmup" => {
+                    options.warmup = value(&mut args, &arg)?
+                        .parse()
+                        .map_err(|_| "warmup must be a number of runs")?;
+                }
+                "--matrix" => {
+                    for axis in value(&mut args, &arg)?.split(',') {
+                        let axis = [Axis::Table, Axis::Scanner, Axis::Io, Axis::Threads]
+                            .into_iter()
+                            .find(|known| known.name() == axis)
+                            .ok_or_else(|| format!("unknown matrix axis `{axis}`"))?;
+                        if !options.matrix.contains(&axis) {
+                            options.matrix.push(axis);
+                        }
+                    }
+                }
+                "--rows" => {
+                    options.rows = value(&mut args, &arg)?
+                        .parse()
+                        .map_err(|_| "rows must be a number")?;
+                }
+                "--stations"
+                    if !matches!(options.command, Command::Generate | Command::BenchParts) =>
+                {
+                    let path = value(&mut args, &arg)?;
+                    let list = StationList::read(&path)
+                        .map_err(|error| format!("failed to read `{path}`: {error}"))?;
+                    options.station_list = Some(Arc::new(list));
+                }
+                "--reserve" => {
+                    let path = value(&mut args, &arg)?;
+                    let keys = perfect::read_keys(&path)
+                        .map_err(|error| format!("failed to read `{path}`: {error}"))?;
+                    options.reserved = Some(keys.into());
+                }
+                "--stations" => {
+                    options.stations = value(&mut args, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|stations| (1..=10_000).contains(stations))
+                        .ok_or("stations must be a number from 1 to 10000")?;
+                }
+                "--seed" => {
+                    options.seed = value(&mut args, &arg)?
+                        .parse()
+                        .map_err(|_| "seed must be a number")?;
+                }
+                "--tolerance" => {
+                    options.tolerance = value(&mut args, &arg)?
+                        .parse()
+                        .ok()
+                        .filter(|tolerance: &f64| *tolerance >= 0.0)
+                        .ok_or("tolerance must be a non-negative number")?;
+                }
+                "--workers" => {
+                    options.workers = value(&mut args, &arg)?
+                        .split(',')
+                        .map(String::from)
+                        .collect();
+                }
+                "--interval" => {
+                    options.interval = value(&mut args, &arg)?
+                        .parse()
+                        .ok()
+                        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                        .ok_or("interval must be a number of seconds")?;
+                }
+                flag if flag.starts_with("--") => {
+                    return Err(format!("unknown option `{flag}`"));
+                }
+                _ if options.command == Command::Merge => options.states.push(arg),
+                _ if options.command == Command::Diff && options.results.len() < 2 => {
+                    options.results.push(arg)
+                }
+                _ if file_name.is_none() => file_name = Some(arg),
+                _ => return Err(format!("unexpected argument `{arg}`")),
+            }
+        }
+
+        if options.quiet && options.verbosity > 0 {
+            return Err("--quiet and --verbose contradict each other".into());
+        }
+        if options.follow && options.command != Command::Run {
+            return Err("--follow only applies to aggregating a file".into());
+        }
+        if options.sliding.is_some() && !options.follow {
+            return Err("a --window of time or rows needs --follow".into());
+        }
+        if options.metrics.is_some() && !options.follow {
+            return Err("--metrics needs --follow".into());
+        }
+        if options.dedup.is_some() {
+            // Their duplicates would go uncounted
+            let uncounted = [
+                ("--follow", options.follow),
+                ("--cache", options.cache.is_some()),
+                ("--numa", options.numa),
+            ];
+            if let Some((flag, _)) = uncounted.into_iter().find(|(_, set)| *set) {
+                return Err(format!(
+                    "--dedup counts the lines of a single run, drop {flag}"
+                ));
+            }
+        }
+        if options.follow && options.numa {
+            return Err("--follow runs in a single process, so it cannot use --numa".into());
+        }
+        let single_run = [
+            ("--summary", options.summary),
+            ("--dry-run", options.dry_run),
+            ("--report", options.report.is_some()),
+            ("--distribution", options.distribution.is_some()),
+        ];
+        for (flag, set) in single_run {
+            if set && (options.command != Command::Run || options.follow) {
+                return Err(format!(
+                    "{flag} only applies to a single aggregation of a file"
+                ));
+            }
+        }
+        if options.window.is_some() {
+            if let Some(flag) = options.whole_file() {
+                return Err(format!("{flag} needs the whole file, not a --window"));
+            }
+        }
+        match options.command {
+            Command::Run
+            | Command::Validate
+            | Command::Inspect
+            | Command::Serve
+            | Command::Coordinate
+            | Command::CountStations
+            | Command::Bench => options.file_name = file_name.ok_or("missing input file")?,
+            Command::Generate => options.file_name = file_name.ok_or("missing output file")?,
+            Command::Merge if options.states.is_empty() => {
+                return Err("missing states to merge".into())
+            }
+            Command::Worker if file_name.is_some() => {
+                return Err("workers get their files from the coordinator".into())
+            }
+            Command::BenchParts if file_name.is_some() => {
+                return Err("bench-parts generates its own rows, see --rows".into())
+            }
+            Command::Diff if options.results.len() < 2 => {
+                return Err("diff needs an expected and an actual result".into())
+            }
+            Command::Merge
+            | Command::Worker
+            | Command::Diff
+            | Command::BenchParts
+            | Command::Info
+            | Command::Help => {}
+        }
+        if options.command == Command::Coordinate && options.workers.is_empty() {
+            return Err("coordinate needs --workers".into());
+        }
+        if options
+            .columns
+            .is_some_and(|columns| columns.key == columns.value)
+        {
+            return Err("key and value must be different columns".into());
+        }
+        if let Some(columns) = options.columns {
+            if columns.time == Some(columns.key) || columns.time == Some(columns.value) {
+                return Err("time must be a column of its own".into());
+            }
+        }
+        if options.group_by.is_some() {
+            if options.columns.and_then(|columns| columns.time).is_none() {
+                return Err("--group-by station,<period> needs a --time-col".into());
+            }
+            // Tables that only know names of stations
+            let by_name = [
+                ("--stations", options.station_list.is_some()),
+                ("--reserve", options.reserved.is_some()),
+                ("--gpu", options.gpu),
+            ];
+            if let Some((flag, _)) = by_name.into_iter().find(|(_, set)| *set) {
+                return Err(format!("{flag} does not apply to --group-by"));
+            }
+        }
+        if options.station_list.is_some() && options.table != Table::Robin {
+            return Err("--stations brings its own table, drop --table".into());
+        }
+        if options.matrix.contains(&Axis::Table) {
+            let own_table = [
+                ("--stations", options.station_list.is_some()),
+                ("--reserve", options.reserved.is_some()),
+                ("--gpu", options.gpu),
+            ];
+            if let Some((flag, _)) = own_table.into_iter().find(|(_, set)| *set) {
+                return Err(format!("--matrix table compares the tables, drop {flag}"));
+            }
+        }
+        if options.reserved.is_some()
+            && (options.station_list.is_some() || options.table != Table::Robin)
+        {
+            return Err("--reserve only sizes the own table, drop --stations and --table".into());
+        }
+        if options.spill.is_some() {
+            let own_table = [
+                ("--table", options.table != Table::Robin),
+                ("--stations", options.station_list.is_some()),
+                ("--reserve", options.reserved.is_some()),
+                ("--follow", options.follow),
+                ("--numa", options.numa),
+                ("--gpu", options.gpu),
+            ];
+            if let Some((flag, _)) = own_table.into_iter().find(|(_, set)| *set) {
+                return Err(format!(
+                    "--spill aggregates into the own tables, drop {flag}"
+                ));
+            }
+        }
+        if options.strict_utf8 && options.encoding == Encoding::Latin1 {
+            return Err("--strict-utf8 checks names read as UTF-8, drop --encoding latin1".into());
+        }
+        if options.max_stations.is_some() {
+            // Tables the limit is not checked in
+            let uncounted = [("--numa", options.numa), ("--gpu", options.gpu)];
+            if let Some((flag, _)) = uncounted.into_iter().find(|(_, set)| *set) {
+                return Err(format!("--max-stations does not apply to {flag}"));
+            }
+        }
+        if options.histogram {
+            if !matches!(options.format, Format::JsonLines | Format::Csv) {
+                return Err("--histogram is written with --format jsonl or csv".into());
+            }
+            let statistics = [
+                ("--spill", options.spill.is_some()),
+                ("--follow", options.follow),
+                ("--save-state", options.save_state.is_some()),
+                ("--cache", options.cache.is_some()),
+                ("--sort", options.sort.is_some()),
+                ("--dry-run", options.dry_run),
+                ("--summary", options.summary),
+                ("--report", options.report.is_some()),
+                ("--distribution", options.distribution.is_some()),
+                ("--flag-outliers", options.flag_outliers),
+                ("--stddev", options.stddev),
+                ("--numa", options.numa),
+                ("--gpu", options.gpu),
+            ];
+            if let Some((flag, _)) = statistics.into_iter().find(|(_, set)| *set) {
+                return Err(format!("{flag} does not apply to --histogram"));
+            }
+        }
+        let text = matches!(
+            options.format,
+            Format::Plain | Format::Table | Format::JsonLines | Format::Csv
+        );
+        if options.flag_outliers && !text {
+            return Err(
+                "--flag-outliers is written with --format plain, table, jsonl or csv".into(),
+            );
+        }
+        if options.stddev && !matches!(options.format, Format::Arrow | Format::Parquet) {
+            return Err("--stddev is written with --format arrow or parquet".into());
+        }
+        let moments = [
+            ("--flag-outliers", options.flag_outliers),
+            ("--stddev", options.stddev),
+        ];
+        if let Some((moments, _)) = moments.into_iter().find(|(_, set)| *set) {
+            // Runs that only keep the statistics of the challenge
+            let deviations = [
+                ("--spill", options.spill.is_some()),
+                ("--follow", options.follow),
+                ("--cache", options.cache.is_some()),
+                ("--numa", options.numa),
+                ("--gpu", options.gpu),
+            ];
+            if let Some((flag, _)) = deviations.into_iter().find(|(_, set)| *set) {
+                return Err(format!("{flag} does not apply to {moments}"));
+            }
+        }
+        if options.format == Format::Sqlite && options.output.is_none() {
+            return Err("the sqlite format needs an --output database".into());
+        }
+        if options.compress.is_some() {
+            if options.output.is_none() {
+                return Err("--compress needs an --output file".into());
+            }
+            if options.format == Format::Sqlite {
+                return Err("a sqlite database cannot be compressed".into());
+            }
+        }
+        Ok(options)
+    }
+
+    // The first flag that needs the whole file at once, so no `--window`
+    pub fn whole_file(&self) -> Option<&'static str> {
+        [
+            ("--follow", self.follow),
+            ("--dry-run", self.dry_run),
+            ("--summary", self.summary),
+            ("--report", self.report.is_some()),
+            ("--distribution", self.distribution.is_some()),
+            ("--cache", self.cache.is_some()),
+            ("--numa", self.numa),
+            ("--gpu", self.gpu),
+            ("--table", self.table != Table::Robin),
+            ("--stations", self.station_list.is_some()),
+            ("--reserve", self.reserved.is_some()),
+            ("--estimate", self.estimate),
+            ("--spill", self.spill.is_some()),
+            ("--histogram", self.histogram),
+            ("--flag-outliers", self.flag_outliers),
+            ("--stddev", self.stddev),
+        ]
+        .into_iter()
+        .find_map(|(flag, set)| set.then_some(flag))
+    }
+
+    // Parsed values are fixed point numbers with this divisor
+    pub fn scale(&self) -> f64 {
+        if self.flexible_values {
+            100.0
+        } else {
+            10.0
+        }
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("missing value for `{flag}`"))
+}
@@ -0,0 +1,67 @@
+//! Stations with values far from their mean, see `--flag-outliers`, and
+//! the standard deviations of `--stddev`
+//!
+//! The file is aggregated with `Moments`, which keeps a sum of squares
+//! besides the statistics of the challenge. Those are handed on as usual,
+//! with the names of the stations whose minimum or maximum is more than
+//! `--sigmas` standard deviations from their mean, which usually means a
+//! misplaced decimal point or a wrong unit rather than weather.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+};
+
+use log::info;
+
+use onebrc::{
+    aggregate_with,
+    aggregator::{Aggregator, Moments, Station},
+    hash_table::HashTable,
+    options::Options,
+    Skipped, StationStats,
+};
+
+// What the sums of squares tell about the stations, by name
+#[derive(Default)]
+pub struct Spreads {
+    // Marked with `--flag-outliers`
+    pub outliers: BTreeSet<Vec<u8>>,
+    // In the input unit, only kept with `--stddev`
+    pub deviations: BTreeMap<Vec<u8>, f64>,
+}
+
+pub fn aggregate<'a>(
+    data: &'a [u8],
+    options: &'a Options,
+) -> io::Result<(HashTable<StationStats<'a>>, Spreads, Skipped)> {
+    let mut spreads = Spreads::default();
+    let (result, skipped) = aggregate_with::<Moments>(data, options)?;
+    let result = result
+        .into_iter()
+        .map(|(key, station)| {
+            let spread = station.stats.finalize(options);
+            if options.flag_outliers && spread.is_outlier(options.sigmas) {
+                spreads.outliers.insert(station.name.to_vec());
+            }
+            if options.stddev {
+                spreads
+                    .deviations
+                    .insert(station.name.to_vec(), spread.deviation);
+            }
+            let stats = Station {
+                name: station.name,
+                stats: station.stats.stats,
+            };
+            (key, StationStats::from(stats))
+        })
+        .collect();
+    if options.flag_outliers {
+        info!(
+            "{} stations with values more than {} standard deviations from their mean",
+            spreads.outliers.len(),
+            options.sigmas
+        );
+    }
+    Ok((result, spreads, skipped))
+}
@@ -0,0 +1,436 @@
+//! Result formatting
+
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    fs::{self, File},
+    io::{self, BufWriter, IsTerminal, Write},
+    path::Path,
+    process,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use onebrc::{
+    hash_table::HashTable,
+    options::{Collation, Color, Format, Options, SortKey},
+    StationStats,
+};
+
+#[cfg(feature = "arrow")]
+mod arrow;
+mod compress;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[34m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+// Final values of a station, converted to the output unit. The name borrows
+// from the station unless it is not valid UTF-8.
+#[derive(Clone)]
+pub struct Row<'a> {
+    pub name: Cow<'a, str>,
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+    pub count: u32,
+    // Marked by `--flag-outliers`
+    pub outlier: bool,
+    // Standard deviation in the output unit, written with `--stddev`, which
+    // only the Arrow formats take
+    #[cfg_attr(not(feature = "arrow"), allow(dead_code))]
+    pub deviation: f64,
+}
+
+impl<'a> Row<'a> {
+    pub fn new(stats: &'a StationStats, options: &Options) -> Self {
+        let scale = options.scale();
+        let convert = |value: f64| options.unit.convert(value / scale);
+        Self {
+            name: String::from_utf8_lossy(&stats.name),
+            min: convert(stats.min as f64),
+            mean: convert(stats.sum as f64 / stats.count as f64),
+            max: convert(stats.max as f64),
+            count: stats.count,
+            outlier: false,
+            deviation: 0.0,
+        }
+    }
+
+    // For rows kept after the stations are gone
+    pub fn into_owned(self) -> Row<'static> {
+        Row {
+            name: Cow::Owned(self.name.into_owned()),
+            ..self
+        }
+    }
+}
+
+// Sort by the given key, ties are broken by name so the order is stable
+pub fn sort(rows: &mut [Row], key: SortKey, options: &Options) {
+    let names = name_order(options.collation);
+    rows.sort_unstable_by(|a, b| {
+        let ordering = match key {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Min => a.min.total_cmp(&b.min),
+            SortKey::Mean => a.mean.total_cmp(&b.mean),
+            SortKey::Max => a.max.total_cmp(&b.max),
+            SortKey::Count => a.count.cmp(&b.count),
+        }
+        .then_with(|| names(&a.name, &b.name));
+        if options.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+type NameOrder = Box<dyn Fn(&str, &str) -> Ordering>;
+
+fn name_order(collation: Collation) -> NameOrder {
+    match collation {
+        Collation::Bytes => Box::new(|a: &str, b: &str| a.cmp(b)),
+        #[cfg(feature = "collate")]
+        Collation::Unicode => {
+            use icu_collator::{options::CollatorOptions, Collator};
+
+            let collator = Collator::try_new(Default::default(), CollatorOptions::default())
+                .expect("collation data is compiled in");
+            // Fully equal under collation, e.g. differently normalized names
+            Box::new(move |a: &str, b: &str| collator.compare(a, b).then_with(|| a.cmp(b)))
+        }
+        #[cfg(not(feature = "collate"))]
+        Collation::Unicode => unreachable!("rejected while parsing options"),
+    }
+}
+
+// Write to the output file if there is one, stdout otherwise
+pub fn emit(rows: &[Row], options: &Options) -> io::Result<()> {
+    match &options.output {
+        #[cfg(feature = "sqlite")]
+        Some(path) if options.format == Format::Sqlite => {
+            sqlite::write(rows, options, Path::new(path))
+        }
+        Some(path) => write_atomically(Path::new(path), |out| {
+            compress::write(options.compress, out, |mut out| {
+                write(rows, options, &mut out)
+            })
+        }),
+        None => {
+            let mut out = BufWriter::new(io::stdout().lock());
+            write(rows, options, &mut out)?;
+            out.flush()
+        }
+    }
+}
+
+// Formats written row by row, so rows already in their final order can be
+// written as they are made instead of collected first. Tables need the
+// widest row and the binary formats whole batches.
+pub fn streams(format: Format) -> bool {
+    matches!(format, Format::Plain | Format::JsonLines | Format::Csv)
+}
+
+// Like `emit`, for a format that `streams`
+pub fn emit_streamed<'a>(rows: impl Iterator<Item = Row<'a>>, options: &Options) -> io::Result<()> {
+    let write = |out: &mut dyn Write| match options.format {
+        Format::Plain => write_plain(rows, options, out),
+        Format::JsonLines => write_json_lines(rows, options, out),
+        Format::Csv => write_csv(rows, options, out),
+        _ => unreachable!("only formats that stream"),
+    };
+    emit_with(options, write)
+}
+
+// Write with `write` to the output file if there is one, compressed if asked
+// to, stdout otherwise
+pub fn emit_with(
+    options: &Options,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    match &options.output {
+        Some(path) => write_atomically(Path::new(path), |out| {
+            compress::write(options.compress, out, write)
+        }),
+        None => {
+            let mut out = BufWriter::new(io::stdout().lock());
+            write(&mut out)?;
+            out.flush()
+        }
+    }
+}
+
+pub fn write(rows: &[Row], options: &Options, out: &mut impl Write) -> io::Result<()> {
+    match options.format {
+        Format::Plain => write_plain(rows.iter().cloned(), options, out),
+        Format::JsonLines => write_json_lines(rows.iter().cloned(), options, out),
+        Format::Csv => write_csv(rows.iter().cloned(), options, out),
+        #[cfg(feature = "arrow")]
+        Format::Arrow => arrow::write(rows, options.stddev, out),
+        #[cfg(not(feature = "arrow"))]
+        Format::Arrow => unreachable!("rejected while parsing options"),
+        #[cfg(feature = "parquet")]
+        Format::Parquet => parquet::write(rows, options.stddev, out),
+        #[cfg(not(feature = "parquet"))]
+        Format::Parquet => unreachable!("rejected while parsing options"),
+        // Needs a database file, see `emit`
+        Format::Sqlite => unreachable!("rejected while parsing options"),
+        Format::Table => {
+            let color = match options.color {
+                Color::Auto => options.output.is_none() && io::stdout().is_terminal(),
+                Color::Always => true,
+                Color::Never => false,
+            };
+            write_table(rows, options, color, out)
+        }
+    }
+}
+
+// Write to a temporary file next to `path` and rename it into place, so
+// readers never see a partially written result
+pub fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.tmp", process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = File::create(&temp_path).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write(&mut out)?;
+        let file = out.into_inner().map_err(|error| error.into_error())?;
+        file.sync_all()
+    });
+    match result.and_then(|_| fs::rename(&temp_path, path)) {
+        Ok(()) => Ok(()),
+        Err(error) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(error)
+        }
+    }
+}
+
+fn write_plain<'a>(
+    rows: impl IntoIterator<Item = Row<'a>>,
+    options: &Options,
+    out: &mut (impl Write + ?Sized),
+) -> io::Result<()> {
+    let precision = options.precision;
+    write!(out, "{{")?;
+    for (i, row) in rows.into_iter().enumerate() {
+        if i > 0 {
+            write!(out, ", ")?;
+        }
+        let Row {
+            name,
+            min,
+            mean,
+            max,
+            outlier,
+            ..
+        } = row;
+        write!(
+            out,
+            "{name}={min:.precision$}/{max:.precision$}/{mean:.precision$}"
+        )?;
+        if outlier {
+            write!(out, " (outlier)")?;
+        }
+    }
+    writeln!(out, "}}")
+}
+
+// A row as JSON, values rounded to the output precision
+#[derive(Serialize)]
+pub struct JsonRow<'a> {
+    name: &'a str,
+    min: f64,
+    mean: f64,
+    max: f64,
+    count: u32,
+    // Only with `--flag-outliers`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outlier: Option<bool>,
+}
+
+impl<'a> JsonRow<'a> {
+    pub fn new(row: &'a Row<'a>, options: &Options) -> Self {
+        let factor = 10f64.powi(options.precision as i32);
+        let round = |value: f64| (value * factor).round() / factor;
+        Self {
+            name: &row.name,
+            min: round(row.min),
+            mean: round(row.mean),
+            max: round(row.max),
+            count: row.count,
+            outlier: options.flag_outliers.then_some(row.outlier),
+        }
+    }
+}
+
+// Totals over all stations and how fast `data` was aggregated
+pub fn write_summary(
+    result: &HashTable<StationStats>,
+    data: &[u8],
+    elapsed: Duration,
+    options: &Options,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let (mut rows, mut sum, mut min, mut max) = (0u64, 0f64, i32::MAX, i32::MIN);
+    let mut stations = 0;
+    for (_, stats) in result.key_set() {
+        rows += stats.count as u64;
+        sum += stats.sum as f64;
+        min = min.min(stats.min);
+        max = max.max(stats.max);
+        stations += 1;
+    }
+    // Blank lines, comments and the header
+    let mut lines = data.iter().filter(|&&c| c == b'\n').count() as u64;
+    if !data.is_empty() && !data.ends_with(b"\n") {
+        lines += 1;
+    }
+    let skipped = lines.saturating_sub(rows);
+
+    let precision = options.precision;
+    let convert = |value: f64| options.unit.convert(value / options.scale());
+    writeln!(out, "rows: {rows}")?;
+    writeln!(out, "stations: {stations}")?;
+    writeln!(out, "skipped lines: {skipped}")?;
+    if rows > 0 {
+        let (min, mean, max) = (
+            convert(min as f64),
+            convert(sum / rows as f64),
+            convert(max as f64),
+        );
+        writeln!(
+            out,
+            "min/mean/max: {min:.precision$}/{mean:.precision$}/{max:.precision$}"
+        )?;
+    }
+    let seconds = elapsed.as_secs_f64();
+    writeln!(
+        out,
+        "time: {seconds:.3}s, {:.0} rows/s, {:.2} GB/s",
+        rows as f64 / seconds,
+        data.len() as f64 / seconds / 1e9
+    )
+}
+
+// One object per line
+fn write_json_lines<'a>(
+    rows: impl IntoIterator<Item = Row<'a>>,
+    options: &Options,
+    out: &mut (impl Write + ?Sized),
+) -> io::Result<()> {
+    for row in rows {
+        serde_json::to_writer(&mut *out, &JsonRow::new(&row, options))?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+// A header, then one line per station
+fn write_csv<'a>(
+    rows: impl IntoIterator<Item = Row<'a>>,
+    options: &Options,
+    out: &mut (impl Write + ?Sized),
+) -> io::Result<()> {
+    let precision = options.precision;
+    let flagged = options.flag_outliers;
+    let outlier = if flagged { ",outlier" } else { "" };
+    writeln!(out, "station,min,mean,max,count{outlier}")?;
+    for row in rows {
+        let Row {
+            name,
+            min,
+            mean,
+            max,
+            count,
+            outlier,
+            ..
+        } = row;
+        write!(
+            out,
+            "{},{min:.precision$},{mean:.precision$},{max:.precision$},{count}",
+            csv_field(&name)
+        )?;
+        if flagged {
+            write!(out, ",{outlier}")?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+// In double quotes if it contains a comma, quote or line break
+pub fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+fn write_table(
+    rows: &[Row],
+    options: &Options,
+    color: bool,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let precision = options.precision;
+    let values = |row: &Row| {
+        [
+            format!("{:.precision$}", row.min),
+            format!("{:.precision$}", row.mean),
+            format!("{:.precision$}", row.max),
+            row.count.to_string(),
+        ]
+    };
+    let header = ["Station", "Min", "Mean", "Max", "Count"];
+
+    // Column widths in characters, names may contain multi-byte characters
+    let mut widths = header.map(|title| title.chars().count());
+    for row in rows {
+        widths[0] = widths[0].max(row.name.chars().count());
+        for (width, value) in widths[1..].iter_mut().zip(values(row)) {
+            *width = (*width).max(value.len());
+        }
+    }
+
+    let (bold, blue, red, reset) = if color {
+        (BOLD, BLUE, RED, RESET)
+    } else {
+        ("", "", "", "")
+    };
+    write!(out, "{bold}{:<1$}", header[0], widths[0])?;
+    for (title, width) in header[1..].iter().zip(&widths[1..]) {
+        write!(out, "  {title:>width$}")?;
+    }
+    if options.flag_outliers {
+        write!(out, "  Outlier")?;
+    }
+    writeln!(out, "{reset}")?;
+
+    for row in rows {
+        let [min, mean, max, count] = values(row);
+        write!(out, "{:<1$}", row.name, widths[0])?;
+        write!(out, "  {blue}{min:>0$}{reset}", widths[1])?;
+        write!(out, "  {mean:>0$}", widths[2])?;
+        write!(out, "  {red}{max:>0$}{reset}", widths[3])?;
+        write!(out, "  {count:>0$}", widths[4])?;
+        if row.outlier {
+            write!(out, "  {bold}yes{reset}")?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
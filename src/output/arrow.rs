@@ -0,0 +1,57 @@
+//! Arrow IPC file output, readable with `pyarrow.feather` or polars
+
+use std::{
+    io::{self, Write},
+    sync::Arc,
+};
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, UInt32Array};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+
+use super::Row;
+
+pub fn write(rows: &[Row], stddev: bool, out: &mut impl Write) -> io::Result<()> {
+    let batch = record_batch(rows, stddev).map_err(io_error)?;
+    let mut writer = FileWriter::try_new(out, &batch.schema()).map_err(io_error)?;
+    writer.write(&batch).map_err(io_error)?;
+    writer.finish().map_err(io_error)
+}
+
+pub fn io_error(error: ArrowError) -> io::Error {
+    match error {
+        ArrowError::IoError(_, error) => error,
+        error => io::Error::other(error),
+    }
+}
+
+// All rows as one batch with columns name, min, mean, max and count, and
+// stddev if asked for
+pub fn record_batch(rows: &[Row], stddev: bool) -> Result<RecordBatch, ArrowError> {
+    let mut fields = vec![
+        Field::new("name", DataType::Utf8, false),
+        Field::new("min", DataType::Float64, false),
+        Field::new("mean", DataType::Float64, false),
+        Field::new("max", DataType::Float64, false),
+        Field::new("count", DataType::UInt32, false),
+    ];
+    let floats = |value: fn(&Row) -> f64| -> ArrayRef {
+        Arc::new(rows.iter().map(value).collect::<Float64Array>())
+    };
+    let mut columns = vec![
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|row| &row.name),
+        )) as ArrayRef,
+        floats(|row| row.min),
+        floats(|row| row.mean),
+        floats(|row| row.max),
+        Arc::new(UInt32Array::from_iter_values(
+            rows.iter().map(|row| row.count),
+        )),
+    ];
+    if stddev {
+        fields.push(Field::new("stddev", DataType::Float64, false));
+        columns.push(floats(|row| row.deviation));
+    }
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+}
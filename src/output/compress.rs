@@ -0,0 +1,33 @@
+//! Compressed output files, see `--compress`
+
+use std::io::{self, Write};
+
+use onebrc::options::Compression;
+
+// Write with `write` through the compressor of `compression`, if there is one,
+// and finish its stream
+pub fn write(
+    compression: Option<Compression>,
+    out: &mut impl Write,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    match compression {
+        None => write(out),
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd) => {
+            let mut encoder = zstd::Encoder::new(out, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            write(&mut encoder)?;
+            encoder.finish().map(drop)
+        }
+        #[cfg(not(feature = "zstd"))]
+        Some(Compression::Zstd) => unreachable!("rejected while parsing options"),
+        #[cfg(feature = "gzip")]
+        Some(Compression::Gzip) => {
+            let mut encoder = flate2::write::GzEncoder::new(out, flate2::Compression::default());
+            write(&mut encoder)?;
+            encoder.finish().map(drop)
+        }
+        #[cfg(not(feature = "gzip"))]
+        Some(Compression::Gzip) => unreachable!("rejected while parsing options"),
+    }
+}
@@ -0,0 +1,29 @@
+//! Parquet output, using the same columns as the Arrow output
+
+use std::io::{self, Write};
+
+use parquet::{
+    arrow::ArrowWriter, basic::Compression, errors::ParquetError,
+    file::properties::WriterProperties,
+};
+
+use super::{arrow, Row};
+
+pub fn write(rows: &[Row], stddev: bool, out: &mut impl Write) -> io::Result<()> {
+    let batch = arrow::record_batch(rows, stddev).map_err(arrow::io_error)?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+
+    // The writer needs a `Send` sink, which locked stdout is not
+    let mut buffer = Vec::new();
+    let mut writer =
+        ArrowWriter::try_new(&mut buffer, batch.schema(), Some(properties)).map_err(io_error)?;
+    writer.write(&batch).map_err(io_error)?;
+    writer.close().map_err(io_error)?;
+    out.write_all(&buffer)
+}
+
+fn io_error(error: ParquetError) -> io::Error {
+    io::Error::other(error)
+}
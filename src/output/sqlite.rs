@@ -0,0 +1,55 @@
+//! SQLite output, every run appends its rows to the `stations` table
+
+use std::{io, path::Path};
+
+use rusqlite::{params, Connection};
+
+use onebrc::options::Options;
+
+use super::Row;
+
+pub fn write(rows: &[Row], options: &Options, path: &Path) -> io::Result<()> {
+    insert(rows, options, path).map_err(io::Error::other)
+}
+
+fn insert(rows: &[Row], options: &Options, path: &Path) -> rusqlite::Result<()> {
+    let mut connection = Connection::open(path)?;
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS stations (
+            run INTEGER NOT NULL,
+            file TEXT NOT NULL,
+            name TEXT NOT NULL,
+            min REAL NOT NULL,
+            mean REAL NOT NULL,
+            max REAL NOT NULL,
+            count INTEGER NOT NULL,
+            PRIMARY KEY (run, name)
+        )",
+    )?;
+
+    // A single transaction, so a run is either fully recorded or not at all
+    let transaction = connection.transaction()?;
+    let run: i64 = transaction.query_row(
+        "SELECT COALESCE(MAX(run), 0) + 1 FROM stations",
+        [],
+        |row| row.get(0),
+    )?;
+    {
+        let mut statement = transaction.prepare(
+            "INSERT INTO stations (run, file, name, min, mean, max, count)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        for row in rows {
+            statement.execute(params![
+                run,
+                options.file_name,
+                row.name,
+                row.min,
+                row.mean,
+                row.max,
+                row.count,
+            ])?;
+        }
+    }
+    transaction.commit()
+}
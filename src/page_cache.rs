@@ -0,0 +1,26 @@
+//! Page cache control for cold benchmarks
+//!
+//! Asking the kernel to drop the cached pages of the input makes the next
+//! run read it from the disk again. Pages that are dirty or mapped by
+//! another process stay cached.
+
+use std::{fs::File, io};
+
+#[cfg(target_os = "linux")]
+pub fn evict(file: &File) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    // The whole file, a length of 0 extends to its end
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+        0 => Ok(()),
+        error => Err(io::Error::from_raw_os_error(error)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn evict(_file: &File) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "evicting files from the page cache needs Linux",
+    ))
+}
@@ -0,0 +1,292 @@
+//! Stages of the aggregation timed one by one, see `bench-parts`
+//!
+//! Every stage runs over all lines on the calling thread, with what the
+//! stages before it produce prepared up front, so a change to one of them
+//! shows in its own time instead of drowning in the others. The last stage
+//! is the whole loop for comparison, merging is timed with a table per
+//! thread there would be. Inserting is also timed into a table of chained
+//! buckets like the one the robin hood table replaced, as a baseline.
+
+use std::{hint::black_box, time::Instant};
+
+use crate::{
+    aggregator::{Aggregator, MinMeanMax, Station},
+    hash_table::HashTable,
+    line_ranges,
+    options::Options,
+    scan::{self, Scalar, Scan},
+    station_name, thread_count, Chunk,
+};
+
+// The median of the timed runs of a stage
+pub struct Stage {
+    pub name: &'static str,
+    pub seconds: f64,
+    // Lines, or stations for merging, the stage went through per run
+    pub items: u64,
+}
+
+// Time each stage `options.runs` times over `data` with the scanner of
+// `options`
+pub fn time(data: &[u8], options: &Options) -> Vec<Stage> {
+    let scanner = options
+        .scanner
+        .filter(|scanner| scanner.is_supported())
+        .unwrap_or_else(scan::detect);
+    match scanner {
+        #[cfg(target_arch = "x86_64")]
+        scan::Scanner::Avx512 => unsafe { time_avx512(data, options) },
+        #[cfg(target_arch = "x86_64")]
+        scan::Scanner::Avx2 => unsafe { time_avx2(data, options) },
+        #[cfg(target_arch = "aarch64")]
+        scan::Scanner::Neon => unsafe { time_neon(data, options) },
+        _ => time_with::<Scalar>(data, options),
+    }
+}
+
+// Like the loop of a chunk, the stages are compiled for their scanner
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn time_avx2(data: &[u8], options: &Options) -> Vec<Stage> {
+    time_with::<scan::Avx2>(data, options)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,avx512f,avx512bw")]
+unsafe fn time_avx512(data: &[u8], options: &Options) -> Vec<Stage> {
+    time_with::<scan::Avx512>(data, options)
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn time_neon(data: &[u8], options: &Options) -> Vec<Stage> {
+    time_with::<scan::Neon>(data, options)
+}
+
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn time_with<S: Scan>(data: &[u8], options: &Options) -> Vec<Stage> {
+    // A single chunk of all lines, only its parsing is used
+    let lines = line_ranges(data, 1, options).pop().unwrap_or(0..1);
+    let chunk = || Chunk::<MinMeanMax>::new(data, options, lines.start, lines.end - 1, None);
+    let mut stages = Vec::new();
+    let mut stage = |name, run: &mut dyn FnMut() -> u64| {
+        let (seconds, items) = median(options.runs, run);
+        stages.push(Stage {
+            name,
+            seconds,
+            items,
+        });
+    };
+
+    stage("newline scan", &mut || {
+        let (mut position, mut newlines) = (lines.start, 0);
+        while position < lines.end {
+            position = S::find(data, position, b'\n') + 1;
+            newlines += 1;
+        }
+        newlines
+    });
+
+    let mut records = Vec::new();
+    stage("field split", &mut || {
+        let mut chunk = chunk();
+        records.clear();
+        while chunk.position < chunk.end {
+            records.extend(chunk.split_record::<S>());
+        }
+        records.len() as u64
+    });
+
+    let parser = chunk();
+    let mut values = Vec::with_capacity(records.len());
+    stage("value parse", &mut || {
+        values.clear();
+        values.extend(
+            records
+                .iter()
+                .map(|&(_, value)| match options.flexible_values {
+                    true => parser.parse_flexible_value(value),
+                    false => parser.parse_value(value),
+                }),
+        );
+        values.len() as u64
+    });
+
+    let mut keys = Vec::with_capacity(records.len());
+    stage("hash", &mut || {
+        keys.clear();
+        keys.extend(records.iter().map(|&(name, _)| parser.key::<S>(name)));
+        keys.len() as u64
+    });
+
+    // Names are attached to the keys they were hashed to
+    let inserts = || {
+        keys.iter()
+            .zip(&records)
+            .zip(&values)
+            .map(|((&key, &(name, _)), &value)| (key, name, value))
+    };
+    stage("table insert", &mut || {
+        let mut table = HashTable::new();
+        insert(&mut table, inserts(), options);
+        black_box(table);
+        records.len() as u64
+    });
+
+    stage("chained insert", &mut || {
+        let mut table = Chained::new();
+        insert(&mut table, inserts(), options);
+        black_box(table);
+        records.len() as u64
+    });
+
+    // What each thread would have aggregated, in turn
+    let threads = thread_count(options).max(1);
+    let part = records.len().div_ceil(threads).max(1);
+    let tables = inserts()
+        .collect::<Vec<_>>()
+        .chunks(part)
+        .map(|part| {
+            let mut table = HashTable::new();
+            insert(&mut table, part.iter().copied(), options);
+            table
+        })
+        .collect::<Vec<_>>();
+    stage("merge", &mut || {
+        let mut merged = HashTable::<Station<MinMeanMax>>::new();
+        for table in &tables {
+            for (key, station) in table.key_set() {
+                merged.insert_or_update(
+                    key,
+                    |merged| merged.name == station.name,
+                    |merged| merged.stats.merge(&station.stats, options),
+                    || station.clone(),
+                );
+            }
+        }
+        tables.iter().map(|table| table.len() as u64).sum()
+    });
+
+    stage("all of a line", &mut || {
+        let mut chunk = chunk();
+        chunk.parse_lines_with::<S>(usize::MAX);
+        black_box(chunk.result).len();
+        chunk.rows
+    });
+    stages
+}
+
+// Add the values to their stations like a chunk does
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn insert<'a>(
+    table: &mut impl Table<Station<'a, MinMeanMax>>,
+    inserts: impl Iterator<Item = (u64, &'a [u8], i32)>,
+    options: &Options,
+) {
+    for (key, name, value) in inserts {
+        table.insert_or_update(
+            key,
+            |station| *station.name == *name,
+            |station| station.stats.update(value, options),
+            || {
+                let mut stats = MinMeanMax::new();
+                stats.update(value, options);
+                Station {
+                    name: station_name(name, options),
+                    stats,
+                }
+            },
+        );
+    }
+}
+
+// What `insert` needs of a table
+trait Table<T> {
+    fn insert_or_update(
+        &mut self,
+        key: u64,
+        is: impl FnMut(&T) -> bool,
+        modify: impl FnOnce(&mut T),
+        provide: impl FnOnce() -> T,
+    );
+}
+
+impl<T> Table<T> for HashTable<T> {
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn insert_or_update(
+        &mut self,
+        key: u64,
+        is: impl FnMut(&T) -> bool,
+        modify: impl FnOnce(&mut T),
+        provide: impl FnOnce() -> T,
+    ) {
+        HashTable::insert_or_update(self, key, is, modify, provide);
+    }
+}
+
+// A bucket of entries per home slot, grown at 3/4 load
+struct Chained<T> {
+    buckets: Vec<Vec<(u64, T)>>,
+    size: usize,
+}
+
+impl<T> Chained<T> {
+    fn new() -> Self {
+        Self::with_buckets(1 << 16)
+    }
+
+    fn with_buckets(buckets: usize) -> Self {
+        Self {
+            buckets: (0..buckets).map(|_| Vec::new()).collect(),
+            size: 0,
+        }
+    }
+}
+
+impl<T> Table<T> for Chained<T> {
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn insert_or_update(
+        &mut self,
+        key: u64,
+        mut is: impl FnMut(&T) -> bool,
+        modify: impl FnOnce(&mut T),
+        provide: impl FnOnce() -> T,
+    ) {
+        if self.size >= self.buckets.len() * 3 / 4 {
+            let mut resized = Self::with_buckets(self.buckets.len() * 2);
+            for (key, value) in self.buckets.drain(..).flatten() {
+                let index = key as usize % resized.buckets.len();
+                resized.buckets[index].push((key, value));
+            }
+            resized.size = self.size;
+            *self = resized;
+        }
+        let index = key as usize % self.buckets.len();
+        let bucket = &mut self.buckets[index];
+        match bucket.iter_mut().find(|(k, v)| *k == key && is(v)) {
+            Some((_, value)) => modify(value),
+            None => {
+                bucket.push((key, provide()));
+                self.size += 1;
+            }
+        }
+    }
+}
+
+// Median seconds of `runs` calls of `run`, with what the last one returned
+fn median(runs: usize, run: &mut dyn FnMut() -> u64) -> (f64, u64) {
+    let mut items = 0;
+    let mut seconds = (0..runs.max(1))
+        .map(|_| {
+            let started = Instant::now();
+            items = black_box(run());
+            started.elapsed().as_secs_f64()
+        })
+        .collect::<Vec<_>>();
+    seconds.sort_unstable_by(f64::total_cmp);
+    let median = match seconds.len() {
+        n if n % 2 == 0 => (seconds[n / 2 - 1] + seconds[n / 2]) / 2.0,
+        n => seconds[n / 2],
+    };
+    (median, items)
+}
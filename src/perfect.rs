@@ -0,0 +1,218 @@
+//! Perfect hashing of a known station list
+//!
+//! With `--stations <path>` the names of all stations are known up front,
+//! so their keys are placed into slots with hash and displace: keys are
+//! split into buckets, and each bucket, the fullest first, gets the first
+//! seed that hashes all its keys to free slots. A lookup then reads the
+//! seed of its bucket and the key of its slot, without probing. Names that
+//! are not on the list still work, they go to an ordinary table.
+
+use std::{fs, io, path::Path, sync::Arc};
+
+use crate::{
+    hash_table::{self, HashTable},
+    options::Options,
+    scan::{self, Scalar},
+    table::Backend,
+};
+
+// Seeds tried per bucket before giving up, at half load a few do
+const ATTEMPTS: u32 = 1 << 20;
+
+#[derive(Debug)]
+pub struct StationList {
+    // Seed of each bucket
+    seeds: Box<[u32]>,
+    // Key placed in each slot, free slots hold a key that does not hash to
+    // them, so they never match
+    keys: Box<[u64]>,
+    len: usize,
+}
+
+impl StationList {
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_keys(read_keys(path)?)
+            .ok_or_else(|| io::Error::other("found no perfect hash for the stations"))
+    }
+
+    pub fn new<'a>(names: impl IntoIterator<Item = &'a [u8]>) -> Option<Self> {
+        Self::from_keys(keys(names))
+    }
+
+    fn from_keys(keys: Vec<u64>) -> Option<Self> {
+        // At least two, so a free slot has keys that hash elsewhere
+        let slots = (keys.len() * 2).max(2).next_power_of_two();
+        let count = keys.len().div_ceil(2).next_power_of_two();
+        let mut buckets = vec![Vec::new(); count];
+        for &key in &keys {
+            buckets[bucket(key, count)].push(key);
+        }
+        let mut order = (0..buckets.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|&bucket| usize::MAX - buckets[bucket].len());
+
+        let mut seeds = vec![0; buckets.len()].into_boxed_slice();
+        // A free slot gets a key of another slot, which does not hash to it
+        let mut placed = vec![None; slots];
+        let mut positions = Vec::new();
+        for bucket in order {
+            let found = (0..ATTEMPTS).find(|&seed| {
+                positions.clear();
+                buckets[bucket].iter().all(|&key| {
+                    let position = slot(key, seed, slots);
+                    let free = placed[position].is_none() && !positions.contains(&position);
+                    positions.push(position);
+                    free
+                })
+            })?;
+            seeds[bucket] = found;
+            for (&key, &position) in buckets[bucket].iter().zip(&positions) {
+                placed[position] = Some(key);
+            }
+        }
+        let keys = placed
+            .iter()
+            .enumerate()
+            .map(|(position, key)| key.unwrap_or_else(|| free_key(position, &seeds, slots)))
+            .collect();
+        Some(Self {
+            seeds,
+            keys,
+            len: buckets.iter().map(Vec::len).sum(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Where `key` is if it is on the list
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn slot(&self, key: u64) -> usize {
+        let seed = self.seeds[bucket(key, self.seeds.len())];
+        slot(key, seed, self.keys.len())
+    }
+}
+
+// Keys of the names in a file, one per line, optionally followed by `;`
+// and anything, like the station list of the challenge. Blank lines and `#`
+// comments are skipped.
+pub fn read_keys(path: impl AsRef<Path>) -> io::Result<Vec<u64>> {
+    let data = fs::read(path)?;
+    let names = data
+        .split(|&c| c == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .map(|line| line.split(|&c| c == b';').next().unwrap());
+    Ok(keys(names))
+}
+
+// The keys the aggregation loop computes, which every scanner agrees on,
+// without duplicates
+fn keys<'a>(names: impl IntoIterator<Item = &'a [u8]>) -> Vec<u64> {
+    let mut keys = names
+        .into_iter()
+        .map(scan::key::<Scalar>)
+        .collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys.dedup();
+    keys
+}
+
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn bucket(key: u64, buckets: usize) -> usize {
+    (scan::mix(key) >> 32) as usize & (buckets - 1)
+}
+
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn slot(key: u64, seed: u32, slots: usize) -> usize {
+    let seed = (seed as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    ((scan::mix(key) ^ seed).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize & (slots - 1)
+}
+
+// Some key that does not hash to `position`
+fn free_key(position: usize, seeds: &[u32], slots: usize) -> u64 {
+    (0..)
+        .find(|&key| slot(key, seeds[bucket(key, seeds.len())], slots) != position)
+        .unwrap()
+}
+
+// Stations of the list in their slots, others in an ordinary table
+pub(crate) struct Perfect<T> {
+    list: Arc<StationList>,
+    slots: Box<[Option<T>]>,
+    rest: HashTable<T>,
+}
+
+impl<T: Send> Backend<T> for Perfect<T> {
+    fn new(options: &Options, _: Option<usize>) -> Self {
+        let list = Arc::clone(
+            options
+                .station_list
+                .as_ref()
+                .expect("checked by the caller"),
+        );
+        let slots = (0..list.keys.len()).map(|_| None).collect();
+        Self {
+            list,
+            slots,
+            rest: HashTable::new(),
+        }
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn insert_or_update(
+        &mut self,
+        key: u64,
+        mut is: impl FnMut(&T) -> bool,
+        modify: impl FnOnce(&mut T),
+        provide: impl FnOnce() -> T,
+    ) {
+        let slot = self.list.slot(key);
+        if self.list.keys[slot] != key {
+            return self.rest.insert_or_update(key, is, modify, provide);
+        }
+        // The slot is taken by the first station of its key, others with it
+        // go to the rest
+        match &mut self.slots[slot] {
+            Some(value) if is(value) => modify(value),
+            Some(_) => self.rest.insert_or_update(key, is, modify, provide),
+            empty => *empty = Some(provide()),
+        }
+    }
+
+    fn entries<'t>(&'t self) -> impl Iterator<Item = (u64, &'t T)>
+    where
+        T: 't,
+    {
+        self.slots
+            .iter()
+            .zip(&self.list.keys)
+            .filter_map(|(value, &key)| Some((key, value.as_ref()?)))
+            .chain(self.rest.key_set())
+    }
+
+    // The probes are those of the stations not on the list
+    fn stats(&self) -> hash_table::Stats {
+        let rest = self.rest.stats();
+        hash_table::Stats {
+            len: self.slots.iter().flatten().count() + rest.len,
+            capacity: self.slots.len() + rest.capacity,
+            ..rest
+        }
+    }
+
+    fn into_table(self) -> HashTable<T> {
+        let keys = self.list.keys.iter().copied();
+        self.slots
+            .into_vec()
+            .into_iter()
+            .zip(keys)
+            .filter_map(|(value, key)| Some((key, value?)))
+            .chain(self.rest)
+            .collect()
+    }
+}
@@ -0,0 +1,46 @@
+//! Reused read buffers
+//!
+//! Windows read into memory are all about the same size and only a few are
+//! alive at a time, see `aggregate_windowed`, so the buffers of dropped ones
+//! are kept to read later ones into instead of allocating and freeing one
+//! per window. The last thread done with a window is rarely the one that
+//! reads the next, so the pool is shared, not thread local. Kept buffers
+//! stay counted in use, see `memory`.
+
+use std::{io, sync::Mutex};
+
+use crate::memory;
+
+// Buffers kept, enough for the windows in flight
+const KEPT: usize = 4;
+
+static FREE: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+// A buffer of `len` zeroed bytes, one of the kept ones if there is any
+pub(crate) fn take(len: usize, what: &str) -> io::Result<Vec<u8>> {
+    let mut buffer = FREE.lock().unwrap().pop().unwrap_or_default();
+    let capacity = buffer.capacity();
+    if len > capacity {
+        if let Err(error) = memory::check(len - capacity, what) {
+            give(buffer);
+            return Err(error);
+        }
+        buffer.clear();
+        buffer.reserve_exact(len);
+        memory::allocate(buffer.capacity() - capacity);
+    }
+    buffer.clear();
+    buffer.resize(len, 0);
+    Ok(buffer)
+}
+
+// Keep `buffer` for later, unless enough are kept already
+pub(crate) fn give(buffer: Vec<u8>) {
+    let mut free = FREE.lock().unwrap();
+    if free.len() < KEPT {
+        free.push(buffer);
+    } else {
+        drop(free);
+        memory::free(buffer.capacity());
+    }
+}
@@ -0,0 +1,243 @@
+//! Library entry point
+//!
+//! ```no_run
+//! use onebrc::{Io, Processor};
+//!
+//! let processor = Processor::builder().threads(8).io(Io::Mmap).strict(true).build();
+//! let stations = processor.process("measurements.txt")?;
+//!
+//! for (name, stats) in onebrc::process_iter("measurements.txt")? {
+//!     println!("{name}: {} measurements", stats.count);
+//! }
+//!
+//! // Custom aggregation over every record, values are in tenths
+//! let mut hot = 0;
+//! processor.visit("measurements.txt", |_name, value| hot += (value > 300) as u64)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{fs::File, io, path::Path};
+
+use crate::{
+    aggregate, aggregate_windowed, aggregate_with,
+    aggregator::{Aggregator, Station},
+    hash_table::HashTable,
+    options::{Columns, Dedup, Encoding, Header, Options, Period},
+    scan::Scanner,
+    table::Table,
+    visit, visit_parallel, Io, StationStats,
+};
+
+// Aggregates files with a fixed configuration
+#[derive(Debug, Clone)]
+pub struct Processor {
+    options: Options,
+}
+
+impl Processor {
+    pub fn builder() -> ProcessorBuilder {
+        ProcessorBuilder::default()
+    }
+
+    // Aggregate the file at `path`
+    pub fn process(&self, path: impl AsRef<Path>) -> io::Result<HashTable<StationStats<'static>>> {
+        let file = File::open(path)?;
+        if let Some(window) = self.options.window {
+            let (result, _) = aggregate_windowed(&file, window, &self.options)?;
+            return Ok(result);
+        }
+        let input = self.options.io.load(&file)?;
+        Ok(self
+            .process_bytes(&input)?
+            .into_iter()
+            .map(|(key, stats)| (key, stats.into_owned()))
+            .collect())
+    }
+
+    // Aggregate the file at `path` and yield its stations sorted by name
+    pub fn process_iter(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> io::Result<impl Iterator<Item = (String, StationStats<'static>)>> {
+        let mut stations = self
+            .process(path)?
+            .into_iter()
+            .map(|(_, stats)| stats)
+            .collect::<Vec<_>>();
+        // Bytes sort like their UTF-8 strings, names only become strings as
+        // they are yielded
+        stations.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        Ok(stations
+            .into_iter()
+            .map(|stats| (String::from_utf8_lossy(&stats.name).into_owned(), stats)))
+    }
+
+    // Aggregate the file at `path` with custom statistics
+    pub fn process_with<A: Aggregator>(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> io::Result<HashTable<Station<'static, A>>> {
+        let input = self.options.io.load(&File::open(path)?)?;
+        let (result, _) = aggregate_with::<A>(&input, &self.options)?;
+        Ok(result
+            .into_iter()
+            .map(|(key, station)| (key, station.into_owned()))
+            .collect())
+    }
+
+    // Call `visitor` with the raw name and value of every line of the file,
+    // see `onebrc::visit`
+    pub fn visit(&self, path: impl AsRef<Path>, visitor: impl FnMut(&[u8], i32)) -> io::Result<()> {
+        let input = self.options.io.load(&File::open(path)?)?;
+        visit(&input, &self.options, visitor)
+    }
+
+    // Visit chunks of the file in parallel, see `onebrc::visit_parallel`
+    pub fn visit_parallel<S: Send>(
+        &self,
+        path: impl AsRef<Path>,
+        init: impl Fn() -> S + Sync,
+        visitor: impl Fn(&mut S, &[u8], i32) + Sync,
+    ) -> io::Result<Vec<S>> {
+        let input = self.options.io.load(&File::open(path)?)?;
+        visit_parallel(&input, &self.options, init, visitor)
+    }
+
+    // Aggregate lines already in memory, names borrow from them
+    pub fn process_bytes<'a>(&'a self, data: &'a [u8]) -> io::Result<HashTable<StationStats<'a>>> {
+        aggregate(data, &self.options)
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+}
+
+impl Default for Processor {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+// `Processor::process_iter` with the default configuration
+pub fn process_iter(
+    path: impl AsRef<Path>,
+) -> io::Result<impl Iterator<Item = (String, StationStats<'static>)>> {
+    Processor::default().process_iter(path)
+}
+
+// Everything defaults to what the command line does without options
+#[derive(Debug, Clone, Default)]
+pub struct ProcessorBuilder {
+    options: Options,
+}
+
+impl ProcessorBuilder {
+    // Number of chunks aggregated in parallel
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = Some(threads.max(1));
+        self
+    }
+
+    pub fn io(mut self, io: Io) -> Self {
+        self.options.io = io;
+        self
+    }
+
+    // Aggregate with this scanner instead of the widest the CPU supports,
+    // unsupported ones are ignored
+    pub fn scanner(mut self, scanner: Scanner) -> Self {
+        self.options.scanner = Some(scanner);
+        self
+    }
+
+    // Aggregate chunks into this table, a baseline for the own one. Windows
+    // always use the own table.
+    pub fn table(mut self, table: Table) -> Self {
+        self.options.table = table;
+        self
+    }
+
+    // Touch pages this many bytes ahead of each thread in the background
+    pub fn prefetch(mut self, bytes: usize) -> Self {
+        self.options.prefetch = Some(bytes.max(1));
+        self
+    }
+
+    // Load `process` inputs this many bytes at a time, see
+    // `onebrc::aggregate_windowed`
+    pub fn window(mut self, bytes: usize) -> Self {
+        self.options.window = Some(bytes.max(1));
+        self
+    }
+
+    // Panic when a sum overflows instead of wrapping in release builds
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.options.encoding = encoding;
+        self
+    }
+
+    // Skip blank lines and `#` comments
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.options.lenient = lenient;
+        self
+    }
+
+    // Skip or only count lines a thread saw before, see `--dedup`
+    pub fn dedup(mut self, dedup: Dedup) -> Self {
+        self.options.dedup = Some(dedup);
+        self
+    }
+
+    // Accept integers and up to two decimals, sums are hundredths then
+    pub fn flexible_values(mut self, flexible_values: bool) -> Self {
+        self.options.flexible_values = flexible_values;
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.options.delimiter = delimiter;
+        self
+    }
+
+    // Zero based columns of name and value in lines with more than those
+    pub fn columns(mut self, key: usize, value: usize) -> Self {
+        let time = self.options.columns.and_then(|columns| columns.time);
+        self.options.columns = Some(Columns { key, value, time });
+        self
+    }
+
+    // Aggregate each station per period of the timestamps in column `time`,
+    // see `--group-by`
+    pub fn group_by(mut self, time: usize, period: Period) -> Self {
+        self.options
+            .columns
+            .get_or_insert_with(Columns::default)
+            .time = Some(time);
+        self.options.group_by = Some(period);
+        self
+    }
+
+    // Allow fields in double quotes, see `--quotes`
+    pub fn quotes(mut self, quotes: bool) -> Self {
+        self.options.quotes = quotes;
+        self
+    }
+
+    // Skip the first line, detected by default
+    pub fn header(mut self, header: Header) -> Self {
+        self.options.header = header;
+        self
+    }
+
+    pub fn build(self) -> Processor {
+        Processor {
+            options: self.options,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! A flamegraph of the run, see `--flamegraph`
+//!
+//! The stacks of all threads are sampled while the command runs and written
+//! once it returns, runs that end with an error write nothing. Built with
+//! the `flamegraph` feature, which implies `profiling`, so the stages of the
+//! loop show as frames of their own.
+
+use std::{fs::File, path::PathBuf};
+
+use log::{info, warn};
+use pprof::{ProfilerGuard, ProfilerGuardBuilder};
+
+// Samples per second, prime so they do not line up with periodic work
+const FREQUENCY: i32 = 997;
+
+pub struct Profile {
+    guard: ProfilerGuard<'static>,
+    path: PathBuf,
+}
+
+// Start sampling, a run that cannot be profiled still runs
+pub fn start(path: &str) -> Option<Profile> {
+    let guard = ProfilerGuardBuilder::default()
+        .frequency(FREQUENCY)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build();
+    match guard {
+        Ok(guard) => Some(Profile {
+            guard,
+            path: path.into(),
+        }),
+        Err(error) => {
+            warn!("not profiling: {error}");
+            None
+        }
+    }
+}
+
+impl Profile {
+    // Write the samples so far as an SVG
+    pub fn finish(self) {
+        let written = self
+            .guard
+            .report()
+            .build()
+            .map_err(|error| error.to_string())
+            .and_then(|report| {
+                let file = File::create(&self.path).map_err(|error| error.to_string())?;
+                report.flamegraph(file).map_err(|error| error.to_string())
+            });
+        match written {
+            Ok(()) => info!("wrote the flamegraph to {}", self.path.display()),
+            Err(error) => eprintln!("failed to write the flamegraph: {error}"),
+        }
+    }
+}
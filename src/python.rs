@@ -0,0 +1,46 @@
+//! Python bindings
+//!
+//! ```python
+//! import onebrc
+//! for name, (min, mean, max, count) in onebrc.process("measurements.txt").items():
+//!     ...
+//! ```
+
+use std::{collections::HashMap, fs::File};
+
+use pyo3::{exceptions::PyOSError, prelude::*};
+
+use crate::{aggregate, load, options::Options};
+
+type Station = (f64, f64, f64, u32);
+
+/// Aggregate a measurements file into `{name: (min, mean, max, count)}`,
+/// values in degrees Celsius
+#[pyfunction]
+fn process(py: Python<'_>, path: &str) -> PyResult<HashMap<String, Station>> {
+    let file = File::open(path).map_err(|error| PyOSError::new_err(error.to_string()))?;
+    let input = load(&file).map_err(|error| PyOSError::new_err(error.to_string()))?;
+
+    // Other Python threads may run while the file is aggregated
+    py.detach(|| {
+        let options = Options::default();
+        let scale = options.scale();
+        let result =
+            aggregate(&input, &options).map_err(|error| PyOSError::new_err(error.to_string()))?;
+        Ok(result
+            .key_set()
+            .map(|(_, stats)| {
+                let name = String::from_utf8_lossy(&stats.name).into_owned();
+                let min = stats.min as f64 / scale;
+                let mean = stats.sum as f64 / stats.count as f64 / scale;
+                let max = stats.max as f64 / scale;
+                (name, (min, mean, max, stats.count))
+            })
+            .collect())
+    })
+}
+
+#[pymodule]
+fn onebrc(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(process, module)?)
+}
@@ -0,0 +1,103 @@
+//! Machine readable run report
+//!
+//! The schema only ever gains fields, anything else bumps its version:
+//!
+//! ```text
+//! {"version": 1, "file": "m.txt", "bytes": 13795, "rows": 1000,
+//!  "stations": 413, "threads": 8, "cpu_features": ["sse4.2", "avx2"],
+//!  "scanner": "avx2", "table": "robin",
+//!  "seconds": {"total": 0.01, "load": 0.0, "aggregate": 0.01, "output": 0.0},
+//!  "throughput": {"bytes_per_second": 1379500.0, "rows_per_second": 100000.0}}
+//! ```
+
+use std::{
+    io::{self, Write},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use onebrc::{hash_table::HashTable, options::Options, scan, StationStats};
+
+use crate::{info::cpu_features, output};
+
+const VERSION: u32 = 1;
+
+// How long each phase of the run took
+pub struct Timings {
+    pub load: Duration,
+    pub aggregate: Duration,
+    pub output: Duration,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    version: u32,
+    file: &'a str,
+    bytes: u64,
+    rows: u64,
+    stations: usize,
+    threads: usize,
+    cpu_features: Vec<&'static str>,
+    scanner: &'static str,
+    table: &'static str,
+    seconds: Seconds,
+    throughput: Throughput,
+}
+
+#[derive(Serialize)]
+struct Seconds {
+    total: f64,
+    load: f64,
+    aggregate: f64,
+    output: f64,
+}
+
+#[derive(Serialize)]
+struct Throughput {
+    bytes_per_second: f64,
+    rows_per_second: f64,
+}
+
+pub fn write(
+    path: &Path,
+    result: &HashTable<StationStats>,
+    bytes: u64,
+    timings: Timings,
+    options: &Options,
+) -> io::Result<()> {
+    let rows = result
+        .key_set()
+        .map(|(_, stats)| stats.count as u64)
+        .sum::<u64>();
+    let total = (timings.load + timings.aggregate + timings.output).as_secs_f64();
+    let report = Report {
+        version: VERSION,
+        file: &options.file_name,
+        bytes,
+        rows,
+        stations: result.key_set().count(),
+        threads: options
+            .threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, usize::from)),
+        cpu_features: cpu_features(),
+        scanner: options.scanner.unwrap_or_else(scan::detect).name(),
+        table: options.table.name(),
+        seconds: Seconds {
+            total,
+            load: timings.load.as_secs_f64(),
+            aggregate: timings.aggregate.as_secs_f64(),
+            output: timings.output.as_secs_f64(),
+        },
+        throughput: Throughput {
+            bytes_per_second: bytes as f64 / total,
+            rows_per_second: rows as f64 / total,
+        },
+    };
+    output::write_atomically(path, |out| {
+        serde_json::to_writer_pretty(&mut *out, &report)?;
+        writeln!(out)
+    })
+}
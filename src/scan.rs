@@ -0,0 +1,324 @@
+//! Byte search and name hashing with the widest vectors of the CPU
+//!
+//! The aggregation loop is compiled once per scanner, each copy with the
+//! target features of its scanner enabled, so one binary runs everywhere
+//! but uses NEON, AVX2 or AVX-512 where the CPU has them. The copy is picked
+//! at runtime, see `detect`. Every scanner hashes names to the same keys.
+//!
+//! Most names are shorter than 16 bytes. Those are searched for the
+//! delimiter with SWAR on two words and hashed from the same words, longer
+//! ones take the vector search and the byte by byte hash.
+
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+
+// From the narrowest to the widest vectors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Scanner {
+    Scalar,
+    Neon,
+    Avx2,
+    Avx512,
+}
+
+impl Scanner {
+    pub fn name(self) -> &'static str {
+        match self {
+            Scanner::Scalar => "scalar",
+            Scanner::Neon => "neon",
+            Scanner::Avx2 => "avx2",
+            Scanner::Avx512 => "avx512",
+        }
+    }
+
+    // Whether this CPU can run the scanner
+    pub fn is_supported(self) -> bool {
+        match self {
+            Scanner::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            Scanner::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(target_arch = "x86_64")]
+            Scanner::Avx512 => {
+                is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512bw")
+            }
+            #[cfg(not(target_arch = "x86_64"))]
+            Scanner::Avx2 | Scanner::Avx512 => false,
+            #[cfg(target_arch = "aarch64")]
+            Scanner::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            #[cfg(not(target_arch = "aarch64"))]
+            Scanner::Neon => false,
+        }
+    }
+}
+
+impl FromStr for Scanner {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "scalar" => Ok(Scanner::Scalar),
+            "neon" => Ok(Scanner::Neon),
+            "avx2" => Ok(Scanner::Avx2),
+            "avx512" => Ok(Scanner::Avx512),
+            other => Err(format!("unknown scanner `{other}`")),
+        }
+    }
+}
+
+// 0 until detected, the scanner plus one after
+static DETECTED: AtomicU8 = AtomicU8::new(0);
+
+// The widest scanner this CPU supports, detected once
+pub fn detect() -> Scanner {
+    const SCANNERS: [Scanner; 4] = [
+        Scanner::Scalar,
+        Scanner::Neon,
+        Scanner::Avx2,
+        Scanner::Avx512,
+    ];
+    match DETECTED.load(Ordering::Relaxed) {
+        0 => {
+            let best = SCANNERS
+                .into_iter()
+                .filter(|scanner| scanner.is_supported())
+                .max()
+                .unwrap_or(Scanner::Scalar);
+            DETECTED.store(best as u8 + 1, Ordering::Relaxed);
+            best
+        }
+        detected => SCANNERS[detected as usize - 1],
+    }
+}
+
+pub(crate) trait Scan {
+    // Index of the first `byte` from `from` on, the end of data if it does
+    // not occur, so a last line without a trailing newline ends there
+    fn find(data: &[u8], from: usize, byte: u8) -> usize;
+
+    // Simple hash function, stolen from java, for names of 16 bytes and more,
+    // see `key`
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn key(name: &[u8]) -> u64 {
+        extend_key(0, name)
+    }
+}
+
+// Names shorter than this are found and hashed two words at a time
+pub(crate) const SHORT: usize = 16;
+
+// Key of any name, short ones are hashed like `short_key` does
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+pub(crate) fn key<S: Scan>(name: &[u8]) -> u64 {
+    if name.len() < SHORT {
+        // Only near the end of the data, there are no 16 bytes to load
+        let mut padded = [0; SHORT];
+        padded[..name.len()].copy_from_slice(name);
+        short_key(words(&padded, 0).unwrap(), name.len())
+    } else {
+        S::key(name)
+    }
+}
+
+// The 16 bytes at `position` as two little endian words, if there are that
+// many
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+pub(crate) fn words(data: &[u8], position: usize) -> Option<[u64; 2]> {
+    let bytes = data.get(position..)?.first_chunk::<SHORT>()?;
+    let (first, second) = bytes.split_at(8);
+    Some([
+        u64::from_le_bytes(first.try_into().unwrap()),
+        u64::from_le_bytes(second.try_into().unwrap()),
+    ])
+}
+
+// Index of the first `byte` in the words. With SWAR a byte of the words that
+// equals it becomes 0 and borrows when subtracting 1 from every byte, which
+// sets its high bit. Borrows only flag false matches above a real one.
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+pub(crate) fn find_in_words(words: [u64; 2], byte: u8) -> Option<usize> {
+    const LOW: u64 = 0x0101_0101_0101_0101;
+    const HIGH: u64 = 0x8080_8080_8080_8080;
+    let pattern = LOW * byte as u64;
+    let matches = |word: u64| {
+        let zeroed = word ^ pattern;
+        zeroed.wrapping_sub(LOW) & !zeroed & HIGH
+    };
+    match (matches(words[0]), matches(words[1])) {
+        (0, 0) => None,
+        (0, second) => Some(8 + second.trailing_zeros() as usize / 8),
+        (first, _) => Some(first.trailing_zeros() as usize / 8),
+    }
+}
+
+// Key of a name of `len` bytes, below 16, from the words at its start. The
+// bytes after the name are masked off and the rest mixed in registers.
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+pub(crate) fn short_key(words: [u64; 2], len: usize) -> u64 {
+    let mask = |bytes: usize| ((1u128 << (bytes * 8)) - 1) as u64;
+    let first = words[0] & mask(len.min(8));
+    let second = words[1] & mask(len.saturating_sub(8));
+    let key = (first ^ second.rotate_left(29).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+        .wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    // The table indexes with the low bits, which need the high ones too
+    key ^ (key >> 32)
+}
+
+// Keys of long names are barely mixed, so where all their bits count they
+// are mixed again first, see `perfect` and `estimate`
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+pub(crate) fn mix(key: u64) -> u64 {
+    let key = (key ^ (key >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    let key = (key ^ (key >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    key ^ (key >> 31)
+}
+
+// Continue the hash of earlier bytes with `bytes`
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn extend_key(mut key: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        key = key.wrapping_mul(31).wrapping_add(byte as u64);
+    }
+    key
+}
+
+pub(crate) struct Scalar;
+
+impl Scan for Scalar {
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn find(data: &[u8], from: usize, byte: u8) -> usize {
+        match data.get(from..) {
+            Some(rest) => rest
+                .iter()
+                .position(|&c| c == byte)
+                .map_or(data.len(), |i| from + i),
+            None => data.len(),
+        }
+    }
+}
+
+// Only inlined into code compiled with `avx2` enabled, after `detect`
+#[cfg(target_arch = "x86_64")]
+pub(crate) struct Avx2;
+
+#[cfg(target_arch = "x86_64")]
+impl Scan for Avx2 {
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn find(data: &[u8], from: usize, byte: u8) -> usize {
+        use std::arch::x86_64::*;
+
+        let mut position = from;
+        unsafe {
+            let needle = _mm256_set1_epi8(byte as i8);
+            while position + 32 <= data.len() {
+                let bytes = _mm256_loadu_si256(data.as_ptr().add(position) as *const __m256i);
+                let matches = _mm256_movemask_epi8(_mm256_cmpeq_epi8(bytes, needle)) as u32;
+                if matches != 0 {
+                    return position + matches.trailing_zeros() as usize;
+                }
+                position += 32;
+            }
+        }
+        // Fewer than a vector of bytes left
+        Scalar::find(data, position, byte)
+    }
+}
+
+// Only inlined into code compiled with `avx512f` and `avx512bw` enabled,
+// after `detect`
+#[cfg(target_arch = "x86_64")]
+pub(crate) struct Avx512;
+
+#[cfg(target_arch = "x86_64")]
+impl Scan for Avx512 {
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn find(data: &[u8], from: usize, byte: u8) -> usize {
+        use std::arch::x86_64::*;
+
+        let mut position = from;
+        unsafe {
+            let needle = _mm512_set1_epi8(byte as i8);
+            while position + 64 <= data.len() {
+                let bytes = _mm512_loadu_si512(data.as_ptr().add(position) as *const __m512i);
+                let matches = _mm512_cmpeq_epi8_mask(bytes, needle);
+                if matches != 0 {
+                    return position + matches.trailing_zeros() as usize;
+                }
+                position += 64;
+            }
+        }
+        // Fewer than a vector of bytes left, which AVX2 may still cover
+        Avx2::find(data, position, byte)
+    }
+}
+
+// Only inlined into code compiled with `neon` enabled, after `detect`
+#[cfg(target_arch = "aarch64")]
+pub(crate) struct Neon;
+
+#[cfg(target_arch = "aarch64")]
+impl Scan for Neon {
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn find(data: &[u8], from: usize, byte: u8) -> usize {
+        use std::arch::aarch64::*;
+
+        let mut position = from;
+        unsafe {
+            let needle = vdupq_n_u8(byte);
+            while position + 16 <= data.len() {
+                let bytes = vld1q_u8(data.as_ptr().add(position));
+                let matches = vceqq_u8(bytes, needle);
+                // Four bits per byte, NEON has no movemask
+                let narrowed = vshrn_n_u16::<4>(vreinterpretq_u16_u8(matches));
+                let matches = vget_lane_u64::<0>(vreinterpret_u64_u8(narrowed));
+                if matches != 0 {
+                    return position + (matches.trailing_zeros() / 4) as usize;
+                }
+                position += 16;
+            }
+        }
+        // Fewer than a vector of bytes left
+        Scalar::find(data, position, byte)
+    }
+
+    // The same keys as `Scalar`, 16 bytes at a time. Each group of 4 bytes
+    // is weighted by powers of 31 in 32-bit lanes, which cannot overflow,
+    // and the groups are combined with their powers modulo 2^64.
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn key(name: &[u8]) -> u64 {
+        use std::arch::aarch64::*;
+
+        const POWERS: [u32; 4] = [31 * 31 * 31, 31 * 31, 31, 1];
+        const P4: u64 = 31u64.pow(4);
+        const P8: u64 = 31u64.pow(8);
+        const P12: u64 = 31u64.pow(12);
+        const P16: u64 = 31u64.wrapping_pow(16);
+
+        let mut blocks = name.chunks_exact(16);
+        let mut key = 0u64;
+        unsafe {
+            let powers = vld1q_u32(POWERS.as_ptr());
+            for block in &mut blocks {
+                let bytes = vld1q_u8(block.as_ptr());
+                let (low, high) = (vmovl_u8(vget_low_u8(bytes)), vmovl_high_u8(bytes));
+                let groups = [
+                    vmovl_u16(vget_low_u16(low)),
+                    vmovl_high_u16(low),
+                    vmovl_u16(vget_low_u16(high)),
+                    vmovl_high_u16(high),
+                ]
+                .map(|group| vaddvq_u32(vmulq_u32(group, powers)) as u64);
+                key = key
+                    .wrapping_mul(P16)
+                    .wrapping_add(groups[0].wrapping_mul(P12))
+                    .wrapping_add(groups[1].wrapping_mul(P8))
+                    .wrapping_add(groups[2].wrapping_mul(P4))
+                    .wrapping_add(groups[3]);
+            }
+        }
+        extend_key(key, blocks.remainder())
+    }
+}
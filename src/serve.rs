@@ -0,0 +1,467 @@
+//! HTTP server
+//!
+//! A minimal HTTP/1.1 server on top of std, answering one request per
+//! connection with JSON:
+//!
+//! ```text
+//! GET /stations                all stations in the output order
+//! GET /stations/{name}         a single station, the name percent-encoded
+//! GET /top?n=10&by=mean        the n stations with the highest value
+//! GET /metrics                 Prometheus text format
+//! ```
+//!
+//! `--follow --metrics <port>` answers the same on a thread of its own,
+//! with the result so far.
+
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use onebrc::options::{Options, SortKey};
+
+use crate::output::{self, JsonRow, Row};
+
+// Requests are served one at a time, so a stalled client must not block
+// the others for long
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Result of aggregating the file once
+pub struct Scan {
+    pub rows: Vec<Row<'static>>,
+    pub bytes: u64,
+}
+
+// Totals over all scans since the server started, or aggregations of the
+// lines `--follow` read
+#[derive(Default)]
+struct Throughput {
+    scans: u64,
+    bytes: u64,
+    lines: u64,
+    seconds: f64,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(rows: &[Row], options: &Options) -> Self {
+        let rows = rows
+            .iter()
+            .map(|row| JsonRow::new(row, options))
+            .collect::<Vec<_>>();
+        Self::ok(serde_json::to_string(&rows).unwrap())
+    }
+
+    fn ok(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::json!({ "error": message }).to_string(),
+        }
+    }
+}
+
+// Serve until the listener fails, `scan` aggregates the file
+pub fn run(options: &Options, scan: impl Fn() -> io::Result<Scan>) -> io::Result<()> {
+    let listener = TcpListener::bind((options.host.as_str(), options.port))?;
+    let mut throughput = Throughput::default();
+    let mut rows = timed_scan(&scan, &mut throughput, options)?;
+    if !options.quiet {
+        eprintln!("listening on http://{}", listener.local_addr()?);
+    }
+
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        answer(&mut stream, |target| {
+            if options.rescan {
+                match timed_scan(&scan, &mut throughput, options) {
+                    Ok(scanned) => rows = scanned,
+                    Err(error) => {
+                        return Response::error("500 Internal Server Error", &error.to_string())
+                    }
+                }
+            }
+            respond(target, &rows, &throughput, options)
+        });
+    }
+    Ok(())
+}
+
+// The result of `--follow` so far, and how much it took
+pub struct Followed {
+    shared: Arc<Mutex<(Vec<Row<'static>>, Throughput)>>,
+}
+
+impl Followed {
+    // Answer requests on `port` of `--host` until the process exits
+    pub fn serve(port: u16, options: &Options) -> io::Result<Self> {
+        let listener = TcpListener::bind((options.host.as_str(), port))?;
+        if !options.quiet {
+            eprintln!("listening on http://{}", listener.local_addr()?);
+        }
+        let shared = Arc::new(Mutex::new((Vec::new(), Throughput::default())));
+        let (followed, options) = (shared.clone(), options.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                answer(&mut stream, |target| {
+                    let (rows, throughput) = &*followed.lock().unwrap();
+                    respond(target, rows, throughput, &options)
+                });
+            }
+        });
+        Ok(Self { shared })
+    }
+
+    // Answer with `rows` from now on
+    pub fn show(&self, mut rows: Vec<Row<'static>>, options: &Options) {
+        sort(&mut rows, options);
+        self.shared.lock().unwrap().0 = rows;
+    }
+
+    // Count `bytes` of `lines` more aggregated in `took`
+    pub fn count(&self, bytes: u64, lines: u64, took: Duration) {
+        let throughput = &mut self.shared.lock().unwrap().1;
+        throughput.scans += 1;
+        throughput.bytes += bytes;
+        throughput.lines += lines;
+        throughput.seconds += took.as_secs_f64();
+    }
+}
+
+// Read a request from `stream` and write what `respond` makes of its target
+fn answer(stream: &mut TcpStream, respond: impl FnOnce(&str) -> Response) {
+    let response = match read_request(stream) {
+        Ok((method, _)) if method != "GET" => {
+            Response::error("405 Method Not Allowed", "only GET is supported")
+        }
+        Ok((_, target)) => respond(&target),
+        Err(_) => Response::error("400 Bad Request", "malformed request"),
+    };
+    // A client that went away is not an error of the server
+    let _ = write_response(stream, &response);
+}
+
+// Scan and account for it in the throughput
+fn timed_scan(
+    scan: &impl Fn() -> io::Result<Scan>,
+    throughput: &mut Throughput,
+    options: &Options,
+) -> io::Result<Vec<Row<'static>>> {
+    let start = Instant::now();
+    let Scan { mut rows, bytes } = scan()?;
+    throughput.scans += 1;
+    throughput.bytes += bytes;
+    throughput.lines += rows.iter().map(|row| row.count as u64).sum::<u64>();
+    throughput.seconds += start.elapsed().as_secs_f64();
+    sort(&mut rows, options);
+    Ok(rows)
+}
+
+fn sort(rows: &mut [Row], options: &Options) {
+    output::sort(rows, options.sort.unwrap_or(SortKey::Name), options);
+}
+
+// Returns the method and target of the request line, headers are ignored.
+// The timeouts also hold for writing the response to the stream.
+fn read_request(stream: &mut TcpStream) -> io::Result<(String, String)> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(io::ErrorKind::InvalidData.into());
+    };
+    let request = (method.to_string(), target.to_string());
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            return Ok(request);
+        }
+    }
+}
+
+fn respond(target: &str, rows: &[Row], throughput: &Throughput, options: &Options) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path == "/stations" {
+        return Response::json(rows, options);
+    }
+    if path == "/metrics" {
+        return Response {
+            status: "200 OK",
+            content_type: "text/plain; version=0.0.4",
+            body: metrics(rows, throughput),
+        };
+    }
+    if path == "/top" {
+        let n = match parameter(query, "n").map(str::parse) {
+            None => 10,
+            Some(Ok(n)) => n,
+            Some(Err(_)) => return Response::error("400 Bad Request", "n must be a number"),
+        };
+        let key = match parameter(query, "by").map(str::parse) {
+            None => SortKey::Mean,
+            Some(Ok(key)) => key,
+            Some(Err(error)) => return Response::error("400 Bad Request", &error),
+        };
+        let mut top = rows.to_vec();
+        let descending = Options {
+            descending: true,
+            ..options.clone()
+        };
+        output::sort(&mut top, key, &descending);
+        top.truncate(n);
+        return Response::json(&top, options);
+    }
+    if let Some(name) = path.strip_prefix("/stations/") {
+        let name = percent_decode(name);
+        return match rows.iter().find(|row| row.name == name) {
+            Some(row) => Response::ok(serde_json::to_string(&JsonRow::new(row, options)).unwrap()),
+            None => Response::error("404 Not Found", "unknown station"),
+        };
+    }
+    Response::error("404 Not Found", "unknown path")
+}
+
+// Metric name, help text and value of a station
+type Gauge = (&'static str, &'static str, fn(&Row) -> f64);
+
+// Station gauges are in the output unit, the last scan is what they show
+fn metrics(rows: &[Row], throughput: &Throughput) -> String {
+    let mut body = String::new();
+    let gauges: [Gauge; 4] = [
+        ("min", "Lowest measurement", |row| row.min),
+        ("mean", "Mean of all measurements", |row| row.mean),
+        ("max", "Highest measurement", |row| row.max),
+        ("count", "Number of measurements", |row| row.count as f64),
+    ];
+    for (name, help, value) in gauges {
+        writeln!(body, "# HELP onebrc_station_{name} {help} of the station").unwrap();
+        writeln!(body, "# TYPE onebrc_station_{name} gauge").unwrap();
+        for row in rows {
+            let station = escape_label(&row.name);
+            writeln!(
+                body,
+                "onebrc_station_{name}{{station=\"{station}\"}} {}",
+                value(row)
+            )
+            .unwrap();
+        }
+    }
+
+    let counters = [
+        ("scans", "Aggregations of the file", throughput.scans as f64),
+        ("bytes", "Bytes aggregated", throughput.bytes as f64),
+        ("lines", "Measurements aggregated", throughput.lines as f64),
+        ("scan_seconds", "Time spent aggregating", throughput.seconds),
+    ];
+    for (name, help, value) in counters {
+        writeln!(body, "# HELP onebrc_{name}_total {help}").unwrap();
+        writeln!(body, "# TYPE onebrc_{name}_total counter").unwrap();
+        writeln!(body, "onebrc_{name}_total {value}").unwrap();
+    }
+    body
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn parameter<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+// `%XX` escapes to bytes, invalid escapes are kept as they are
+fn percent_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .map(|hex| u8::from_str_radix(std::str::from_utf8(hex).unwrap(), 16).unwrap());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, io::Read};
+
+    use super::*;
+
+    fn row(name: &'static str, mean: f64, count: u32) -> Row<'static> {
+        Row {
+            name: Cow::Borrowed(name),
+            min: mean - 1.0,
+            mean,
+            max: mean + 1.0,
+            count,
+            outlier: false,
+            deviation: 0.0,
+        }
+    }
+
+    // Sorted by name like `show` does
+    fn rows() -> Vec<Row<'static>> {
+        vec![
+            row("Bulawayo", 8.9, 2),
+            row("Hamburg", 12.0, 3),
+            row("São Paulo", 25.1, 1),
+        ]
+    }
+
+    fn get(target: &str) -> Response {
+        respond(target, &rows(), &Throughput::default(), &Options::default())
+    }
+
+    // Names of the stations in a JSON array
+    fn names(response: &Response) -> Vec<String> {
+        let rows = serde_json::from_str::<Vec<serde_json::Value>>(&response.body).unwrap();
+        let names = rows
+            .iter()
+            .map(|row| row["name"].as_str().unwrap().to_string());
+        names.collect()
+    }
+
+    #[test]
+    fn stations_are_listed_in_order() {
+        let response = get("/stations");
+        assert_eq!(response.status, "200 OK");
+        assert_eq!(names(&response), ["Bulawayo", "Hamburg", "São Paulo"]);
+    }
+
+    #[test]
+    fn a_station_is_found_by_its_encoded_name() {
+        let response = get("/stations/S%C3%A3o%20Paulo");
+        assert_eq!(response.status, "200 OK");
+        let station = serde_json::from_str::<serde_json::Value>(&response.body).unwrap();
+        assert_eq!(station["name"], "São Paulo");
+        assert_eq!(station["mean"], 25.1);
+        assert_eq!(station["count"], 1);
+
+        assert_eq!(get("/stations/Nowhere").status, "404 Not Found");
+        assert_eq!(get("/nowhere").status, "404 Not Found");
+    }
+
+    #[test]
+    fn top_stations_are_the_highest_means_by_default() {
+        let response = get("/top");
+        assert_eq!(response.status, "200 OK");
+        assert_eq!(names(&response), ["São Paulo", "Hamburg", "Bulawayo"]);
+        assert_eq!(names(&get("/top?n=1&by=count")), ["Hamburg"]);
+    }
+
+    #[test]
+    fn bad_top_parameters_are_refused() {
+        for target in ["/top?n=x", "/top?by=bogus"] {
+            assert_eq!(get(target).status, "400 Bad Request", "{target}");
+        }
+    }
+
+    #[test]
+    fn metrics_show_what_follow_counted() {
+        let followed = Followed {
+            shared: Arc::default(),
+        };
+        followed.show(rows(), &Options::default());
+        followed.count(100, 4, Duration::from_millis(250));
+        followed.count(50, 2, Duration::from_millis(500));
+        let body = {
+            let (rows, throughput) = &*followed.shared.lock().unwrap();
+            metrics(rows, throughput)
+        };
+        let lines = body.lines().collect::<Vec<_>>();
+        for line in [
+            "onebrc_scans_total 2",
+            "onebrc_bytes_total 150",
+            "onebrc_lines_total 6",
+            "onebrc_scan_seconds_total 0.75",
+            "# TYPE onebrc_lines_total counter",
+            "# TYPE onebrc_station_mean gauge",
+            "onebrc_station_mean{station=\"Hamburg\"} 12",
+            "onebrc_station_min{station=\"São Paulo\"} 24.1",
+            "onebrc_station_count{station=\"Bulawayo\"} 2",
+        ] {
+            assert!(lines.contains(&line), "{line} missing in\n{body}");
+        }
+        // A gauge per station for each of min, mean, max and count
+        let stations = lines
+            .iter()
+            .filter(|line| line.starts_with("onebrc_station_"));
+        assert_eq!(stations.count(), 12);
+    }
+
+    #[test]
+    fn methods_other_than_get_are_not_allowed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client
+            .write_all(b"POST /stations HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        answer(&mut stream, |_| unreachable!("only GET is answered"));
+        drop(stream);
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"),
+            "{response}"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Rolling aggregates of `--follow`, see `--window 5m` or `--window 1000rows`
+//!
+//! Minimums and maximums cannot be taken out of an aggregate again, so the
+//! lines read at each poll are kept as a pane of their own and the result is
+//! merged from the panes still in the window. Panes go as a whole: once they
+//! are older than the time window, or once the newer ones alone have the
+//! rows of the window.
+
+use std::{collections::VecDeque, time::Instant};
+
+use onebrc::{hash_table::HashTable, options::Sliding, StationStats};
+
+struct Pane {
+    stations: HashTable<StationStats<'static>>,
+    rows: u64,
+    read: Instant,
+}
+
+pub struct Panes {
+    window: Sliding,
+    panes: VecDeque<Pane>,
+    // Of all panes
+    rows: u64,
+}
+
+impl Panes {
+    pub fn new(window: Sliding) -> Self {
+        Self {
+            window,
+            panes: VecDeque::new(),
+            rows: 0,
+        }
+    }
+
+    // Add the stations of the lines just read
+    pub fn push(&mut self, stations: HashTable<StationStats<'static>>) {
+        let rows = stations
+            .key_set()
+            .map(|(_, stats)| stats.count as u64)
+            .sum();
+        self.rows += rows;
+        self.panes.push_back(Pane {
+            stations,
+            rows,
+            read: Instant::now(),
+        });
+    }
+
+    // Drop the panes that left the window, returns whether there were any
+    pub fn expire(&mut self) -> bool {
+        let before = self.panes.len();
+        while let Some(oldest) = self.panes.front() {
+            let expired = match self.window {
+                Sliding::Time(time) => oldest.read.elapsed() > time,
+                Sliding::Rows(rows) => self.rows - oldest.rows >= rows,
+            };
+            if !expired {
+                break;
+            }
+            self.rows -= oldest.rows;
+            self.panes.pop_front();
+        }
+        self.panes.len() < before
+    }
+
+    // The stations of all lines in the window
+    pub fn merged(&self) -> HashTable<StationStats<'static>> {
+        let mut merged = HashTable::new();
+        for pane in &self.panes {
+            for (key, stats) in pane.stations.key_set() {
+                merged.insert_or_update(
+                    key,
+                    |merged: &StationStats| merged.name == stats.name,
+                    |merged| merged.merge(stats),
+                    || stats.clone(),
+                );
+            }
+        }
+        merged
+    }
+}
@@ -0,0 +1,103 @@
+//! Guessing the format of a file from its first lines, see `--no-sniff`
+//!
+//! Files not written for the challenge often use `,` or tabs and values like
+//! `12` or `12.34`. The first lines of a sample are split at each delimiter
+//! in turn, `;` first, and the first one after which every line has a value
+//! is taken. Values with other than one decimal turn on `flexible_values`.
+//! A first line without a value is a header, which `Header::Auto` detects
+//! again later. Files in the default format are left as they are.
+
+use log::info;
+
+use crate::options::Options;
+
+// Bytes of the file sampled
+pub const SAMPLE: usize = 4096;
+
+// Tried in this order
+const DELIMITERS: [u8; 3] = [b';', b',', b'\t'];
+
+// What a sample looks like
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sniffed {
+    pub delimiter: u8,
+    pub flexible_values: bool,
+    pub header: bool,
+}
+
+// The format of the lines in `sample`, `None` if no delimiter fits them all
+pub fn sniff(sample: &[u8]) -> Option<Sniffed> {
+    let sample = sample.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(sample);
+    let mut lines = sample
+        .split(|&c| c == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect::<Vec<_>>();
+    // The last line is cut off unless the sample is the whole file
+    if sample.len() >= SAMPLE || sample.ends_with(b"\n") {
+        lines.pop();
+    }
+    lines.retain(|line| !line.is_empty() && !line.starts_with(b"#"));
+
+    DELIMITERS.into_iter().find_map(|delimiter| {
+        let value = |line: &[u8]| -> Option<bool> {
+            let split = line.iter().position(|&c| c == delimiter)?;
+            decimals(&line[split + 1..])
+        };
+        let (first, rest) = lines.split_first()?;
+        let header = value(first).is_none();
+        let data = if header { rest } else { &lines[..] };
+        if data.is_empty() {
+            return None;
+        }
+        let strict = data
+            .iter()
+            .map(|line| value(line))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Sniffed {
+            delimiter,
+            flexible_values: strict.contains(&false),
+            header,
+        })
+    })
+}
+
+// Whether `value` has exactly one decimal, `None` if it is not a number the
+// flexible parser takes
+fn decimals(value: &[u8]) -> Option<bool> {
+    let digits = value.strip_prefix(b"-").unwrap_or(value);
+    let (whole, decimals) = match digits.iter().position(|&c| c == b'.') {
+        Some(dot) => (&digits[..dot], &digits[dot + 1..]),
+        None => (digits, &b""[..]),
+    };
+    let is_number = |digits: &[u8]| digits.iter().all(u8::is_ascii_digit);
+    let fits = (1..=2).contains(&whole.len())
+        && decimals.len() <= 2
+        && is_number(whole)
+        && is_number(decimals)
+        && !(decimals.is_empty() && digits.contains(&b'.'));
+    fits.then_some(decimals.len() == 1)
+}
+
+// Take the delimiter and value format guessed from `sample`, and say so if
+// they are not the defaults
+pub fn apply(sample: &[u8], options: &mut Options) {
+    let Some(sniffed) = sniff(sample) else {
+        info!("no delimiter fits the first lines, reading them as `;`");
+        return;
+    };
+    if sniffed.delimiter != options.delimiter {
+        let delimiter = match sniffed.delimiter {
+            b'\t' => "tab".into(),
+            delimiter => format!("`{}`", delimiter as char),
+        };
+        info!("sniffed the delimiter {delimiter}, set --delimiter to override");
+        options.delimiter = sniffed.delimiter;
+    }
+    if sniffed.flexible_values && !options.flexible_values {
+        info!("sniffed values without exactly one decimal, reading them with --flexible-values");
+        options.flexible_values = true;
+    }
+    if sniffed.header {
+        info!("sniffed a header");
+    }
+}
@@ -0,0 +1,223 @@
+//! Spilling tables to disk, see `--spill`
+//!
+//! Every thread aggregates into a table sized for its share of the budget.
+//! Once it is full its stations are split by the hash of their key into
+//! `PARTITIONS` saved states under the spill directory and the thread starts
+//! over with an empty table. At the end the states of one partition at a
+//! time are read back and merged, so only the result and a single partition
+//! are in memory then, not a table per thread holding every station.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    mem,
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+    thread,
+};
+
+use log::{debug, info};
+
+use crate::{
+    aggregator::{MinMeanMax, Station},
+    chunks,
+    hash_table::HashTable,
+    options::Options,
+    scan, state, thread_count, Chunk, Skipped, StationStats,
+};
+
+// Files per spill, the most the final merge reads back together is the
+// spilled stations divided by this
+const PARTITIONS: usize = 16;
+
+// Bytes of tables without `--max-memory`
+const BUDGET: usize = 1 << 30;
+
+// The spilled states of every partition, to merge and remove at the end
+struct Spills {
+    dir: PathBuf,
+    partitions: Mutex<Vec<Vec<PathBuf>>>,
+}
+
+impl Spills {
+    // Write `stations` partitioned by key as the `spill`th of `thread`
+    fn write(
+        &self,
+        thread: usize,
+        spill: usize,
+        stations: HashTable<Station<MinMeanMax>>,
+        options: &Options,
+    ) -> io::Result<()> {
+        let mut partitions = vec![Vec::new(); PARTITIONS];
+        for (key, station) in stations {
+            partitions[scan::mix(key) as usize % PARTITIONS].push(StationStats::from(station));
+        }
+        for (partition, stations) in partitions.iter().enumerate() {
+            if stations.is_empty() {
+                continue;
+            }
+            let path = self.dir.join(format!(
+                "1brc-{}-{thread}-{spill}-{partition}.state",
+                process::id()
+            ));
+            // Listed first, so a failed write is still removed
+            self.partitions.lock().unwrap()[partition].push(path.clone());
+            let mut out = BufWriter::new(File::create(&path)?);
+            let stations = stations.iter().collect::<Vec<_>>();
+            state::write(&stations, options.scale() as u8, &mut out)?;
+            out.flush()?;
+        }
+        debug!(
+            "thread {thread}: spilled {} stations",
+            partitions.iter().map(Vec::len).sum::<usize>()
+        );
+        Ok(())
+    }
+
+    // Merge the spilled states into `result` a partition at a time, each in
+    // a table that starts out like those of the threads
+    fn merge_into(
+        &self,
+        result: &mut HashTable<StationStats<'static>>,
+        limit: usize,
+    ) -> io::Result<()> {
+        for paths in self.partitions.lock().unwrap().iter() {
+            let mut partition = HashTable::for_stations(limit);
+            for path in paths {
+                let (_, stations) = state::read(path)?;
+                for stats in stations {
+                    partition.insert_or_update(
+                        stats.key(),
+                        |merged: &StationStats| merged.name == stats.name,
+                        |merged| merged.merge(&stats),
+                        || stats.clone(),
+                    );
+                }
+                fs::remove_file(path)?;
+            }
+            for (_, stats) in partition {
+                merge(result, stats);
+            }
+        }
+        Ok(())
+    }
+
+    // Best effort, after an error
+    fn remove(&self) {
+        for path in self.partitions.lock().unwrap().iter().flatten() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Stations are keyed by name, spilled states do not keep the key of the raw
+// name, which differs from it if it was transcoded
+fn merge(result: &mut HashTable<StationStats<'static>>, stats: StationStats<'static>) {
+    result.insert_or_update(
+        stats.key(),
+        |merged: &StationStats| merged.name == stats.name,
+        |merged| merged.merge(&stats),
+        || stats.clone(),
+    );
+}
+
+pub(crate) fn aggregate(
+    data: &[u8],
+    dir: &Path,
+    options: &Options,
+) -> io::Result<(HashTable<StationStats<'static>>, Skipped)> {
+    fs::create_dir_all(dir)?;
+    // Half of the budget, the rest is left for the input and the result
+    let budget = options.max_memory.map_or(BUDGET, |limit| limit / 2) / thread_count(options);
+    // Past `--max-stations` a table spills instead of failing
+    let limit = HashTable::<Station<MinMeanMax>>::stations_within(budget)
+        .min(options.max_stations.unwrap_or(usize::MAX));
+    let chunks = chunks::<MinMeanMax, HashTable<_>>(data, options, Some(limit));
+    info!(
+        "spilling to {} past {limit} stations per thread",
+        dir.display()
+    );
+    let spills = Spills {
+        dir: dir.to_owned(),
+        partitions: Mutex::new(vec![Vec::new(); PARTITIONS]),
+    };
+
+    let spills = &spills;
+    // A single chunk, e.g. without threads on WASI, is aggregated in place
+    let aggregated = if chunks.len() == 1 {
+        let chunk = chunks.into_iter().next().unwrap();
+        aggregate_chunk(0, chunk, limit, spills).map(|stations| vec![stations])
+    } else {
+        thread::scope(|scope| {
+            let handles = chunks
+                .into_iter()
+                .enumerate()
+                .map(|(thread, chunk)| {
+                    scope.spawn(move || aggregate_chunk(thread, chunk, limit, spills))
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<io::Result<Vec<_>>>()
+        })
+    };
+
+    // Within the budget too, it only grows with the stations
+    let mut result = HashTable::for_stations(limit);
+    let mut skipped = Skipped::default();
+    let merged = aggregated.and_then(|threads| {
+        for (stations, thread_skipped) in threads {
+            skipped.add(&thread_skipped);
+            for stats in stations {
+                merge(&mut result, stats);
+            }
+        }
+        spills.merge_into(&mut result, limit)
+    });
+    if let Err(error) = merged {
+        spills.remove();
+        return Err(error);
+    }
+    Ok((result, skipped))
+}
+
+// Aggregate `chunk`, spilling its table whenever it holds `limit` stations.
+// Returns the stations of a chunk that never spilled and the lines skipped.
+fn aggregate_chunk(
+    thread: usize,
+    mut chunk: Chunk<'_>,
+    limit: usize,
+    spills: &Spills,
+) -> io::Result<(Vec<StationStats<'static>>, Skipped)> {
+    let options = chunk.options;
+    let mut spilled = 0;
+    // A line adds at most one station, so the table never holds more than
+    // `limit`
+    while chunk.parse_lines(limit - chunk.result.len()) {
+        if chunk.result.len() == limit {
+            let full = mem::replace(&mut chunk.result, HashTable::for_stations(limit));
+            chunk.stations = 0;
+            spills.write(thread, spilled, full, options)?;
+            spilled += 1;
+        }
+    }
+    if let Some(error) = chunk.failed.take() {
+        return Err(error);
+    }
+    // The rest of a chunk that spilled is spilled too, its stations are
+    // merged by partition anyway
+    let (rest, skipped) = (chunk.result, chunk.skipped);
+    if spilled > 0 {
+        if !rest.is_empty() {
+            spills.write(thread, spilled, rest, options)?;
+        }
+        return Ok((Vec::new(), skipped));
+    }
+    let stations = rest
+        .into_iter()
+        .map(|(_, station)| StationStats::from(station).into_owned())
+        .collect();
+    Ok((stations, skipped))
+}
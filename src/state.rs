@@ -0,0 +1,110 @@
+//! Aggregation state
+//!
+//! Compact little-endian encoding of merged accumulators, so partial results
+//! can be combined later without reading the measurements again:
+//!
+//! ```text
+//! magic    b"1BRCSTAT"
+//! version  u8
+//! scale    u8     divisor of the fixed point values, 10 or 100
+//! count    u64    number of stations
+//! station  name length u16, name bytes, sum i128, count u32, min i32, max i32
+//! ```
+//!
+//! Sums are always stored as i128, so states do not depend on `wide-sum`.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{name::Name, StationStats, Sum};
+
+const MAGIC: &[u8; 8] = b"1BRCSTAT";
+const VERSION: u8 = 2;
+
+pub fn write(stations: &[&StationStats], scale: u8, out: &mut impl Write) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION, scale])?;
+    out.write_all(&(stations.len() as u64).to_le_bytes())?;
+    for stats in stations {
+        let name_len = u16::try_from(stats.name.len())
+            .map_err(|_| io::Error::other("station name too long"))?;
+        out.write_all(&name_len.to_le_bytes())?;
+        out.write_all(&stats.name)?;
+        // A no-op with `wide-sum`
+        #[allow(clippy::useless_conversion)]
+        let sum = i128::from(stats.sum);
+        out.write_all(&sum.to_le_bytes())?;
+        out.write_all(&stats.count.to_le_bytes())?;
+        out.write_all(&stats.min.to_le_bytes())?;
+        out.write_all(&stats.max.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+// Returns the scale and the stations of a saved state
+pub fn read(path: &Path) -> io::Result<(u8, Vec<StationStats<'static>>)> {
+    decode(&fs::read(path)?)
+}
+
+pub fn decode(data: &[u8]) -> io::Result<(u8, Vec<StationStats<'static>>)> {
+    let mut reader = Reader { data };
+    if !data.starts_with(MAGIC) {
+        // Printed means are rounded and plain output has no counts, so
+        // results of the binary cannot be merged exactly
+        return Err(invalid(match data {
+            [b'{', b'"', ..] => "a JSON lines result, not a state saved with --save-state",
+            [b'{', ..] => "a plain result, not a state saved with --save-state",
+            _ => "not a saved state",
+        }));
+    }
+    reader.take(MAGIC.len())?;
+    let [version, scale] = reader.array()?;
+    if version != VERSION {
+        return Err(invalid("unsupported state version"));
+    }
+    let count = u64::from_le_bytes(reader.array()?);
+
+    let mut stations = Vec::new();
+    for _ in 0..count {
+        let name_len = u16::from_le_bytes(reader.array()?);
+        let name = Name::copied(reader.take(name_len as usize)?);
+        let sum = i128::from_le_bytes(reader.array()?);
+        let mut stats = StationStats::new(name);
+        stats.sum = Sum::try_from(sum)
+            .map_err(|_| invalid("sum overflows, build with the `wide-sum` feature"))?;
+        stats.count = u32::from_le_bytes(reader.array()?);
+        stats.min = i32::from_le_bytes(reader.array()?);
+        stats.max = i32::from_le_bytes(reader.array()?);
+        stations.push(stats);
+    }
+    if !reader.data.is_empty() {
+        return Err(invalid("trailing bytes after the last station"));
+    }
+    Ok((scale, stations))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(invalid("truncated state"));
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
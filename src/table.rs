@@ -0,0 +1,385 @@
+//! Hash tables to aggregate into
+//!
+//! Chunks aggregate into the table picked with `--table`, the own Robin Hood
+//! table by default. The others are baselines to measure it against: the
+//! `HashMap` of std with its default hasher, and with the `hashbrown`
+//! feature the raw entry API of hashbrown, which takes the key as the hash
+//! like the own table does. `sharded` splits the own table into `--shards`
+//! smaller ones picked by the top bits of the key, whose probes stay within
+//! less memory. Tables are only merged and output as the own one, so only
+//! the hot loop differs. Stations whose keys collide are kept apart by all
+//! of them, the maps of std and hashbrown hold a list per key for that.
+
+use std::{
+    cell::Cell,
+    collections::{hash_map, HashMap},
+    str::FromStr,
+};
+
+use crate::{
+    hash_table::{self, HashTable},
+    options::Options,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Table {
+    #[default]
+    Robin,
+    Std,
+    Hashbrown,
+    Sharded,
+}
+
+impl Table {
+    pub fn name(self) -> &'static str {
+        match self {
+            Table::Robin => "robin",
+            Table::Std => "std",
+            Table::Hashbrown => "hashbrown",
+            Table::Sharded => "sharded",
+        }
+    }
+}
+
+impl FromStr for Table {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "robin" => Ok(Table::Robin),
+            "std" => Ok(Table::Std),
+            "hashbrown" if cfg!(feature = "hashbrown") => Ok(Table::Hashbrown),
+            "hashbrown" => Err("built without the `hashbrown` feature".into()),
+            "sharded" => Ok(Table::Sharded),
+            other => Err(format!("unknown table `{other}`")),
+        }
+    }
+}
+
+// What the aggregation loop needs of a table, keys are hashes of names
+pub(crate) trait Backend<T>: Send {
+    // Room for `stations` if they were estimated, else for as many as a new
+    // own table holds before it grows, so no table starts out ahead
+    fn new(options: &Options, stations: Option<usize>) -> Self;
+
+    // Like `HashTable::insert_or_update`, `is` tells entries of the same key
+    // apart
+    fn insert_or_update(
+        &mut self,
+        key: u64,
+        is: impl FnMut(&T) -> bool,
+        modify: impl FnOnce(&mut T),
+        provide: impl FnOnce() -> T,
+    );
+
+    fn entries<'t>(&'t self) -> impl Iterator<Item = (u64, &'t T)>
+    where
+        T: 't;
+
+    // Only the own table knows its probe lengths and resizes
+    fn stats(&self) -> hash_table::Stats;
+
+    fn into_table(self) -> HashTable<T>;
+}
+
+impl<T: Send> Backend<T> for HashTable<T> {
+    fn new(_: &Options, stations: Option<usize>) -> Self {
+        stations.map_or_else(HashTable::new, HashTable::for_stations)
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn insert_or_update(
+        &mut self,
+        key: u64,
+        is: impl FnMut(&T) -> bool,
+        modify: impl FnOnce(&mut T),
+        provide: impl FnOnce() -> T,
+    ) {
+        HashTable::insert_or_update(self, key, is, modify, provide)
+    }
+
+    fn entries<'t>(&'t self) -> impl Iterator<Item = (u64, &'t T)>
+    where
+        T: 't,
+    {
+        self.key_set()
+    }
+
+    fn stats(&self) -> hash_table::Stats {
+        HashTable::stats(self)
+    }
+
+    fn into_table(self) -> HashTable<T> {
+        self
+    }
+}
+
+impl<T: Send> Backend<T> for HashMap<u64, Vec<T>> {
+    fn new(_: &Options, stations: Option<usize>) -> Self {
+        HashMap::with_capacity(stations.unwrap_or(hash_table::CAPACITY * 7 / 8))
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn insert_or_update(
+        &mut self,
+        key: u64,
+        is: impl FnMut(&T) -> bool,
+        modify: impl FnOnce(&mut T),
+        provide: impl FnOnce() -> T,
+    ) {
+        match self.entry(key) {
+            hash_map::Entry::Occupied(entry) => {
+                update_listed(entry.into_mut(), is, modify, provide)
+            }
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(vec![provide()]);
+            }
+        }
+    }
+
+    fn entries<'t>(&'t self) -> impl Iterator<Item = (u64, &'t T)>
+    where
+        T: 't,
+    {
+        self.iter()
+            .flat_map(|(&key, values)| values.iter().map(move |value| (key, value)))
+    }
+
+    fn stats(&self) -> hash_table::Stats {
+        hash_table::Stats {
+            len: self.values().map(Vec::len).sum(),
+            capacity: self.capacity(),
+            longest_probe: 0,
+            resizes: 0,
+        }
+    }
+
+    fn into_table(self) -> HashTable<T> {
+        self.into_iter()
+            .flat_map(|(key, values)| values.into_iter().map(move |value| (key, value)))
+            .collect()
+    }
+}
+
+// Update the value in the list of a key that `is` the one looked for, or add
+// the provided one
+#[cfg_attr(not(feature = "profiling"), inline(always))]
+fn update_listed<T>(
+    values: &mut Vec<T>,
+    mut is: impl FnMut(&T) -> bool,
+    modify: impl FnOnce(&mut T),
+    provide: impl FnOnce() -> T,
+) {
+    match values.iter_mut().find(|value| is(value)) {
+        Some(value) => modify(value),
+        None => values.push(provide()),
+    }
+}
+
+// The own table with an entry reserved for each station of `--reserve`,
+// empty until its first line, so it never grows for them
+pub(crate) struct Reserved<T>(HashTable<Option<T>>);
+
+impl<T: Send> Backend<T> for Reserved<T> {
+    fn new(options: &Options, stations: Option<usize>) -> Self {
+        let keys = options.reserved.as_deref().unwrap_or_default();
+        let mut table = HashTable::for_stations(keys.len().max(stations.unwrap_or(0)));
+        for &key in keys {
+            table.push(key, None);
+        }
+        Self(table)
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn insert_or_update(
+        &mut self,
+        key: u64,
+        mut is: impl FnMut(&T) -> bool,
+        modify: impl FnOnce(&mut T),
+        provide: impl FnOnce() -> T,
+    ) {
+        // Only one of the closures runs
+        let provide = Cell::new(Some(provide));
+        let provide = || provide.take().map(|provide| provide());
+        // A reserved entry is taken by the first station of its key
+        self.0.insert_or_update(
+            key,
+            |value| value.as_ref().is_none_or(&mut is),
+            |value| match value {
+                Some(value) => modify(value),
+                reserved => *reserved = provide(),
+            },
+            provide,
+        )
+    }
+
+    fn entries<'t>(&'t self) -> impl Iterator<Item = (u64, &'t T)>
+    where
+        T: 't,
+    {
+        self.0
+            .key_set()
+            .filter_map(|(key, value)| Some((key, value.as_ref()?)))
+    }
+
+    fn stats(&self) -> hash_table::Stats {
+        hash_table::Stats {
+            len: self.entries().count(),
+            ..self.0.stats()
+        }
+    }
+
+    fn into_table(self) -> HashTable<T> {
+        self.0
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect()
+    }
+}
+
+// Shards of `--table sharded` without `--shards`
+pub const SHARDS: usize = 16;
+
+// The own table split into shards by the top bits of the key, a power of two
+// of them
+pub(crate) struct Sharded<T> {
+    shards: Box<[HashTable<T>]>,
+    // Top bits that pick the shard
+    bits: u32,
+}
+
+impl<T: Send> Backend<T> for Sharded<T> {
+    fn new(options: &Options, stations: Option<usize>) -> Self {
+        let count = options.shards;
+        let stations = stations.unwrap_or(hash_table::CAPACITY * 7 / 8);
+        Self {
+            shards: (0..count)
+                .map(|_| HashTable::for_stations(stations.div_ceil(count)))
+                .collect(),
+            bits: count.trailing_zeros(),
+        }
+    }
+
+    #[cfg_attr(not(feature = "profiling"), inline(always))]
+    fn insert_or_update(
+        &mut self,
+        key: u64,
+        is: impl FnMut(&T) -> bool,
+        modify: impl FnOnce(&mut T),
+        provide: impl FnOnce() -> T,
+    ) {
+        // Shifted twice, so a single shard takes no bits
+        let shard = (key >> (63 - self.bits) >> 1) as usize;
+        self.shards[shard].insert_or_update(key, is, modify, provide)
+    }
+
+    fn entries<'t>(&'t self) -> impl Iterator<Item = (u64, &'t T)>
+    where
+        T: 't,
+    {
+        self.shards.iter().flat_map(HashTable::key_set)
+    }
+
+    fn stats(&self) -> hash_table::Stats {
+        let stats = self.shards.iter().map(HashTable::stats);
+        hash_table::Stats {
+            len: stats.clone().map(|stats| stats.len).sum(),
+            capacity: stats.clone().map(|stats| stats.capacity).sum(),
+            longest_probe: stats
+                .clone()
+                .map(|stats| stats.longest_probe)
+                .max()
+                .unwrap_or(0),
+            resizes: stats.map(|stats| stats.resizes).sum(),
+        }
+    }
+
+    fn into_table(self) -> HashTable<T> {
+        self.shards.into_vec().into_iter().flatten().collect()
+    }
+}
+
+#[cfg(feature = "hashbrown")]
+pub(crate) use raw::Hashbrown;
+
+#[cfg(feature = "hashbrown")]
+mod raw {
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    use hashbrown::hash_map::RawEntryMut;
+
+    use super::{update_listed, Backend};
+    use crate::{
+        hash_table::{self, HashTable},
+        options::Options,
+    };
+
+    // Keys are hashes already, hashbrown only rehashes them when it grows
+    #[derive(Default)]
+    pub(crate) struct Identity(u64);
+
+    impl Hasher for Identity {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, _: &[u8]) {
+            unreachable!("only u64 keys are hashed")
+        }
+
+        fn write_u64(&mut self, key: u64) {
+            self.0 = key;
+        }
+    }
+
+    // Values of colliding keys are listed together, like with the map of std
+    pub(crate) type Hashbrown<T> = hashbrown::HashMap<u64, Vec<T>, BuildHasherDefault<Identity>>;
+
+    impl<T: Send> Backend<T> for Hashbrown<T> {
+        fn new(_: &Options, stations: Option<usize>) -> Self {
+            let stations = stations.unwrap_or(hash_table::CAPACITY * 7 / 8);
+            Hashbrown::with_capacity_and_hasher(stations, Default::default())
+        }
+
+        #[cfg_attr(not(feature = "profiling"), inline(always))]
+        fn insert_or_update(
+            &mut self,
+            key: u64,
+            is: impl FnMut(&T) -> bool,
+            modify: impl FnOnce(&mut T),
+            provide: impl FnOnce() -> T,
+        ) {
+            match self.raw_entry_mut().from_key_hashed_nocheck(key, &key) {
+                RawEntryMut::Occupied(entry) => {
+                    update_listed(entry.into_mut(), is, modify, provide)
+                }
+                RawEntryMut::Vacant(entry) => {
+                    entry.insert_hashed_nocheck(key, key, vec![provide()]);
+                }
+            }
+        }
+
+        fn entries<'t>(&'t self) -> impl Iterator<Item = (u64, &'t T)>
+        where
+            T: 't,
+        {
+            self.iter()
+                .flat_map(|(&key, values)| values.iter().map(move |value| (key, value)))
+        }
+
+        fn stats(&self) -> hash_table::Stats {
+            hash_table::Stats {
+                len: self.values().map(Vec::len).sum(),
+                capacity: self.capacity(),
+                longest_probe: 0,
+                resizes: 0,
+            }
+        }
+
+        fn into_table(self) -> HashTable<T> {
+            self.into_iter()
+                .flat_map(|(key, values)| values.into_iter().map(move |value| (key, value)))
+                .collect()
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Check the format of a measurements file
+//!
+//! Every line must be a station name of 1 to 100 bytes, the delimiter and a
+//! value from -99.9 to 99.9 with exactly one decimal, or up to two with
+//! `--flexible-values`. With `--lenient` blank lines and comments pass too.
+
+use std::{fs::File, io};
+
+use onebrc::{data_start, options::Options};
+
+use crate::load_input;
+
+// Invalid lines shown before only counting them
+const SHOWN: u64 = 10;
+
+// Returns whether the file is valid
+pub fn run(options: &Options) -> io::Result<bool> {
+    let input = load_input(&File::open(&options.file_name)?, options)?;
+    let start = data_start(&input, options);
+    let skipped_lines = input[..start].iter().filter(|&&c| c == b'\n').count() as u64;
+    let data = input[start..]
+        .strip_suffix(b"\n")
+        .unwrap_or(&input[start..]);
+
+    let (mut lines, mut invalid) = (0, 0);
+    for (i, line) in data.split(|&c| c == b'\n').enumerate() {
+        lines += 1;
+        if let Err(problem) = check(line, options) {
+            invalid += 1;
+            if invalid <= SHOWN {
+                println!("line {}: {problem}", skipped_lines + i as u64 + 1);
+            }
+        }
+    }
+    if invalid > SHOWN {
+        println!("... and {} more", invalid - SHOWN);
+    }
+    println!("{lines} lines, {invalid} invalid");
+    Ok(invalid == 0)
+}
+
+// Why `line` is invalid, if it is
+pub fn check(line: &[u8], options: &Options) -> Result<(), &'static str> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if options.lenient && matches!(line.first(), None | Some(b'#')) {
+        return Ok(());
+    }
+    let delimiter = line
+        .iter()
+        .position(|&c| c == options.delimiter)
+        .ok_or("no delimiter")?;
+    let (name, value) = (&line[..delimiter], &line[delimiter + 1..]);
+    if name.is_empty() || name.len() > 100 {
+        return Err("name is not 1 to 100 bytes");
+    }
+    if options.encoding == onebrc::options::Encoding::Utf8 && std::str::from_utf8(name).is_err() {
+        return Err("name is not UTF-8");
+    }
+
+    let digits = value.strip_prefix(b"-").unwrap_or(value);
+    let (whole, decimals) = match digits.iter().position(|&c| c == b'.') {
+        Some(dot) => (&digits[..dot], &digits[dot + 1..]),
+        None => (digits, &b""[..]),
+    };
+    let decimals_allowed = if options.flexible_values {
+        0..=2
+    } else {
+        1..=1
+    };
+    let is_number = |digits: &[u8]| digits.iter().all(u8::is_ascii_digit);
+    if !(1..=2).contains(&whole.len())
+        || !decimals_allowed.contains(&decimals.len())
+        || !is_number(whole)
+        || !is_number(decimals)
+        || (decimals.is_empty() && digits.contains(&b'.'))
+    {
+        return Err("value is not a number from -99.9 to 99.9");
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! Aggregating with the options that change the hot loop, which panics with
+//! the `alloc-check` feature if parsing a line or updating its station
+//! allocated. Run with `cargo test --features alloc-check`.
+
+#![cfg(feature = "alloc-check")]
+
+use onebrc::{
+    aggregate,
+    options::{Columns, Encoding, Options},
+};
+
+fn measurements(delimiter: char) -> Vec<u8> {
+    let mut data = String::new();
+    for row in 0..20_000 {
+        // Names of every length up to 40 bytes
+        let station = row % 300;
+        let name = format!("{station}{}", "x".repeat(station % 41));
+        data.push_str(&format!("{name}{delimiter}{}.{}\n", row % 100, row % 10));
+    }
+    data.into_bytes()
+}
+
+fn run(data: &[u8], options: Options) {
+    let options = Options {
+        threads: Some(2),
+        ..options
+    };
+    assert_eq!(aggregate(data, &options).unwrap().stats().len, 300);
+}
+
+#[test]
+fn the_hot_loop_does_not_allocate() {
+    let data = measurements(';');
+    run(&data, Options::default());
+    run(
+        &data,
+        Options {
+            hash_stats: true,
+            lenient: true,
+            ..Options::default()
+        },
+    );
+    run(
+        &data,
+        Options {
+            flexible_values: true,
+            quotes: true,
+            ..Options::default()
+        },
+    );
+    run(
+        &data,
+        Options {
+            encoding: Encoding::Latin1,
+            ..Options::default()
+        },
+    );
+}
+
+#[test]
+fn the_hot_loop_does_not_allocate_with_columns() {
+    let data = measurements(',');
+    run(
+        &data,
+        Options {
+            delimiter: b',',
+            columns: Some(Columns {
+                key: 0,
+                value: 1,
+                time: None,
+            }),
+            ..Options::default()
+        },
+    );
+}
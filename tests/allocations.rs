@@ -0,0 +1,104 @@
+//! Parsing and merging allocate nothing per row or per station, names borrow
+//! from the input until something formats them
+//!
+//! The `alloc-check` and `heap-profile` features bring their own global
+//! allocators.
+
+#![cfg(not(any(feature = "alloc-check", feature = "heap-profile")))]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use onebrc::{aggregate, name::INLINE, options::Options};
+
+mod common;
+
+// Counts the allocations of each thread, so tests running in parallel do
+// not see each other's
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn allocations(run: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    run();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+fn measurements(stations: usize, rows: usize) -> Vec<u8> {
+    common::named(rows, |row| {
+        let station = row % stations;
+        // Names of every length up to 40 bytes
+        format!("{station}{}", "x".repeat(station % 37))
+    })
+}
+
+// On the calling thread, spawning threads allocates
+fn options() -> Options {
+    Options {
+        threads: Some(1),
+        ..Options::default()
+    }
+}
+
+#[test]
+fn aggregating_allocates_nothing_per_row() {
+    let options = options();
+    let (few, many) = (measurements(500, 500), measurements(500, 50_000));
+    let few = allocations(|| drop(aggregate(&few, &options).unwrap()));
+    let many = allocations(|| drop(aggregate(&many, &options).unwrap()));
+    assert_eq!(few, many);
+}
+
+#[test]
+fn aggregating_allocates_nothing_per_station() {
+    let options = options();
+    let (few, many) = (measurements(10, 10_000), measurements(5_000, 10_000));
+    let few = allocations(|| drop(aggregate(&few, &options).unwrap()));
+    let many = allocations(|| drop(aggregate(&many, &options).unwrap()));
+    assert_eq!(few, many);
+}
+
+#[test]
+fn owning_names_allocates_only_long_ones() {
+    let options = options();
+    let data = measurements(200, 2_000);
+    let result = aggregate(&data, &options).unwrap();
+    let long = result
+        .key_set()
+        .filter(|(_, stats)| stats.name.len() > INLINE)
+        .count();
+    assert!(long > 0);
+    let mut owned = Vec::with_capacity(result.stats().len);
+    let allocated = allocations(|| {
+        owned.extend(
+            result
+                .key_set()
+                .map(|(_, stats)| stats.clone().into_owned()),
+        );
+    });
+    assert_eq!(allocated, long);
+}
@@ -0,0 +1,87 @@
+//! `bench --matrix` times every combination of the strategies named, and
+//! `bench-parts` every stage
+
+use std::{env, fs, process::Command};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+
+#[test]
+fn every_combination_gets_a_line() {
+    let input = env::temp_dir().join(format!("bench-{}.txt", std::process::id()));
+    fs::write(
+        &input,
+        "Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n".repeat(1000),
+    )
+    .unwrap();
+    let output = Command::new(BINARY)
+        .args([
+            "bench", "--runs", "1", "--warmup", "0", "--matrix", "io,table",
+        ])
+        .arg(&input)
+        .output()
+        .unwrap();
+    fs::remove_file(&input).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines().skip(1);
+    let header = lines.next().unwrap().split_whitespace().collect::<Vec<_>>();
+    assert_eq!(
+        header,
+        ["io", "table", "median", "stddev", "GB/s", "relative"]
+    );
+    let combinations = lines
+        .map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            (
+                fields[0].to_string(),
+                fields[1].to_string(),
+                fields[5].to_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let tables = if cfg!(feature = "hashbrown") { 4 } else { 3 };
+    assert_eq!(combinations.len(), 2 * tables);
+    assert_eq!(combinations[0].0, "mmap");
+    assert_eq!(combinations[0].1, "robin");
+    assert_eq!(combinations[tables].0, "read");
+    assert!(combinations
+        .iter()
+        .any(|(_, _, relative)| relative == "1.00x"));
+}
+
+#[test]
+fn every_stage_gets_a_line() {
+    let output = Command::new(BINARY)
+        .args(["bench-parts", "--rows", "1000", "--runs", "1"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stages = stdout
+        .lines()
+        .skip(2)
+        .map(|line| line.split("  ").next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        stages,
+        [
+            "newline scan",
+            "field split",
+            "value parse",
+            "hash",
+            "table insert",
+            "chained insert",
+            "merge",
+            "all of a line"
+        ]
+    );
+}
@@ -0,0 +1,116 @@
+//! `--cache` reuses the result of an identical run, and aggregates again
+//! when the file or an option that changes parsing differs, or the cached
+//! state does not load
+
+use std::{
+    env, fs,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, SystemTime},
+};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+
+// A file and cache directory of their own, removed when dropped
+struct Cached {
+    input: PathBuf,
+    dir: PathBuf,
+}
+
+impl Cached {
+    fn new(name: &str, data: &str) -> Self {
+        let base = env::temp_dir().join(format!("cache-{}-{name}", std::process::id()));
+        fs::create_dir_all(&base).unwrap();
+        let input = base.join("measurements.txt");
+        fs::write(&input, data).unwrap();
+        Self {
+            input,
+            dir: base.join("cache"),
+        }
+    }
+
+    // Standard output of a run with `args`, and whether it used the cache
+    fn run(&self, args: &[&str]) -> (String, bool) {
+        let output = Command::new(BINARY)
+            .args(["--cache", self.dir.to_str().unwrap(), "-v"])
+            .args(args)
+            .arg(&self.input)
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{args:?} failed: {stderr}");
+        let hit = stderr.contains("using the cached result");
+        (String::from_utf8(output.stdout).unwrap(), hit)
+    }
+}
+
+impl Drop for Cached {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(self.input.parent().unwrap());
+    }
+}
+
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    let entries = fs::read_dir(dir).unwrap();
+    entries.map(|entry| entry.unwrap().path()).collect()
+}
+
+const DATA: &str = "Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n";
+
+#[test]
+fn an_identical_run_is_a_hit() {
+    let cached = Cached::new("hit", DATA);
+    let (first, hit) = cached.run(&[]);
+    assert!(!hit);
+    assert_eq!(cached.run(&[]), (first, true));
+}
+
+#[test]
+fn options_that_change_parsing_miss() {
+    let cached = Cached::new("options", DATA);
+    cached.run(&["--lenient"]);
+    for args in [
+        &["--lenient", "--delimiter", ","][..],
+        &["--lenient", "--strict-utf8"],
+        &["--lenient", "--encoding", "latin1"],
+    ] {
+        assert!(!cached.run(args).1, "{args:?}");
+        // Now cached under their own key
+        assert!(cached.run(args).1, "{args:?}");
+    }
+    // Options of the output share the entry
+    assert!(cached.run(&["--lenient", "--format", "jsonl"]).1);
+}
+
+#[test]
+fn a_changed_file_misses() {
+    let cached = Cached::new("file", DATA);
+    cached.run(&[]);
+    // Only the modification time
+    let file = OpenOptions::new().write(true).open(&cached.input).unwrap();
+    let modified = SystemTime::now() - Duration::from_secs(3600);
+    file.set_modified(modified).unwrap();
+    assert!(!cached.run(&[]).1);
+
+    let mut file = OpenOptions::new().append(true).open(&cached.input).unwrap();
+    writeln!(file, "Hamburg;30.0").unwrap();
+    drop(file);
+    let (output, hit) = cached.run(&[]);
+    assert!(!hit);
+    assert!(output.contains("Hamburg=-3.4/30.0/"), "{output}");
+}
+
+#[test]
+fn a_corrupt_entry_aggregates_again() {
+    let cached = Cached::new("corrupt", DATA);
+    let (expected, _) = cached.run(&[]);
+    let entries = entries(&cached.dir);
+    assert_eq!(entries.len(), 1);
+    fs::write(&entries[0], b"1BRCSTAT garbage").unwrap();
+    assert_eq!(cached.run(&[]), (expected.clone(), false));
+    // And stores the result again
+    assert_eq!(cached.run(&[]), (expected, true));
+}
@@ -0,0 +1,34 @@
+//! Fixtures shared by the tests, each of them uses only some
+
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+
+use onebrc::{hash_table::HashTable, StationStats, Sum};
+
+// Name to min, max, sum and count of each station
+pub type Aggregated = BTreeMap<Vec<u8>, (i32, i32, Sum, u32)>;
+
+// `rows` lines over `stations` names, spread so neighbours differ
+pub fn measurements(stations: usize, rows: usize) -> Vec<u8> {
+    named(rows, |row| format!("station {}", row * 7 % stations))
+}
+
+// `rows` lines with the name `name` gives each row
+pub fn named(rows: usize, name: impl Fn(usize) -> String) -> Vec<u8> {
+    let mut data = String::new();
+    for row in 0..rows {
+        data.push_str(&format!("{};{}.{}\n", name(row), row % 100, row % 10));
+    }
+    data.into_bytes()
+}
+
+pub fn aggregated(result: &HashTable<StationStats>) -> Aggregated {
+    result
+        .key_set()
+        .map(|(_, stats)| {
+            let values = (stats.min, stats.max, stats.sum, stats.count);
+            (stats.name.to_vec(), values)
+        })
+        .collect()
+}
@@ -0,0 +1,67 @@
+//! Output files written with `--compress` decompress to the plain output.
+//! Run with `cargo test --features zstd,gzip`.
+
+#![cfg(any(feature = "zstd", feature = "gzip"))]
+
+use std::{env, fs, io::Read, process::Command};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+
+fn run(args: &[&str]) -> Vec<u8> {
+    let output = Command::new(BINARY).args(args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output.stdout
+}
+
+// The output of `--compress` with `compression` decompressed by `decompress`
+fn compressed(compression: &str, decompress: impl Fn(&[u8]) -> Vec<u8>) {
+    let dir = env::temp_dir();
+    let input = dir.join(format!("compress-{compression}.txt"));
+    let output = dir.join(format!("compress-{compression}.out"));
+    let data = (0..10_000)
+        .map(|row| format!("station {};{}.{}\n", row % 500, row % 100, row % 10))
+        .collect::<String>();
+    fs::write(&input, data).unwrap();
+    let (input_path, output_path) = (input.to_str().unwrap(), output.to_str().unwrap());
+
+    for format in ["plain", "jsonl", "table"] {
+        let expected = run(&["--format", format, input_path]);
+        let args = [
+            "--format",
+            format,
+            "--compress",
+            compression,
+            "--output",
+            output_path,
+            input_path,
+        ];
+        run(&args);
+        let written = fs::read(&output).unwrap();
+        assert!(written.len() < expected.len());
+        assert_eq!(decompress(&written), expected, "{compression} {format}");
+    }
+    fs::remove_file(&input).unwrap();
+    fs::remove_file(&output).unwrap();
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn zstd_output_decompresses_to_the_plain_output() {
+    compressed("zstd", |written| zstd::decode_all(written).unwrap());
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_output_decompresses_to_the_plain_output() {
+    compressed("gzip", |written| {
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(written)
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        decompressed
+    });
+}
@@ -0,0 +1,79 @@
+//! Options of the config file, then of the environment, then of the command
+//! line, each overriding those before, and flags turned off with `--no-`
+
+use std::{env, fs, path::PathBuf};
+
+use onebrc::options::{Format, Options};
+
+// A config file of its own, removed when dropped
+struct Config(PathBuf);
+
+impl Config {
+    fn new(name: &str, toml: &str) -> Self {
+        let path = env::temp_dir().join(format!("config-{}-{name}.toml", std::process::id()));
+        fs::write(&path, toml).unwrap();
+        Self(path)
+    }
+
+    fn parse(&self, args: &[&str]) -> Options {
+        let config = ["--config", self.0.to_str().unwrap()];
+        let args = config.iter().chain(args).chain(&["measurements.txt"]);
+        Options::parse(args.map(|arg| arg.to_string())).unwrap()
+    }
+}
+
+impl Drop for Config {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// The only test that sets variables, the others do not look at what they
+// set
+#[test]
+fn the_command_line_overrides_the_environment_which_overrides_the_file() {
+    let config = Config::new("precedence", "threads = 2\nformat = \"csv\"\n");
+    env::remove_var("ONEBRC_THREADS");
+    env::remove_var("ONEBRC_FORMAT");
+    let options = config.parse(&[]);
+    assert_eq!(options.threads, Some(2));
+    assert_eq!(options.format, Format::Csv);
+
+    env::set_var("ONEBRC_THREADS", "3");
+    env::set_var("ONEBRC_FORMAT", "jsonl");
+    let options = config.parse(&[]);
+    env::remove_var("ONEBRC_THREADS");
+    assert_eq!(options.threads, Some(3));
+    assert_eq!(options.format, Format::JsonLines);
+
+    env::set_var("ONEBRC_THREADS", "3");
+    let options = config.parse(&["--threads", "4"]);
+    env::remove_var("ONEBRC_THREADS");
+    env::remove_var("ONEBRC_FORMAT");
+    assert_eq!(options.threads, Some(4));
+    assert_eq!(options.format, Format::JsonLines);
+}
+
+#[test]
+fn flags_of_the_file_are_turned_off_with_no() {
+    let config = Config::new("on", "lenient = true\nsummary = true\n");
+    let options = config.parse(&[]);
+    assert!(options.lenient && options.summary);
+    let options = config.parse(&["--no-lenient"]);
+    assert!(!options.lenient && options.summary);
+}
+
+#[test]
+fn flags_set_false_in_the_file_are_off_until_the_command_line() {
+    let config = Config::new("off", "lenient = false\n\n[run]\nsummary = false\n");
+    let options = config.parse(&[]);
+    assert!(!options.lenient && !options.summary);
+    let options = config.parse(&["--lenient", "--summary"]);
+    assert!(options.lenient && options.summary);
+}
+
+#[test]
+fn a_subcommand_table_turns_off_a_flag_of_the_file() {
+    let config = Config::new("run", "stddev = true\n\n[run]\nstddev = false\n");
+    assert!(!config.parse(&[]).stddev);
+}
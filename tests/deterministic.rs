@@ -0,0 +1,155 @@
+//! The output does not depend on how many threads aggregated the file, in
+//! any format and order
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+
+// A file of this test in the temporary directory, removed on drop
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("onebrc-{}-{name}", std::process::id()));
+        Self(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn run(args: &[&str]) -> Vec<u8> {
+    let output = Command::new(BINARY).args(args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output.stdout
+}
+
+fn measurements(name: &str) -> TempFile {
+    let file = TempFile::new(name);
+    let path = file.0.to_str().unwrap();
+    run(&[
+        "generate",
+        "--rows",
+        "200000",
+        "--stations",
+        "1000",
+        "--seed",
+        "3",
+        path,
+    ]);
+    file
+}
+
+#[test]
+fn output_is_identical_for_any_thread_count() {
+    let file = measurements("threads.txt");
+    let path = file.0.to_str().unwrap();
+    let orders: [&[&str]; 4] = [
+        &[],
+        &["--sort", "name", "--desc"],
+        &["--sort", "mean"],
+        &["--sort", "count", "--desc"],
+    ];
+    for format in ["plain", "jsonl", "csv", "table"] {
+        for order in orders {
+            let output = |threads: &str| {
+                let mut args = vec!["--threads", threads, "--format", format, "--color", "never"];
+                args.extend(order);
+                args.push(path);
+                run(&args)
+            };
+            let expected = output("1");
+            assert!(!expected.is_empty());
+            for threads in ["2", "8"] {
+                assert!(
+                    output(threads) == expected,
+                    "{format} {order:?} differs with {threads} threads"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn histograms_are_identical_for_any_thread_count() {
+    let file = measurements("histograms.txt");
+    let path = file.0.to_str().unwrap();
+    let histograms = |threads| run(&["--threads", threads, "--histogram", "--format", "csv", path]);
+    let expected = histograms("1");
+    // Every value lands in a bin
+    let counted = String::from_utf8_lossy(&expected)
+        .lines()
+        .skip(1)
+        .map(|line| line.rsplit(',').next().unwrap().parse::<u64>().unwrap())
+        .sum::<u64>();
+    assert_eq!(counted, 200_000);
+    assert_eq!(histograms("8"), expected);
+}
+
+#[test]
+fn windows_do_not_change_the_output() {
+    let file = measurements("windows.txt");
+    let path = file.0.to_str().unwrap();
+    let expected = run(&["--threads", "1", path]);
+    assert_eq!(run(&["--threads", "3", "--window", "1", path]), expected);
+    // Windows read into reused buffers
+    let read = ["--threads", "3", "--io", "read", "--window", "1", path];
+    assert_eq!(run(&read), expected);
+}
+
+#[test]
+fn tables_do_not_change_the_output() {
+    let file = measurements("tables.txt");
+    let path = file.0.to_str().unwrap();
+    let expected = run(&["--threads", "3", path]);
+    assert_eq!(run(&["--threads", "3", "--table", "std", path]), expected);
+    for shards in ["1", "4", "16"] {
+        let sharded = [
+            "--threads",
+            "3",
+            "--table",
+            "sharded",
+            "--shards",
+            shards,
+            path,
+        ];
+        assert_eq!(run(&sharded), expected, "differs with {shards} shards");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn pipes_are_read_like_files() {
+    use std::{io::Write, process::Stdio};
+
+    let file = measurements("pipe.txt");
+    let path = file.0.to_str().unwrap();
+    let expected = run(&["--threads", "3", path]);
+    let data = fs::read(path).unwrap();
+    // In a single window, and in several with lines cut between them
+    for args in [&["/dev/stdin"][..], &["--window", "1", "/dev/stdin"]] {
+        let mut child = Command::new(BINARY)
+            .args(["--threads", "3"])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = {
+            let data = data.clone();
+            std::thread::spawn(move || stdin.write_all(&data).unwrap())
+        };
+        let output = child.wait_with_output().unwrap();
+        writer.join().unwrap();
+        assert!(output.status.success(), "{args:?} failed");
+        assert_eq!(output.stdout, expected, "differs with {args:?}");
+    }
+}
@@ -0,0 +1,56 @@
+//! `diff` of two plain results, with stations added, removed and changed
+//! past the tolerance or within it
+
+use std::{env, fs, process::Command};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+
+// Exit code and output of comparing `expected` with `actual`
+fn diff(name: &str, expected: &str, actual: &str, args: &[&str]) -> (i32, String) {
+    let dir = env::temp_dir();
+    let paths = ["expected", "actual"]
+        .map(|which| dir.join(format!("diff-{}-{name}-{which}.txt", std::process::id())));
+    fs::write(&paths[0], expected).unwrap();
+    fs::write(&paths[1], actual).unwrap();
+    let output = Command::new(BINARY)
+        .arg("diff")
+        .args(args)
+        .args(&paths)
+        .output()
+        .unwrap();
+    for path in paths {
+        fs::remove_file(path).unwrap();
+    }
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    (output.status.code().unwrap(), stdout)
+}
+
+const EXPECTED: &str =
+    "{Bulawayo=8.9/8.9/8.9, Hamburg=-3.4/12.0/4.3, Washington, D.C.=1.0/2.0/1.5}\n";
+
+#[test]
+fn results_within_the_tolerance_match() {
+    let rounded = "{Bulawayo=8.9/8.9/8.9, Hamburg=-3.4/12.0/4.4, Washington, D.C.=1.0/2.0/1.5}\n";
+    assert_eq!(diff("same", EXPECTED, EXPECTED, &[]), (0, String::new()));
+    assert_eq!(diff("rounded", EXPECTED, rounded, &[]), (0, String::new()));
+    let (code, stdout) = diff("strict", EXPECTED, rounded, &["--tolerance", "0"]);
+    assert_eq!(code, 1);
+    assert_eq!(stdout, "Hamburg: mean 4.3 expected, got 4.4\n");
+}
+
+#[test]
+fn added_removed_and_changed_stations_are_listed() {
+    let actual = "{Bulawayo=8.9/9.9/8.9, Cracow=0.0/0.0/0.0, Washington, D.C.=1.0/2.0/1.5}\n";
+    let (code, stdout) = diff("changed", EXPECTED, actual, &[]);
+    assert_eq!(code, 1);
+    assert_eq!(
+        stdout,
+        "Bulawayo: max 8.9 expected, got 9.9\nHamburg: missing\nCracow: unexpected\n"
+    );
+}
+
+#[test]
+fn a_result_that_is_not_plain_fails() {
+    let (code, stdout) = diff("invalid", EXPECTED, "Hamburg;12.0\n", &[]);
+    assert_eq!((code, stdout), (2, String::new()));
+}
@@ -0,0 +1,54 @@
+//! `--distribution` counts every value of a generated file once, whatever
+//! the threads
+
+use std::{env, fs, process::Command};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+
+fn run(args: &[&str]) {
+    let output = Command::new(BINARY).args(args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn every_value_is_counted_once() {
+    let dir = env::temp_dir();
+    let input = dir.join(format!("distribution-{}.txt", std::process::id()));
+    let output = dir.join(format!("distribution-{}.csv", std::process::id()));
+    let (input_path, output_path) = (input.to_str().unwrap(), output.to_str().unwrap());
+    run(&["generate", "--rows", "50000", "--seed", "5", input_path]);
+
+    let distribution = |threads| {
+        let args = [
+            "--threads",
+            threads,
+            "--distribution",
+            output_path,
+            input_path,
+        ];
+        run(&args);
+        fs::read_to_string(&output).unwrap()
+    };
+    let expected = distribution("1");
+    assert_eq!(distribution("4"), expected);
+    let mut lines = expected.lines();
+    assert_eq!(lines.next(), Some("value,count"));
+    let counts = lines
+        .map(|line| {
+            let (value, count) = line.split_once(',').unwrap();
+            (value.parse::<f64>().unwrap(), count.parse::<u64>().unwrap())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(counts.iter().map(|&(_, count)| count).sum::<u64>(), 50_000);
+    // Every step from the lowest value to the highest
+    assert!(counts.first().unwrap().1 > 0 && counts.last().unwrap().1 > 0);
+    for pair in counts.windows(2) {
+        assert!((pair[1].0 - pair[0].0 - 0.1).abs() < 1e-9);
+    }
+    fs::remove_file(&input).unwrap();
+    fs::remove_file(&output).unwrap();
+}
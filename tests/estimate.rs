@@ -0,0 +1,32 @@
+//! Tables sized by `--estimate` aggregate like the default ones, whether the
+//! sample finds few stations, many or only some of them
+
+use onebrc::{aggregate, options::Options};
+
+mod common;
+
+use common::Aggregated;
+
+fn measurements(stations: usize, rows: usize) -> Vec<u8> {
+    // Every tenth row is of a station that only occurs once
+    common::named(rows, |row| match row % 10 {
+        0 => format!("rare {row}"),
+        _ => format!("station {}", row * 7 % stations),
+    })
+}
+
+fn aggregated(data: &[u8], estimate: bool) -> Aggregated {
+    let options = Options {
+        estimate,
+        ..Options::default()
+    };
+    common::aggregated(&aggregate(data, &options).unwrap())
+}
+
+#[test]
+fn estimated_tables_aggregate_like_default_ones() {
+    for (stations, rows) in [(1, 10), (20, 1_000), (10_000, 500_000)] {
+        let data = measurements(stations, rows);
+        assert_eq!(aggregated(&data, true), aggregated(&data, false));
+    }
+}
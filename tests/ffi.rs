@@ -0,0 +1,109 @@
+//! The C API called like a C program would, and its signatures against
+//! `include/onebrc.h`
+
+use std::{
+    env,
+    ffi::{c_char, CStr, CString},
+    fs,
+    mem::offset_of,
+    ptr,
+};
+
+use onebrc::ffi::{
+    brc_process_file, brc_result_free, brc_result_len, brc_result_station, BrcResult, BrcStation,
+};
+
+const HEADER: &str = include_str!("../include/onebrc.h");
+
+#[test]
+fn a_file_is_processed_through_the_c_api() {
+    let path = env::temp_dir().join(format!("ffi-{}.txt", std::process::id()));
+    fs::write(&path, "Hamburg;12.0\nSão Paulo;25.1\nHamburg;-3.4\n").unwrap();
+    let c_path = CString::new(path.to_str().unwrap()).unwrap();
+
+    let mut stations = unsafe {
+        let result = brc_process_file(c_path.as_ptr());
+        assert!(!result.is_null());
+        let stations = (0..brc_result_len(result))
+            .map(|i| {
+                let station = brc_result_station(result, i);
+                let name = CStr::from_ptr(station.name).to_str().unwrap().to_string();
+                assert_eq!(name.len(), station.name_len);
+                let values = (station.min, station.mean, station.max, station.count);
+                (name, values)
+            })
+            .collect::<Vec<_>>();
+        brc_result_free(result);
+        stations
+    };
+    fs::remove_file(&path).unwrap();
+    stations.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        stations,
+        [
+            ("Hamburg".to_string(), (-3.4, 4.3, 12.0, 2)),
+            ("São Paulo".to_string(), (25.1, 25.1, 25.1, 1)),
+        ]
+    );
+}
+
+#[test]
+fn a_missing_file_is_null() {
+    let path = CString::new("/nonexistent/measurements.txt").unwrap();
+    unsafe {
+        assert!(brc_process_file(path.as_ptr()).is_null());
+        assert!(brc_process_file(ptr::null()).is_null());
+        // Freeing NULL is allowed
+        brc_result_free(ptr::null_mut());
+    }
+}
+
+#[test]
+fn signatures_match_the_header() {
+    // The Rust side, which fails to compile on a change
+    let _: unsafe extern "C" fn(*const c_char) -> *mut BrcResult = brc_process_file;
+    let _: unsafe extern "C" fn(*const BrcResult) -> usize = brc_result_len;
+    let _: unsafe extern "C" fn(*const BrcResult, usize) -> BrcStation = brc_result_station;
+    let _: unsafe extern "C" fn(*mut BrcResult) = brc_result_free;
+    // And the declarations cbindgen makes of them
+    for declaration in [
+        "brc_result *brc_process_file(const char *path);",
+        "size_t brc_result_len(const brc_result *result);",
+        "brc_station brc_result_station(const brc_result *result, size_t index);",
+        "void brc_result_free(brc_result *result);",
+        "typedef struct brc_result brc_result;",
+    ] {
+        assert!(HEADER.contains(declaration), "{declaration}");
+    }
+
+    // Fields of the station in the order of the header, laid out like C
+    let fields = HEADER
+        .split("typedef struct {")
+        .nth(1)
+        .and_then(|rest| rest.split("} brc_station;").next())
+        .unwrap()
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.ends_with(';'))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fields,
+        [
+            "const char *name;",
+            "size_t name_len;",
+            "double min;",
+            "double mean;",
+            "double max;",
+            "uint32_t count;",
+        ]
+    );
+    let offsets = [
+        offset_of!(BrcStation, name),
+        offset_of!(BrcStation, name_len),
+        offset_of!(BrcStation, min),
+        offset_of!(BrcStation, mean),
+        offset_of!(BrcStation, max),
+        offset_of!(BrcStation, count),
+    ];
+    assert!(offsets.is_sorted());
+}
@@ -0,0 +1,78 @@
+//! Removing from the table shifts the rest of a probe chain back, also where
+//! it wraps around the end of the slots
+
+use onebrc::hash_table::HashTable;
+
+// Whether `key` with `value` is still found by probing
+fn found(table: &mut HashTable<u32>, key: u64, value: u32) -> bool {
+    let mut found = false;
+    table.insert_or_update(key, |&v| v == value, |_| found = true, || value);
+    if !found {
+        table.remove(key, |&v| v == value);
+    }
+    found
+}
+
+#[test]
+fn removing_wraps_around_the_end() {
+    let mut table = HashTable::new();
+    let last = table.stats().capacity as u64 - 1;
+    // All at home in the last slot, so the chain goes on from the first
+    for value in 0..4 {
+        table.push(last, value);
+    }
+    table.push(0, 4);
+    assert_eq!(table.stats().longest_probe, 3);
+
+    assert_eq!(table.remove(last, |&v| v == 0), Some(0));
+    assert_eq!(table.len(), 4);
+    assert_eq!(table.stats().longest_probe, 2);
+    for value in 1..4 {
+        assert!(found(&mut table, last, value), "{value}");
+    }
+    assert!(found(&mut table, 0, 4));
+    assert!(!found(&mut table, last, 0));
+}
+
+#[test]
+fn removing_shifts_a_displaced_chain_back() {
+    let mut table = HashTable::new();
+    // The second entry of 0 pushes those of 1 and 2 off their home slots
+    table.push(0, 0);
+    table.push(0, 1);
+    table.push(1, 2);
+    table.push(2, 3);
+    table.push(5, 4);
+    assert_eq!(table.stats().longest_probe, 1);
+
+    assert_eq!(table.remove(0, |&v| v == 0), Some(0));
+    assert_eq!(table.len(), 4);
+    // Every entry of the chain is back home, the one past a gap was already
+    assert_eq!(table.stats().longest_probe, 0);
+    for (key, value) in [(0, 1), (1, 2), (2, 3), (5, 4)] {
+        assert!(found(&mut table, key, value), "{key}");
+    }
+}
+
+#[test]
+fn removing_an_absent_key_changes_nothing() {
+    let mut table = HashTable::new();
+    table.push(3, 0);
+    table.push(3, 1);
+    table.push(4, 2);
+
+    // Neither a key without entries, nor one of another name, which stops
+    // at the entry of 4 as it is closer to home than one of 3 would be
+    assert_eq!(table.remove(7, |_| true), None);
+    assert_eq!(table.remove(3, |&v| v == 2), None);
+    // Probing for 5 goes past the entry of 4 to an empty slot
+    assert_eq!(table.remove(5, |_| true), None);
+    assert_eq!(table.len(), 3);
+    assert_eq!(table.stats().longest_probe, 1);
+    let mut entries = table
+        .key_set()
+        .map(|(key, &v)| (key, v))
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(entries, [(3, 0), (3, 1), (4, 2)]);
+}
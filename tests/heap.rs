@@ -0,0 +1,31 @@
+//! With `heap-profile` a run ends with the heap usage of each phase
+#![cfg(all(feature = "heap-profile", not(feature = "alloc-check")))]
+
+use std::{env, fs, process::Command};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+
+#[test]
+fn every_phase_gets_a_line() {
+    let input = env::temp_dir().join(format!("heap-{}.txt", std::process::id()));
+    fs::write(&input, "Hamburg;12.0\nBulawayo;8.9\n".repeat(1000)).unwrap();
+    let output = Command::new(BINARY)
+        .args(["--io", "read"])
+        .arg(&input)
+        .output()
+        .unwrap();
+    fs::remove_file(&input).unwrap();
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let mut lines = stderr
+        .lines()
+        .skip_while(|line| !line.starts_with("heap: peak"));
+    assert!(lines.next().is_some(), "{stderr}");
+    let mut phases = lines
+        .map(|line| line.split_whitespace().next().unwrap())
+        .collect::<Vec<_>>();
+    // Merging only when there are several threads
+    phases.retain(|&phase| phase != "merge");
+    assert_eq!(phases, ["load", "aggregate", "output"]);
+}
@@ -0,0 +1,36 @@
+//! The profile of `inspect` counts the rows, names, values and problems of a
+//! file read whole
+
+use std::{env, fs, process::Command};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+
+#[test]
+fn small_files_are_profiled_whole() {
+    let input = env::temp_dir().join(format!("inspect-{}.txt", std::process::id()));
+    // Four malformed rows, the last also not UTF-8
+    let data = b"\xEF\xBB\xBFHamburg;12.0\r\nBulawayo;-8.9\nPalembang;38.8\nLat\x01n;9.9\n\
+        St. John's;15.\nCracow;abc\nno delimiter\nRo\xE9ma;1.0\n";
+    fs::write(&input, data).unwrap();
+
+    let output = Command::new(BINARY)
+        .args(["inspect", input.to_str().unwrap()])
+        .output()
+        .unwrap();
+    fs::remove_file(&input).unwrap();
+    assert!(output.status.success());
+    let profile = String::from_utf8(output.stdout).unwrap();
+    let lines = profile.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], format!("file: {} bytes, read whole", data.len()));
+    assert!(lines[1].starts_with("rows: 8, "), "{profile}");
+    assert_eq!(lines[2], "names: 5 to 9 bytes, median 7, 99th percentile 9");
+    assert_eq!(
+        lines[3],
+        "values: -8.90 to 38.80, 0 without exactly one decimal"
+    );
+    assert_eq!(
+        lines[4],
+        "encoding: byte order mark, 1 names not UTF-8, 1 with control characters, 1 CRLF lines"
+    );
+    assert_eq!(lines[5], "malformed: 4 of 8 rows, 50.00%");
+}
@@ -0,0 +1,47 @@
+//! Memory accounting of tables and input read into memory, see `memory`.
+//! One test, the counts are global to the process.
+
+use std::{env, fs, fs::File, io::ErrorKind};
+
+use onebrc::{aggregate, memory, options::Options, Io};
+
+#[test]
+fn counted_memory_is_freed_and_limited() {
+    let path = env::temp_dir().join("memory-measurements.txt");
+    let data = (0..100_000)
+        .map(|row| format!("station {};{}.{}\n", row % 5_000, row % 100, row % 10))
+        .collect::<String>();
+    fs::write(&path, &data).unwrap();
+    let file = File::open(&path).unwrap();
+
+    let input = Io::Read.load(&file).unwrap();
+    assert!(memory::used() >= data.len());
+    let options = Options::default();
+    let result = aggregate(&input, &options).unwrap();
+    assert!(memory::used() > data.len());
+    drop(result);
+    drop(input);
+    assert_eq!(memory::used(), 0);
+    assert!(memory::peak() > data.len());
+
+    memory::set_limit(Some(data.len() / 2));
+    assert!(Io::Read.load(&file).is_err());
+
+    // Tables start within their share of the limit and fail past it
+    let limited = |threads, limit| Options {
+        threads: Some(threads),
+        max_memory: Some(limit),
+        ..Options::default()
+    };
+    memory::set_limit(Some(64 << 20));
+    let options = limited(64, 64 << 20);
+    assert_eq!(aggregate(b"a;1.0\nb;2.0\n", &options).unwrap().len(), 2);
+    memory::set_limit(Some(1 << 20));
+    let options = limited(2, 1 << 20);
+    let Err(error) = aggregate(data.as_bytes(), &options) else {
+        panic!("aggregated past --max-memory");
+    };
+    assert_eq!(error.kind(), ErrorKind::OutOfMemory);
+    memory::set_limit(None);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,85 @@
+//! Merging saved states gives what aggregating their inputs together does,
+//! and printed results are refused rather than merged inexactly
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+
+fn temp(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("merge-{}-{name}", std::process::id()))
+}
+
+// Standard output of the binary on `args`, which must succeed
+fn run(args: &[&str]) -> String {
+    let output = Command::new(BINARY)
+        .args(args)
+        .env_remove("ONEBRC_FORMAT")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn merged_states_match_aggregating_both_inputs() {
+    let parts = [
+        "Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n",
+        "São Paulo;25.1\nHamburg;34.2\nBulawayo;-0.1\n",
+    ];
+    let mut states = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        let input = temp(&format!("{i}.txt"));
+        let state = temp(&format!("{i}.state"));
+        fs::write(&input, part).unwrap();
+        run(&[
+            "--save-state",
+            state.to_str().unwrap(),
+            input.to_str().unwrap(),
+        ]);
+        fs::remove_file(&input).unwrap();
+        states.push(state);
+    }
+    let both = temp("both.txt");
+    fs::write(&both, parts.concat()).unwrap();
+
+    for format in ["plain", "jsonl"] {
+        let mut args = vec!["merge", "--format", format];
+        args.extend(states.iter().map(|state| state.to_str().unwrap()));
+        assert_eq!(
+            run(&args),
+            run(&["--format", format, both.to_str().unwrap()]),
+            "{format}"
+        );
+    }
+    fs::remove_file(&both).unwrap();
+    for state in states {
+        fs::remove_file(state).unwrap();
+    }
+}
+
+#[test]
+fn printed_results_are_refused() {
+    let input = temp("printed.txt");
+    fs::write(&input, "Hamburg;12.0\n").unwrap();
+    for (format, refused) in [
+        ("plain", "a plain result"),
+        ("jsonl", "a JSON lines result"),
+    ] {
+        let result = temp(&format!("printed.{format}"));
+        fs::write(&result, run(&["--format", format, input.to_str().unwrap()])).unwrap();
+        let output = Command::new(BINARY)
+            .arg("merge")
+            .arg(&result)
+            .output()
+            .unwrap();
+        fs::remove_file(&result).unwrap();
+        assert!(!output.status.success(), "{format}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(refused), "{format}: {stderr}");
+    }
+    fs::remove_file(&input).unwrap();
+}
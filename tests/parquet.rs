@@ -0,0 +1,75 @@
+//! Parquet output read back, with the column of `--stddev`.
+//! Run with `cargo test --features parquet`.
+
+#![cfg(feature = "parquet")]
+
+use std::{env, fs, fs::File, process::Command};
+
+use arrow_array::{Array, Float64Array, RecordBatch, StringArray};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+
+// The single batch the binary writes for `args` on `data`
+fn written(name: &str, data: &str, args: &[&str]) -> RecordBatch {
+    let dir = env::temp_dir();
+    let input = dir.join(format!("parquet-{name}.txt"));
+    let output = dir.join(format!("parquet-{name}.parquet"));
+    fs::write(&input, data).unwrap();
+    let result = Command::new(BINARY)
+        .args(["--format", "parquet", "--output"])
+        .arg(&output)
+        .args(args)
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(
+        result.status.success(),
+        "{args:?} failed: {}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&output).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    let mut batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    fs::remove_file(&input).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert_eq!(batches.len(), 1);
+    batches.pop().unwrap()
+}
+
+fn floats<'a>(batch: &'a RecordBatch, column: &str) -> &'a [f64] {
+    let column = batch.column_by_name(column).unwrap();
+    column
+        .as_any()
+        .downcast_ref::<Float64Array>()
+        .unwrap()
+        .values()
+}
+
+#[test]
+fn standard_deviations_are_written_with_stddev() {
+    let data = "Hamburg;1.0\nBulawayo;8.9\nHamburg;3.0\n";
+    let batch = written("plain", data, &[]);
+    let names = ["name", "min", "mean", "max", "count"];
+    let schema = batch.schema();
+    let columns = schema.fields().iter().map(|field| field.name());
+    assert!(columns.eq(names.iter()));
+
+    for (name, unit, deviation) in [("celsius", "c", 1.0), ("fahrenheit", "f", 1.8)] {
+        let batch = written(name, data, &["--stddev", "--unit", unit]);
+        let names = batch.column_by_name("name").unwrap();
+        let names = names.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(
+            names.iter().flatten().collect::<Vec<_>>(),
+            ["Bulawayo", "Hamburg"]
+        );
+        let deviations = floats(&batch, "stddev");
+        assert_eq!(deviations[0], 0.0);
+        assert!(
+            (deviations[1] - deviation).abs() < 1e-9,
+            "{deviations:?} {unit}"
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! The value parser against the digit by digit loop it replaced, over every
+//! value the challenge allows
+
+use onebrc::{options::Options, visit};
+
+// The previous parser, one multiply per digit
+fn reference(data: &[u8]) -> i32 {
+    let neg = data[0] == b'-';
+    let mut result = 0;
+    for &digit in &data[neg as usize..data.len() - 2] {
+        result = result * 10 + (digit - b'0') as i32;
+    }
+    result = result * 10 + (data[data.len() - 1] - b'0') as i32;
+    if neg {
+        -result
+    } else {
+        result
+    }
+}
+
+// `-99.9` to `99.9`, including `-0.0`
+fn all_values() -> Vec<String> {
+    let mut values = (-999..=999)
+        .map(|tenths: i32| {
+            let sign = if tenths < 0 { "-" } else { "" };
+            let tenths = tenths.abs();
+            format!("{sign}{}.{}", tenths / 10, tenths % 10)
+        })
+        .collect::<Vec<_>>();
+    values.push("-0.0".into());
+    values
+}
+
+#[test]
+fn every_value_parses_like_the_reference() {
+    let values = all_values();
+    let data = values
+        .iter()
+        .map(|value| format!("station;{value}\n"))
+        .collect::<String>();
+
+    let mut parsed = Vec::new();
+    visit(data.as_bytes(), &Options::default(), |_, value| {
+        parsed.push(value)
+    })
+    .unwrap();
+
+    assert_eq!(parsed.len(), values.len());
+    for (value, parsed) in values.iter().zip(parsed) {
+        assert_eq!(parsed, reference(value.as_bytes()), "`{value}`");
+        let decimal = (value.parse::<f64>().unwrap() * 10.0).round() as i32;
+        assert_eq!(parsed, decimal, "`{value}`");
+    }
+}
+
+#[test]
+fn values_parse_alike_in_any_order() {
+    let values = all_values();
+    // Interleave the lengths the parser selects between, in a fixed shuffle
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut shuffled = values.clone();
+    for i in (1..shuffled.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        shuffled.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+    let data = shuffled
+        .iter()
+        .map(|value| format!("{value};{value}\r\n"))
+        .collect::<String>();
+
+    let mut visited = 0;
+    visit(data.as_bytes(), &Options::default(), |name, value| {
+        assert_eq!(
+            value,
+            reference(name),
+            "`{}`",
+            String::from_utf8_lossy(name)
+        );
+        visited += 1;
+    })
+    .unwrap();
+    assert_eq!(visited, values.len());
+}
@@ -0,0 +1,400 @@
+//! The line parser against a plain reference, run once as is and once with
+//! `--features unsafe-fast` to compare the checked and unchecked paths
+
+use std::{collections::BTreeMap, io::ErrorKind};
+
+use onebrc::{
+    aggregate, aggregate_with,
+    aggregator::MinMeanMax,
+    options::{Dedup, Encoding, Options, Period},
+    table::Table,
+    Malformed, Processor, Sum,
+};
+
+mod common;
+
+use common::Aggregated;
+
+// Name to min, max, sum and count, parsed without any tricks
+fn reference(data: &str) -> Aggregated {
+    let mut stations = BTreeMap::new();
+    for line in data.lines() {
+        let (name, value) = line.split_once(';').unwrap();
+        let value = (value.parse::<f64>().unwrap() * 10.0).round() as i32;
+        let stats = stations
+            .entry(name.as_bytes().to_vec())
+            .or_insert((i32::MAX, i32::MIN, 0, 0));
+        stats.0 = stats.0.min(value);
+        stats.1 = stats.1.max(value);
+        stats.2 += value as Sum;
+        stats.3 += 1;
+    }
+    stations
+}
+
+fn parsed(data: &str, threads: usize) -> Aggregated {
+    let processor = Processor::builder().threads(threads).build();
+    common::aggregated(&processor.process_bytes(data.as_bytes()).unwrap())
+}
+
+// Well formed lines with names of every length and both value shapes
+fn measurements(lines: usize, seed: u64, line_end: &str) -> String {
+    let mut state = seed;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let mut data = String::new();
+    for _ in 0..lines {
+        let length = 1 + next() as usize % 100;
+        let name = (0..length)
+            .map(|i| (b'a' + ((next() as usize + i) % 26) as u8) as char)
+            .collect::<String>();
+        let tenths = (next() % 1999) as i32 - 999;
+        let sign = if tenths < 0 { "-" } else { "" };
+        let tenths = tenths.abs();
+        data += &format!("{name};{sign}{}.{}{line_end}", tenths / 10, tenths % 10);
+    }
+    data
+}
+
+#[test]
+fn lines_parse_like_the_reference() {
+    for (seed, line_end) in [(1, "\n"), (2, "\r\n")] {
+        let data = measurements(5000, seed, line_end);
+        let expected = reference(&data);
+        for threads in [1, 3, 8] {
+            assert_eq!(parsed(&data, threads), expected, "{threads} threads");
+        }
+    }
+}
+
+#[test]
+fn repeated_stations_parse_like_the_reference() {
+    let data = (0..2000)
+        .map(|i| format!("station {};{}.{}\n", i % 7, i % 100 - 50, i % 10))
+        .collect::<String>();
+    assert_eq!(parsed(&data, 4), reference(&data));
+}
+
+#[test]
+fn names_of_one_and_a_hundred_bytes_parse() {
+    let data = ["a", &"b".repeat(100)]
+        .iter()
+        .flat_map(|name| [format!("{name};-99.9\n"), format!("{name};99.9\n")])
+        .collect::<String>()
+        .repeat(50);
+    for threads in [1, 3] {
+        assert_eq!(
+            parsed(&data, threads),
+            reference(&data),
+            "{threads} threads"
+        );
+    }
+}
+
+#[test]
+fn a_last_line_with_or_without_newline_parses() {
+    for line_end in ["\n", "\r\n"] {
+        for last in ["", line_end] {
+            let data = measurements(100, 3, line_end) + "end;-1.5" + last;
+            for threads in [1, 2, 8] {
+                let context = format!("{last:?} at the end, {threads} threads");
+                assert_eq!(parsed(&data, threads), reference(&data), "{context}");
+            }
+        }
+    }
+}
+
+#[test]
+fn a_byte_order_mark_is_not_part_of_the_first_name() {
+    let data = measurements(100, 4, "\n");
+    let marked = format!("\u{feff}{data}");
+    for threads in [1, 3] {
+        assert_eq!(
+            parsed(&marked, threads),
+            reference(&data),
+            "{threads} threads"
+        );
+    }
+}
+
+#[test]
+fn stations_are_grouped_by_period() {
+    let data = "Hamburg;2024-03-15T12:00:00;12.0\nHamburg;2024-03-16T01:00:00;8.0\n\
+        Hamburg;2024-04-01T00:00:00;-1.5\nBulawayo;2024-03-02;20.1\n"
+        .repeat(50);
+    for threads in [1, 3] {
+        let processor = Processor::builder()
+            .threads(threads)
+            .columns(0, 2)
+            .group_by(1, Period::Month)
+            .build();
+        let groups = processor
+            .process_bytes(data.as_bytes())
+            .unwrap()
+            .key_set()
+            .map(|(_, stats)| (String::from_utf8(stats.name.to_vec()).unwrap(), stats.count))
+            .collect::<BTreeMap<_, _>>();
+        let expected = [
+            ("Bulawayo@2024-03", 50),
+            ("Hamburg@2024-03", 100),
+            ("Hamburg@2024-04", 50),
+        ];
+        let expected = expected.map(|(name, count)| (name.to_string(), count));
+        assert_eq!(groups, BTreeMap::from(expected), "{threads} threads");
+    }
+}
+
+#[test]
+fn values_of_columns_only_parse_in_the_shape_asked_for() {
+    let data = "x,Hamburg,12.0\ny,Bulawayo,1234.5\n";
+    let processor = |flexible| {
+        Processor::builder()
+            .threads(1)
+            .delimiter(b',')
+            .columns(1, 2)
+            .flexible_values(flexible)
+            .build()
+    };
+    let Err(error) = processor(false).process_bytes(data.as_bytes()) else {
+        panic!("parsed a value the parser does not take");
+    };
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(
+        error.to_string(),
+        "the value `1234.5` is not a number like 12.3, in the line `y,Bulawayo,1234.5`; \
+         --flexible-values takes others"
+    );
+    let processor = processor(true);
+    let maxima = processor
+        .process_bytes(data.as_bytes())
+        .unwrap()
+        .key_set()
+        .map(|(_, stats)| (String::from_utf8(stats.name.to_vec()).unwrap(), stats.max))
+        .collect::<BTreeMap<_, _>>();
+    let expected = [("Bulawayo", 123450), ("Hamburg", 1200)];
+    let expected = expected.map(|(name, max)| (name.to_string(), max));
+    assert_eq!(maxima, BTreeMap::from(expected));
+}
+
+#[test]
+fn repeated_lines_are_skipped_once_seen() {
+    let data = "Hamburg;12.0\nHamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n".repeat(20);
+    let counts = |dedup| {
+        let processor = Processor::builder().threads(1).dedup(dedup).build();
+        processor
+            .process_bytes(data.as_bytes())
+            .unwrap()
+            .key_set()
+            .map(|(_, stats)| (String::from_utf8(stats.name.to_vec()).unwrap(), stats.count))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let expected = [("Bulawayo", 1), ("Hamburg", 2)];
+    let expected = expected.map(|(name, count)| (name.to_string(), count));
+    assert_eq!(counts(Dedup::Skip), BTreeMap::from(expected));
+    let expected = [("Bulawayo", 20), ("Hamburg", 60)];
+    let expected = expected.map(|(name, count)| (name.to_string(), count));
+    assert_eq!(counts(Dedup::Count), BTreeMap::from(expected));
+    // All but the first of each of the three distinct lines, either way
+    for dedup in [Dedup::Skip, Dedup::Count] {
+        let options = Options {
+            threads: Some(1),
+            dedup: Some(dedup),
+            ..Options::default()
+        };
+        let (_, skipped) = aggregate_with::<MinMeanMax>(data.as_bytes(), &options).unwrap();
+        assert_eq!(skipped.duplicates, 77);
+    }
+}
+
+#[test]
+fn flexible_values_keep_their_range() {
+    let data = "a;400.5\na;10\nb;-1234567.89\n";
+    for lenient in [false, true] {
+        let processor = Processor::builder()
+            .threads(1)
+            .flexible_values(true)
+            .lenient(lenient)
+            .build();
+        let stations = processor
+            .process_bytes(data.as_bytes())
+            .unwrap()
+            .key_set()
+            .map(|(_, stats)| {
+                let name = String::from_utf8(stats.name.to_vec()).unwrap();
+                (name, (stats.min, stats.max, stats.sum, stats.count))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let expected = [
+            ("a", (1000, 40050, 41050, 2)),
+            ("b", (-123456789, -123456789, -123456789, 1)),
+        ];
+        let expected = expected.map(|(name, stats)| (name.to_string(), stats));
+        assert_eq!(stations, BTreeMap::from(expected));
+    }
+}
+
+#[test]
+fn malformed_lines_are_skipped_and_counted() {
+    let long = "x".repeat(101);
+    let data = format!("Hamburg;12.0\nHamburg\nBulawayo;8.x\n{long};1.0\n\nHamburg;-3.4\n");
+    let processor = Processor::builder().threads(1).lenient(true).build();
+    let counts = processor
+        .process_bytes(data.as_bytes())
+        .unwrap()
+        .key_set()
+        .map(|(_, stats)| (String::from_utf8(stats.name.to_vec()).unwrap(), stats.count))
+        .collect::<BTreeMap<_, _>>();
+    assert_eq!(counts, BTreeMap::from([("Hamburg".to_string(), 2)]));
+    let options = Options {
+        threads: Some(1),
+        lenient: true,
+        ..Options::default()
+    };
+    let (_, skipped) = aggregate_with::<MinMeanMax>(data.as_bytes(), &options).unwrap();
+    assert_eq!(Malformed::ALL.map(|why| skipped.malformed(why)), [1, 1, 1]);
+}
+
+#[test]
+fn names_that_are_not_utf8_fail_with_strict_utf8() {
+    let data = b"Hamburg;12.0\nHamb\xfcrg;8.9\n";
+    let options = Options {
+        threads: Some(1),
+        ..Options::default()
+    };
+    assert_eq!(aggregate(data, &options).unwrap().len(), 2);
+    let strict = Options {
+        strict_utf8: true,
+        ..options
+    };
+    let Err(error) = aggregate(data, &strict) else {
+        panic!("aggregated a name that is not UTF-8");
+    };
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(
+        error.to_string(),
+        "the station name `Hamb\\xfcrg` is not UTF-8, in the line `Hamb\\xfcrg;8.9`"
+    );
+}
+
+#[test]
+fn stations_whose_keys_collide_stay_apart() {
+    // Long names of the same key
+    let data = "StationNameLongerAa;1.0\nStationNameLongerBB;9.0\n".repeat(3);
+    let mut tables = vec![Table::Robin, Table::Std, Table::Sharded];
+    if cfg!(feature = "hashbrown") {
+        tables.push(Table::Hashbrown);
+    }
+    for table in tables {
+        for threads in [1, 2] {
+            let options = Options {
+                table,
+                threads: Some(threads),
+                ..Options::default()
+            };
+            let result = aggregate(data.as_bytes(), &options).unwrap();
+            let keys = result.key_set().map(|(key, _)| key).collect::<Vec<_>>();
+            assert_eq!(keys.len(), 2, "{table:?} on {threads} threads");
+            assert_eq!(keys[0], keys[1]);
+            let maxima = result
+                .key_set()
+                .map(|(_, stats)| (String::from_utf8(stats.name.to_vec()).unwrap(), stats.max))
+                .collect::<BTreeMap<_, _>>();
+            let expected = [("StationNameLongerAa", 10), ("StationNameLongerBB", 90)];
+            let expected = expected.map(|(name, max)| (name.to_string(), max));
+            assert_eq!(maxima, BTreeMap::from(expected));
+        }
+    }
+}
+
+#[test]
+fn quoted_fields_may_hold_the_delimiter_and_quotes() {
+    let data = "\"a;b\";1.0\n\"say \"\"hi\"\"\";2.0\n\"a;b\";3.0\n\"open;4.0\nplain;5.0\n";
+    let options = Options {
+        threads: Some(1),
+        quotes: true,
+        lenient: true,
+        ..Options::default()
+    };
+    let (result, skipped) = aggregate_with::<MinMeanMax>(data.as_bytes(), &options).unwrap();
+    let stations = result
+        .key_set()
+        .map(|(_, station)| (station.name.to_vec(), station.stats.count))
+        .collect::<BTreeMap<_, _>>();
+    let expected = [
+        (b"a;b".to_vec(), 2),
+        (b"plain".to_vec(), 1),
+        (b"say \"hi\"".to_vec(), 1),
+    ];
+    assert_eq!(stations, BTreeMap::from(expected));
+    // The unterminated quote takes in the rest of the line, value and all
+    assert_eq!(Malformed::ALL.map(|why| skipped.malformed(why)), [0, 1, 0]);
+}
+
+#[test]
+fn latin1_names_are_read_as_utf8() {
+    let data = b"S\xe3o Paulo;1.0\nSao Paulo;2.0\nS\xe4o Paulo;3.0\nS\xe3o Paulo;5.0\n";
+    let options = Options {
+        encoding: Encoding::Latin1,
+        ..Options::default()
+    };
+    for threads in [1, 3] {
+        let options = Options {
+            threads: Some(threads),
+            ..options.clone()
+        };
+        let stations = common::aggregated(&aggregate(data, &options).unwrap());
+        let expected = [
+            ("Sao Paulo", (20, 20, 20, 1)),
+            ("São Paulo", (10, 50, 60, 2)),
+            ("Säo Paulo", (30, 30, 30, 1)),
+        ]
+        .map(|(name, values)| (name.as_bytes().to_vec(), values));
+        assert_eq!(stations, BTreeMap::from(expected), "{threads} threads");
+    }
+}
+
+#[test]
+fn other_delimiters_parse_like_the_reference() {
+    let data = measurements(500, 5, "\n");
+    for delimiter in [b',', b'\t'] {
+        let delimited = data.replace(';', &char::from(delimiter).to_string());
+        for threads in [1, 3] {
+            let processor = Processor::builder()
+                .threads(threads)
+                .delimiter(delimiter)
+                .build();
+            let result = processor.process_bytes(delimited.as_bytes()).unwrap();
+            let context = format!("{delimiter:?}, {threads} threads");
+            assert_eq!(common::aggregated(&result), reference(&data), "{context}");
+        }
+    }
+}
+
+#[test]
+fn a_header_is_skipped_and_not_malformed() {
+    let data = measurements(100, 6, "\n");
+    let headed = format!("station;measurement\n{data}");
+    for threads in [1, 3] {
+        let options = Options {
+            threads: Some(threads),
+            lenient: true,
+            ..Options::default()
+        };
+        let (result, skipped) = aggregate_with::<MinMeanMax>(headed.as_bytes(), &options).unwrap();
+        let stations = result
+            .key_set()
+            .map(|(_, station)| (station.name.to_vec(), station.stats.count))
+            .collect::<BTreeMap<_, _>>();
+        let expected = reference(&data)
+            .into_iter()
+            .map(|(name, (_, _, _, count))| (name, count))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(stations, expected, "{threads} threads");
+        let malformed = Malformed::ALL.map(|why| skipped.malformed(why));
+        assert_eq!(malformed, [0; 3], "{threads} threads");
+    }
+}
@@ -0,0 +1,67 @@
+//! Aggregating with a perfect hash of `--stations`, or into a table with
+//! entries reserved by `--reserve`, gives the same result as without, with
+//! the whole list, part of it or a list of other names
+
+use std::{env, fs, ops::Range, sync::Arc};
+
+use onebrc::{
+    aggregate,
+    options::Options,
+    perfect::{self, StationList},
+};
+
+mod common;
+
+use common::{measurements, Aggregated};
+
+fn aggregated(data: &[u8], options: Options) -> Aggregated {
+    common::aggregated(&aggregate(data, &options).unwrap())
+}
+
+fn names(stations: Range<usize>) -> Vec<String> {
+    stations
+        .map(|station| format!("station {station}"))
+        .collect()
+}
+
+fn list(names: &[String]) -> StationList {
+    StationList::new(names.iter().map(|name| name.as_bytes())).unwrap()
+}
+
+#[test]
+fn lists_of_any_size_hash_perfectly() {
+    for stations in [0, 1, 2, 3, 413, 10_000] {
+        let list = list(&names(0..stations));
+        assert_eq!(list.len(), stations);
+    }
+}
+
+#[test]
+fn stations_aggregate_like_without_a_list() {
+    let data = measurements(1_000, 50_000);
+    let expected = aggregated(&data, Options::default());
+    for listed in [0..1_000, 0..300, 2_000..3_000] {
+        let options = Options {
+            station_list: Some(Arc::new(list(&names(listed)))),
+            ..Options::default()
+        };
+        assert_eq!(aggregated(&data, options), expected);
+    }
+}
+
+#[test]
+fn reserved_stations_aggregate_like_without() {
+    let data = measurements(1_000, 50_000);
+    let expected = aggregated(&data, Options::default());
+    for listed in [0..1_000, 0..300, 2_000..3_000] {
+        let path = env::temp_dir().join(format!("reserve-{}-{}", listed.start, listed.end));
+        fs::write(&path, names(listed).join("\n")).unwrap();
+        let keys = perfect::read_keys(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let options = Options {
+            reserved: Some(keys.into()),
+            ..Options::default()
+        };
+        assert_eq!(aggregated(&data, options), expected);
+    }
+}
@@ -0,0 +1,84 @@
+//! The embedding API on a file: stations yielded by `process_iter` and the
+//! lines the visitors see
+
+use std::{env, fs, path::PathBuf};
+
+use onebrc::{aggregate, options::Options, Processor};
+
+mod common;
+
+// The measurements in a file of their own, removed when dropped
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str, data: &[u8]) -> Self {
+        let path = env::temp_dir().join(format!("processor-{}-{name}", std::process::id()));
+        fs::write(&path, data).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// Name and tenths of every line of `data`, in order
+fn lines(data: &[u8]) -> Vec<(Vec<u8>, i32)> {
+    let data = std::str::from_utf8(data).unwrap();
+    data.lines()
+        .map(|line| {
+            let (name, value) = line.split_once(';').unwrap();
+            let value = (value.parse::<f64>().unwrap() * 10.0).round() as i32;
+            (name.as_bytes().to_vec(), value)
+        })
+        .collect()
+}
+
+#[test]
+fn iterated_stations_are_sorted_and_aggregated() {
+    let data = common::measurements(300, 20_000);
+    let file = TempFile::new("iter", &data);
+    let processor = Processor::builder().threads(4).build();
+    let stations = processor
+        .process_iter(&file.0)
+        .unwrap()
+        .map(|(name, stats)| {
+            assert_eq!(name.as_bytes(), &*stats.name);
+            let values = (stats.min, stats.max, stats.sum, stats.count);
+            (name.into_bytes(), values)
+        })
+        .collect::<Vec<_>>();
+    let options = Options {
+        threads: Some(4),
+        ..Options::default()
+    };
+    let expected = common::aggregated(&aggregate(&data, &options).unwrap());
+    // A map iterates in name order, so this also checks the order
+    assert_eq!(stations, expected.into_iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn visitors_see_every_line_once_in_order() {
+    let data = common::measurements(50, 20_000);
+    let file = TempFile::new("visit", &data);
+    let expected = lines(&data);
+    for threads in [1, 3, 8] {
+        let processor = Processor::builder().threads(threads).build();
+        let states = processor
+            .visit_parallel(&file.0, Vec::new, |seen, name, value| {
+                seen.push((name.to_vec(), value))
+            })
+            .unwrap();
+        assert!(states.len() <= threads, "{threads} threads");
+        // Chunks are consecutive, so their lines joined are the file
+        assert_eq!(states.concat(), expected, "{threads} threads");
+
+        let mut seen = Vec::new();
+        processor
+            .visit(&file.0, |name, value| seen.push((name.to_vec(), value)))
+            .unwrap();
+        assert_eq!(seen, expected, "{threads} threads");
+    }
+}
@@ -0,0 +1,27 @@
+//! Results survive a round trip through serde, here as JSON
+
+use onebrc::{aggregate, hash_table::HashTable, options::Options, StationStats, Sum};
+
+// Key to name and accumulator, in key order
+fn stations(result: &HashTable<StationStats>) -> Vec<(u64, Vec<u8>, Sum, u32, i32, i32)> {
+    let mut stations = result
+        .key_set()
+        .map(|(key, stats)| {
+            let name = stats.name.to_vec();
+            (key, name, stats.sum, stats.count, stats.min, stats.max)
+        })
+        .collect::<Vec<_>>();
+    stations.sort();
+    stations
+}
+
+#[test]
+fn results_round_trip_through_json() {
+    let data = "Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\nSão Paulo;25.1\na;-99.9\n".repeat(10);
+    let options = Options::default();
+    let result = aggregate(data.as_bytes(), &options).unwrap();
+    let json = serde_json::to_string(&result).unwrap();
+    let read = serde_json::from_str::<HashTable<StationStats>>(&json).unwrap();
+    assert_eq!(read.len(), 4);
+    assert_eq!(stations(&read), stations(&result));
+}
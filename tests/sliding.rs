@@ -0,0 +1,46 @@
+//! `--follow --window` of a stream only shows the lines still in the window
+
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+
+#[test]
+fn older_lines_leave_a_row_window() {
+    let mut child = Command::new(BINARY)
+        .args([
+            "--follow",
+            "--interval",
+            "0.05",
+            "--window",
+            "1rows",
+            "/dev/stdin",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"Hamburg;1.0\nHamburg;2.0\n").unwrap();
+    stdin.flush().unwrap();
+    // Read at a poll of its own
+    thread::sleep(Duration::from_millis(500));
+    stdin.write_all(b"Hamburg;5.0\n").unwrap();
+    drop(stdin);
+
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("{Hamburg=1.0/2.0/1.5}"), "{stdout}");
+    assert_eq!(stdout.lines().last(), Some("{Hamburg=5.0/5.0/5.0}"));
+}
@@ -0,0 +1,107 @@
+//! Snapshots of the text output formats over a fixed small file, so changes
+//! to the output show up in review. After an intended change, update them
+//! with `UPDATE_SNAPSHOTS=1 cargo test --test snapshots` and check the diff.
+
+use std::{env, fs, path::Path, process::Command};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_one_billion_lines");
+const SNAPSHOTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots");
+
+// Compare the output of the arguments with snapshots/`name`.snap
+fn snapshot(name: &str, args: &[&str]) {
+    let measurements = Path::new(SNAPSHOTS).join("measurements.txt");
+    let output = Command::new(BINARY)
+        .args(args)
+        .arg(&measurements)
+        // Options of the environment would change the output
+        .env_remove("ONEBRC_FORMAT")
+        .env_remove("ONEBRC_THREADS")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let actual = String::from_utf8(output.stdout).unwrap();
+
+    let path = Path::new(SNAPSHOTS).join(format!("{name}.snap"));
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("no snapshot {}, create it with UPDATE_SNAPSHOTS=1", name));
+    assert!(
+        actual == expected,
+        "output differs from snapshot {name}\n--- expected\n{expected}--- actual\n{actual}"
+    );
+}
+
+#[test]
+fn plain() {
+    snapshot("plain", &[]);
+}
+
+#[test]
+fn plain_with_precision() {
+    snapshot("plain_precision", &["--precision", "2"]);
+}
+
+#[test]
+fn plain_in_fahrenheit() {
+    snapshot("plain_fahrenheit", &["--unit", "f"]);
+}
+
+#[test]
+fn json_lines() {
+    snapshot("jsonl", &["--format", "jsonl"]);
+}
+
+#[test]
+fn csv() {
+    snapshot("csv", &["--format", "csv"]);
+}
+
+#[test]
+fn table() {
+    snapshot("table", &["--format", "table", "--color", "never"]);
+}
+
+#[test]
+fn colored_table() {
+    snapshot("table_color", &["--format", "table", "--color", "always"]);
+}
+
+#[test]
+fn sorted_by_mean() {
+    snapshot(
+        "sort_mean",
+        &[
+            "--format", "table", "--color", "never", "--sort", "mean", "--desc",
+        ],
+    );
+}
+
+#[test]
+fn histograms() {
+    let args = ["--histogram", "--bins", "8"];
+    snapshot(
+        "histogram_jsonl",
+        &[&args[..], &["--format", "jsonl"]].concat(),
+    );
+    snapshot("histogram_csv", &[&args[..], &["--format", "csv"]].concat());
+}
+
+#[test]
+fn flagged_outliers() {
+    let args = ["--flag-outliers", "--sigmas", "1.2"];
+    snapshot(
+        "outliers_table",
+        &[&args[..], &["--format", "table", "--color", "never"]].concat(),
+    );
+    snapshot(
+        "outliers_jsonl",
+        &[&args[..], &["--format", "jsonl"]].concat(),
+    );
+}
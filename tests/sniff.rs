@@ -0,0 +1,33 @@
+//! Delimiter, value format and header guessed from the first lines of a
+//! file, see `sniff`
+
+use onebrc::sniff::{sniff, Sniffed, SAMPLE};
+
+fn sniffed(delimiter: u8, flexible_values: bool, header: bool) -> Option<Sniffed> {
+    Some(Sniffed {
+        delimiter,
+        flexible_values,
+        header,
+    })
+}
+
+#[test]
+fn formats_are_sniffed() {
+    let challenge = "Hamburg;12.0\nWashington, D.C.;-3.4\n";
+    assert_eq!(sniff(challenge.as_bytes()), sniffed(b';', false, false));
+    let csv = "station,temperature\r\nHamburg,12\r\nBulawayo,8.95\r\n";
+    assert_eq!(sniff(csv.as_bytes()), sniffed(b',', true, true));
+    let tsv = "\u{feff}Hamburg\t12.0\n# a comment\n\nBulawayo\t-8.9";
+    assert_eq!(sniff(tsv.as_bytes()), sniffed(b'\t', false, false));
+    assert_eq!(sniff(b"Hamburg 12.0\n"), None);
+    assert_eq!(sniff(b"Hamburg;12.0.1\n"), None);
+    assert_eq!(sniff(b""), None);
+}
+
+#[test]
+fn a_line_cut_off_by_the_sample_is_ignored() {
+    let mut sample = "Hamburg,1.5\n".repeat(SAMPLE / 12).into_bytes();
+    sample.extend_from_slice(b"Hamb");
+    sample.truncate(SAMPLE);
+    assert_eq!(sniff(&sample), sniffed(b',', false, false));
+}
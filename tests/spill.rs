@@ -0,0 +1,56 @@
+//! Aggregating with tables spilled to disk gives the result of aggregating
+//! in memory, and leaves no files behind. Past `--max-stations` aggregating
+//! in memory fails, spilling does not.
+
+use std::{env, fs};
+
+use onebrc::{aggregate, aggregate_spilled, options::Options};
+
+mod common;
+
+use common::{aggregated, measurements};
+
+#[test]
+fn spilled_tables_aggregate_like_in_memory() {
+    let dir = env::temp_dir().join("spill-test");
+    // 64 KiB of table per thread, far fewer than the stations
+    let options = Options {
+        threads: Some(2),
+        max_memory: Some(256 << 10),
+        ..Options::default()
+    };
+    for (stations, rows) in [(10, 1_000), (20_000, 200_000)] {
+        let data = measurements(stations, rows);
+        let (spilled, _) = aggregate_spilled(&data, &dir, &options).unwrap();
+        assert_eq!(spilled.len(), stations);
+        assert_eq!(
+            aggregated(&spilled),
+            aggregated(&aggregate(&data, &options).unwrap())
+        );
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn stations_past_the_limit_spill_or_fail() {
+    let dir = env::temp_dir().join("spill-limit-test");
+    let options = Options {
+        threads: Some(2),
+        max_stations: Some(100),
+        ..Options::default()
+    };
+    let data = measurements(1_000, 10_000);
+    let Err(error) = aggregate(&data, &options) else {
+        panic!("aggregated more stations than --max-stations");
+    };
+    assert!(error
+        .to_string()
+        .starts_with("more than 100 distinct stations"));
+    let (spilled, _) = aggregate_spilled(&data, &dir, &options).unwrap();
+    assert_eq!(
+        aggregated(&spilled),
+        aggregated(&aggregate(&data, &Options::default()).unwrap())
+    );
+    fs::remove_dir(&dir).unwrap();
+}
@@ -0,0 +1,75 @@
+//! Sums close to the limit of `Sum`, which panic where overflow is checked
+//! and fit with `--features wide-sum`, counts close to that of a `u32` and
+//! squares of the largest values, which always fit
+
+use onebrc::{
+    aggregator::{Aggregator, MinMeanMax, Moments},
+    options::Options,
+    Sum,
+};
+
+// A single value of `sum`
+fn station(sum: Sum) -> MinMeanMax {
+    MinMeanMax {
+        sum,
+        count: 1,
+        min: 0,
+        max: 0,
+    }
+}
+
+#[cfg(not(feature = "wide-sum"))]
+#[test]
+#[should_panic(expected = "sum overflowed")]
+fn strict_sums_panic_on_overflow() {
+    let options = Options {
+        strict: true,
+        ..Options::default()
+    };
+    let mut stats = station(Sum::MAX - 10);
+    stats.merge(&station(20), &options);
+}
+
+#[cfg(feature = "wide-sum")]
+#[test]
+fn wide_sums_go_past_an_i64() {
+    let options = Options {
+        strict: true,
+        ..Options::default()
+    };
+    let mut stats = station(i64::MAX as Sum - 10);
+    stats.merge(&station(20), &options);
+    assert_eq!(stats.sum, i64::MAX as Sum + 10);
+    assert_eq!(stats.count, 2);
+}
+
+#[test]
+#[should_panic(expected = "count overflowed")]
+fn strict_counts_panic_on_overflow() {
+    let options = Options {
+        strict: true,
+        ..Options::default()
+    };
+    let mut stats = MinMeanMax {
+        count: u32::MAX,
+        ..station(0)
+    };
+    stats.update(10, &options);
+}
+
+#[test]
+fn squares_of_the_largest_values_fit() {
+    let options = Options {
+        strict: true,
+        flexible_values: true,
+        ..Options::default()
+    };
+    // Hundredths of almost ten million, each square close to 2^60
+    let mut moments = Moments::new();
+    for value in [999_999_999, -999_999_999].repeat(50) {
+        moments.update(value, &options);
+    }
+    let spread = moments.finalize(&options);
+    assert_eq!(spread.summary.mean, 0.0);
+    assert!((spread.deviation - 9_999_999.99).abs() < 1e-6, "{spread:?}");
+}